@@ -1,20 +1,87 @@
+use clap::ValueEnum;
 use env_logger::fmt::Color;
-use env_logger::{Builder, Env};
-use log::Level;
-use std::io::Write;
+use env_logger::{Builder, Env, WriteStyle};
+use log::{Level, LevelFilter};
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{stderr, IsTerminal, Write};
+use std::sync::Mutex;
+
+/// The format of the log output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines with color-coded levels.
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level`, `target` and `message`, for log ingestion.
+    Json,
+}
 
 /// Configures the logger with the desired log level and format.
 ///
 /// The log level can be adjusted by setting the `RUST_LOG` environment variable.
 /// Valid log levels are `trace`, `debug`, `info`, `warn`, and `error`.
-/// If the `RUST_LOG` environment variable is not set, the default log level is `info`.
+/// If the `RUST_LOG` environment variable is not set, the `level` is used, see `log_level`.
 ///
 /// The logger format includes the timestamp, log level, target module, and log message.
 /// Log levels are color-coded for better readability.
-pub fn configure_logger() {
-    Builder::from_env(Env::default().filter_or("RUST_LOG", "info"))
-        .format(|buf, record| {
+/// Colors are disabled by `no_color`, by a non-empty `NO_COLOR` environment variable,
+/// or if stderr isn't a terminal.
+///
+/// With `LogFormat::Json` each record is written as a JSON object on its own line instead.
+///
+/// With a `log_file` every record is also appended to it, always without colors. If the file
+/// can't be opened, a warning is logged and the records are only written to stderr.
+pub fn configure_logger(
+    no_color: bool,
+    log_format: LogFormat,
+    level: LevelFilter,
+    log_file: Option<&str>,
+) {
+    let write_style = if log_format == LogFormat::Text && use_color(no_color) {
+        WriteStyle::Always
+    } else {
+        WriteStyle::Never
+    };
+
+    let mut log_file_error = None;
+    let log_file = log_file.and_then(|log_file| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .map(Mutex::new)
+            .map_err(|e| log_file_error = Some(format!("{}: {}", log_file, e)))
+            .ok()
+    });
+
+    Builder::from_env(Env::default().filter_or("RUST_LOG", level.as_str()))
+        .write_style(write_style)
+        .format(move |buf, record| {
+            let timestamp = buf.timestamp().to_string();
+            let message = record.args().to_string();
             let level = record.level();
+            let target = record.target();
+
+            // a failing log file is ignored, the record is still written to stderr
+            if let Some(Ok(mut log_file)) = log_file.as_ref().map(Mutex::lock) {
+                let line = match log_format {
+                    LogFormat::Json => json_line(&timestamp, level, target, &message),
+                    LogFormat::Text => format!(
+                        "[{} {} - {}]: {}",
+                        timestamp,
+                        level_str(level),
+                        target,
+                        message
+                    ),
+                };
+                let _ = writeln!(log_file, "{}", line);
+            }
+
+            if log_format == LogFormat::Json {
+                return writeln!(buf, "{}", json_line(&timestamp, level, target, &message));
+            }
+
             let level_color = match level {
                 Level::Trace => Color::White,
                 Level::Debug => Color::Blue,
@@ -25,15 +92,6 @@ pub fn configure_logger() {
 
             let mut level_style = buf.style();
             level_style.set_color(level_color).set_bold(true);
-            let level_str = match level {
-                log::Level::Trace => "TRACE",
-                log::Level::Debug => "DEBUG",
-                log::Level::Info => "INFO ",
-                log::Level::Warn => "WARN ",
-                log::Level::Error => "ERROR",
-            };
-
-            let target = record.target();
 
             let mut target_style = buf.style();
             target_style
@@ -43,11 +101,88 @@ pub fn configure_logger() {
             writeln!(
                 buf,
                 "[{} {} - {}]: {}",
-                buf.timestamp(),
-                level_style.value(level_str),
+                timestamp,
+                level_style.value(level_str(level)),
                 target_style.value(target),
-                record.args()
+                message
             )
         })
         .init();
+
+    if let Some(log_file_error) = log_file_error {
+        warn!(
+            "Failed to open log file {}, logging to stderr only",
+            log_file_error
+        );
+    }
+}
+
+/// Returns the name of the `level`, padded to the same width.
+fn level_str(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRACE",
+        Level::Debug => "DEBUG",
+        Level::Info => "INFO ",
+        Level::Warn => "WARN ",
+        Level::Error => "ERROR",
+    }
+}
+
+/// Returns the log level for the number of `-v` (`verbose`) and `-q` (`quiet`) flags.
+///
+/// Starting from `info`, `-v` logs `debug` and `-vv` `trace`, `-q` logs `warn` and `-qq` `error` only.
+pub fn log_level(verbose: u8, quiet: u8) -> LevelFilter {
+    match i16::from(verbose) - i16::from(quiet) {
+        i16::MIN..=-2 => LevelFilter::Error,
+        -1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        2.. => LevelFilter::Trace,
+    }
+}
+
+/// Returns whether log output should be colored.
+///
+/// See <https://no-color.org> for the `NO_COLOR` convention.
+fn use_color(no_color: bool) -> bool {
+    let no_color_env = env::var("NO_COLOR").is_ok_and(|value| !value.is_empty());
+    !no_color && !no_color_env && stderr().is_terminal()
+}
+
+/// Formats a log record as a JSON object.
+fn json_line(timestamp: &str, level: Level, target: &str, message: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": level.as_str(),
+        "target": target,
+        "message": message,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(0, 0), LevelFilter::Info);
+        assert_eq!(log_level(1, 0), LevelFilter::Debug);
+        assert_eq!(log_level(3, 0), LevelFilter::Trace);
+        assert_eq!(log_level(0, 1), LevelFilter::Warn);
+        assert_eq!(log_level(0, 2), LevelFilter::Error);
+    }
+
+    #[test]
+    fn test_json_line() {
+        assert_eq!(
+            json_line(
+                "2023-06-15T10:00:00Z",
+                Level::Warn,
+                "dd_backup::run",
+                "Mirror \"x\" is not attached"
+            ),
+            r#"{"level":"WARN","message":"Mirror \"x\" is not attached","target":"dd_backup::run","timestamp":"2023-06-15T10:00:00Z"}"#
+        );
+    }
 }
@@ -1,20 +1,38 @@
 use std::process;
 
-use crate::logger::configure_logger;
+use crate::logger::{configure_logger, log_level};
 mod logger;
 mod run;
 
 #[macro_use]
 extern crate log;
 
+/// Runs the command given on the command line.
+///
+/// Exits with 0 on success, otherwise with the code of the kind of failure, so scripts can tell them apart:
+///
+/// - `1`: Any other failure, like a failing `lsblk`.
+/// - `2`: The config can't be read or is invalid.
+/// - `3`: A device isn't found, like the target device of `restore`.
+/// - `4`: A destination has not enough space for a backup.
+/// - `5`: A device backup failed, like a failing `dd` or `rsync`, a destination failed, like a failing mount,
+///   or destinations failed with `--keep-going`.
+///
+/// If several device backups fail, the code of the first failure is used.
 fn main() {
-    configure_logger();
+    let cli = run::parse_cli();
+    configure_logger(
+        cli.no_color,
+        cli.log_format,
+        log_level(cli.verbose, cli.quiet),
+        cli.log_file.as_deref(),
+    );
     debug!("Application is starting");
 
-    if let Err(e) = run::run() {
+    if let Err(e) = run::run(&cli) {
         error!("Application error: {}", e);
 
-        process::exit(1);
+        process::exit(e.exit_code);
     }
     debug!("Application ran successfully");
 }
@@ -1,21 +1,140 @@
-use std::path::Path;
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    os::fd::AsRawFd,
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
 
-use chrono::Local;
+use chrono::{DateTime, Local};
 use chrono_humanize::Humanize;
+use nix::{
+    fcntl::{posix_fadvise, OFlag, PosixFadviseAdvice},
+    sys::stat::Mode,
+};
 use relative_path::RelativePath;
 
-use crate::run::utils::current_date;
+use crate::run::{
+    config::{BackupConfig, BackupMethod, Compression, SpaceHeadroom},
+    error::BackupError,
+    manifest::{manifest_file_path, Manifest, Owner},
+    utils::{
+        expand_date_placeholders, find_executable, format_byte_size, format_duration, format_rate,
+        is_confirmed, shell_quote, DATE_FORMAT, TIME_FORMAT,
+    },
+};
 
-use super::{command_output::command_output, device::Device, filesystem::Filesystem, BackupArgs};
+use super::{
+    command_output::{
+        command_output, command_output_with_stderr_lines, print_commands_only, TIMEOUT_EXIT_CODE,
+    },
+    device::Device,
+    filesystem::{backup_time, Filesystem},
+    progress::Progress,
+    rsync,
+    signal::{track_partial_file, untrack_partial_file},
+    BackupArgs,
+};
+
+/// The extension of an image while `dd` is writing it, it is renamed to the final name on success.
+pub const PARTIAL_FILE_EXTENSION: &str = ".partial";
+
+/// The extension of the file next to a verified image, holding its SHA-256 checksum.
+pub const CHECKSUM_FILE_EXTENSION: &str = ".sha256";
+
+/// The extension a verified image is renamed to, if its checksum differs from the source device.
+const CORRUPT_FILE_EXTENSION: &str = ".corrupt";
+
+/// The SHA-256 checksums of the source devices read in this run, by device path, so a device
+/// backed up to its mirrors too is only read once to verify all of its images.
+static SOURCE_CHECKSUMS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// The wait before retrying a failed `dd`, multiplied by the number of the failed attempt.
+const RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+/// The settings of `dd` backups to a destination, built once from its `BackupConfig`.
+#[derive(Debug, Clone)]
+pub struct DdSettings {
+    /// The compressor `dd` images are piped through, if any.
+    pub compression: Option<Compression>,
+    /// Whether `dd` images are written sparse, skipping zero blocks.
+    pub sparse: bool,
+    /// Whether `dd` images are verified against the source device after writing.
+    pub verify: bool,
+    /// Whether `dd` backups of devices unchanged since their most recent backup are skipped.
+    pub skip_if_unchanged: bool,
+    /// The block size `dd` reads and writes with, in bytes.
+    pub block_size: u64,
+    /// The bandwidth `dd` images are written with at most, in bytes per second, `None` for full speed.
+    pub max_bandwidth: Option<u64>,
+    /// The space which has to remain free after a backup.
+    pub space_headroom: SpaceHeadroom,
+    /// The further operands appended to the `dd` command.
+    pub dd_options: Vec<String>,
+    /// The template of backup file names, see `BackupConfig::file_name_template`.
+    pub name_template: String,
+    /// How often a failing `dd` is retried.
+    pub retries: u32,
+}
+
+impl DdSettings {
+    /// Creates the `DdSettings` of `backup_config`, with the defaults for settings it doesn't configure.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(DdSettings)`: The settings of the destination.
+    /// - `Err(BackupError::InvalidConfig)`: If the block size, bandwidth or space headroom is invalid.
+    pub fn new(backup_config: &BackupConfig) -> Result<DdSettings, BackupError> {
+        Ok(DdSettings {
+            compression: backup_config.compression,
+            sparse: backup_config.sparse.unwrap_or(false),
+            verify: backup_config.verify.unwrap_or(false),
+            skip_if_unchanged: backup_config.skip_if_unchanged.unwrap_or(false),
+            block_size: backup_config.block_size_in_bytes()?,
+            max_bandwidth: backup_config.max_bandwidth_in_bytes()?,
+            space_headroom: backup_config.space_headroom()?,
+            dd_options: backup_config.dd_options.clone().unwrap_or_default(),
+            name_template: backup_config.file_name_template(),
+            retries: backup_config.retries.unwrap_or(0),
+        })
+    }
+}
+
+/// The successful attempt of `dd` writing a partial image, see `Backup::run_with_retries`.
+#[derive(Debug)]
+struct DdRun {
+    /// The output `dd` wrote to stdout.
+    stdout: String,
+    /// The checksum of the image, if it was hashed while `dd` wrote it, see `Backup::hashes_while_writing`.
+    checksum: Option<String>,
+    /// The bytes `dd` copied in the attempt, if it reported any progress.
+    copied_bytes: Option<u64>,
+    /// The duration of the attempt.
+    duration: Duration,
+}
 
 #[derive(Debug)]
 pub struct Backup<'a> {
     /// The destination filesystem for the backup.
     pub dst_filesystem: &'a Filesystem,
+    /// The settings of `dd` backups to the destination.
+    pub dd_settings: &'a DdSettings,
     /// The backup device.
     pub backup_device: &'a Device,
     /// The command line arguments for the backup operation.
     pub backup_args: &'a BackupArgs,
+    /// Whether the oldest copy was deleted during the run, to keep the configured number of copies.
+    pub deleted_oldest: Cell<bool>,
+    /// The number of unreadable blocks `dd` skipped with `conv=noerror`.
+    pub skipped_blocks: Cell<u64>,
+    /// The bytes this backup reserved on the destination filesystem with its space check.
+    reserved_space: Cell<u64>,
+    /// The time the backup was created, which dates its file name.
+    created_at: DateTime<Local>,
 }
 
 impl<'a> Backup<'a> {
@@ -24,87 +143,762 @@ impl<'a> Backup<'a> {
     /// # Arguments
     ///
     /// * `dst_filesystem` - The destination filesystem for the backup.
+    /// * `dd_settings` - The settings of `dd` backups to the destination.
     /// * `backup_device` - The device to be backed up.
     pub fn new(
         dst_filesystem: &'a Filesystem,
+        dd_settings: &'a DdSettings,
         backup_device: &'a Device,
         backup_args: &'a BackupArgs,
     ) -> Backup<'a> {
         let backup = Backup {
             dst_filesystem,
+            dd_settings,
             backup_device,
             backup_args,
+            deleted_oldest: Cell::new(false),
+            skipped_blocks: Cell::new(0),
+            reserved_space: Cell::new(0),
+            created_at: Local::now(),
         };
         debug!("{:?}", backup);
         backup
     }
 
-    /// Runs the backup process using the `dd` command.
+    /// Runs the backup process using the configured method, `dd` or `rsync`.
+    ///
+    /// With `--print-commands-only` the state isn't validated, since this would touch the
+    /// destination filesystem, which is not mounted.
+    ///
+    /// The space reserved by the space check is released afterwards, successful or not.
     ///
     /// # Returns
     ///
     /// * `Ok(())` if the backup process is successful.
     /// * `Err` with an error message if the backup process encounters an error.
-    pub fn run(&self) -> Result<(), String> {
-        self.validate_state()?;
-
-        let input_file_arg = format!("if={}", self.backup_device.device_path.clone());
-        let output_file_arg = format!("of={}", self.backup_file_path());
-        let command_parts = vec!["dd", &input_file_arg, &output_file_arg, "status=progress"];
-        let description = format!("run dd command: {:?}", &command_parts.join(" "));
-        match self.backup_args.dry_run {
-            true => {
+    pub fn run(&self) -> Result<(), BackupError> {
+        let result = self.validate_and_back_up();
+        self.release_reserved_space();
+        result
+    }
+
+    /// Validates the state and runs the backup, see `run`.
+    fn validate_and_back_up(&self) -> Result<(), BackupError> {
+        // without the tag in their names, tagged backups would count as copies of the routine ones
+        if self.backup_args.tag.is_some() && !self.dd_settings.name_template.contains("{tag}") {
+            return Err(BackupError::InvalidConfig(format!(
+                "Name template '{}' needs the placeholder {{tag}} for tagged backups",
+                self.dd_settings.name_template
+            )));
+        }
+        if !print_commands_only() {
+            // before validating the state, which deletes old backups to make room for the new one
+            self.skip_if_unchanged()?;
+            self.validate_state()?;
+        }
+        if self.backup_args.dry_run {
+            self.log_resolved_devices();
+        }
+
+        match self.backup_device.method {
+            BackupMethod::Dd => self.run_dd(),
+            BackupMethod::Rsync => rsync::run(self),
+        }
+    }
+
+    /// Logs which device the serial resolved to and where the destination is mounted,
+    /// to confirm the right disk is backed up before a real run.
+    fn log_resolved_devices(&self) {
+        let blockdevice = &self.backup_device.blockdevice;
+        info!(
+            "[DRY RUN] device {} (model: {}, serial: {}, size: {}) would be backed up to filesystem {} (UUID: {}) mounted at {}",
+            self.backup_device.device_path,
+            blockdevice.model.as_deref().unwrap_or("-"),
+            blockdevice.serial.as_deref().unwrap_or("-"),
+            format_byte_size(self.backup_device.total_size()),
+            self.dst_filesystem.device_path,
+            self.dst_filesystem.blockdevice.uuid.as_deref().unwrap_or("-"),
+            self.dst_filesystem
+                .blockdevice
+                .mountpoint
+                .as_ref()
+                .unwrap_or(&self.dst_filesystem.mountpath),
+        );
+    }
+
+    /// Runs the backup process using the `dd` command.
+    ///
+    /// The image is written to a `.partial` file first, which is only renamed to the final
+    /// name if `dd` succeeds. Otherwise, or when interrupted, the partial file is removed.
+    ///
+    /// `dd` reads and writes in blocks of the configured `block_size`.
+    ///
+    /// With `--resume` a present partial file is continued instead and kept on failure.
+    /// Its whole blocks are skipped on the source and seeked over on the image, `dd` truncates
+    /// an incomplete last block. Before renaming, the image size is compared to the source size.
+    ///
+    /// With `sparse` the image is written with `conv=sparse`, so zero blocks become holes.
+    ///
+    /// With `compression` the output of `dd` is piped through the compressor into the partial file,
+    /// run by `bash` with `pipefail`, so a failing `dd` fails the whole pipeline.
+    ///
+    /// With `verify` the partial image is compared to the source device before renaming, see `verify_image`.
+    ///
+    /// With `--dd-timeout` the `dd` is run by the `timeout` command, which kills it when the limit is exceeded.
+    ///
+    /// With `max_bandwidth` or `--rate-limit` the output of `dd` is piped through `pv -L`, which throttles it,
+    /// into the compressor or a second `dd` writing the partial file, which seeks and skips zero blocks instead.
+    ///
+    /// The configured `dd_options` are appended to the `dd` command. With `conv=noerror` a `dd` failing
+    /// only because of read errors is taken as success, with a warning counting the skipped blocks.
+    ///
+    /// The command is built by `build_pipeline` and run by `run_with_retries`, which retries a failing `dd`,
+    /// then `finalize_image` turns the partial image into the backup file.
+    fn run_dd(&self) -> Result<(), BackupError> {
+        if let Some(compression) = self.dd_settings.compression {
+            find_executable(compression.binary()).ok_or(format!(
+                "Compressor {} not found in PATH, install it or remove the `compression` setting",
+                compression.binary()
+            ))?;
+        }
+        let max_bandwidth = self.max_bandwidth();
+        if max_bandwidth.is_some() {
+            find_executable("pv").ok_or(
+                "pv not found in PATH, install it or remove the `max_bandwidth` setting and `--rate-limit`",
+            )?;
+        }
+
+        let resume_blocks = self.resume_blocks();
+        if let Some(resume_blocks) = resume_blocks {
+            info!(
+                "Resuming partial backup file {} after {}",
+                self.partial_file_path(),
+                format_byte_size(resume_blocks * self.dd_settings.block_size)
+            );
+        }
+        let command_parts = self.build_pipeline(resume_blocks);
+        let command_parts: Vec<&str> = command_parts.iter().map(|part| part.as_str()).collect();
+        if self.backup_args.dry_run {
+            let throttle = max_bandwidth
+                .map(|max_bandwidth| format!(", limited to {}/s", format_byte_size(max_bandwidth)))
+                .unwrap_or_default();
+            info!(
+                "[DRY RUN] backup would run with block size {}{} and command: {}",
+                format_byte_size(self.dd_settings.block_size),
+                throttle,
+                &command_parts.join(" "),
+            );
+            return Ok(());
+        }
+
+        let started_at = Local::now();
+        let dd_run = self.run_with_retries(&command_parts, resume_blocks)?;
+        self.finalize_image(&command_parts.join(" "), resume_blocks, started_at, dd_run)
+    }
+
+    /// Builds the command writing the partial image, the `dd` command or the `bash` pipeline
+    /// piping it through `pv` and the compressor or a second `dd`, see `run_dd`.
+    /// If the image is hashed while writing, it is piped through `tee` into `sha256sum` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `resume_blocks` - The whole blocks of the partial image to skip on the source and seek over on the image.
+    fn build_pipeline(&self, resume_blocks: Option<u64>) -> Vec<String> {
+        let max_bandwidth = self.max_bandwidth();
+        let hashes_while_writing = self.hashes_while_writing(resume_blocks);
+        let is_piped = self.dd_settings.compression.is_some()
+            || max_bandwidth.is_some()
+            || hashes_while_writing;
+
+        let block_size = self.dd_settings.block_size;
+        let mut command_parts = Vec::new();
+        if let Some(dd_timeout) = self.backup_args.dd_timeout {
+            // run as part of the sudo command, so the `dd` running as root can be killed
+            command_parts.extend(["timeout".to_string(), dd_timeout.to_string()]);
+        }
+        command_parts.extend([
+            "dd".to_string(),
+            format!("if={}", self.backup_device.device_path.clone()),
+        ]);
+        // the operands writing the image, which go to a second `dd` if the output is piped
+        let mut output_args = vec![format!("of={}", self.partial_file_path())];
+        command_parts.push(format!("bs={}", block_size));
+        if let Some(resume_blocks) = resume_blocks {
+            let [skip_arg, seek_arg] = resume_args(resume_blocks);
+            command_parts.push(skip_arg);
+            output_args.push(seek_arg);
+        }
+        if self.dd_settings.sparse {
+            output_args.push("conv=sparse".to_string());
+        }
+        // without progress updates nothing is drawn on the terminal, errors are still written
+        command_parts.push(match self.backup_args.summary_only {
+            true => "status=none".to_string(),
+            false => "status=progress".to_string(),
+        });
+        command_parts.extend(self.dd_settings.dd_options.iter().cloned());
+        if !is_piped {
+            command_parts.extend(output_args);
+            return command_parts;
+        }
+
+        let mut pipe_commands = vec![command_parts];
+        if let Some(max_bandwidth) = max_bandwidth {
+            pipe_commands.push(throttle_command(max_bandwidth));
+        }
+        let output_file_path = match self.dd_settings.compression {
+            Some(compression) => {
+                pipe_commands.push(vec![compression.binary().to_string(), "-c".to_string()]);
+                Some(self.partial_file_path())
+            }
+            // the checksum is written to stdout, read by `run_with_retries`
+            None if hashes_while_writing => {
+                pipe_commands.push(vec![
+                    "tee".to_string(),
+                    "--".to_string(),
+                    self.partial_file_path(),
+                ]);
+                pipe_commands.push(vec!["sha256sum".to_string()]);
+                None
+            }
+            None => {
+                // whole blocks, so seeking and skipping zero blocks work like writing directly
+                let mut output_command = vec![
+                    "dd".to_string(),
+                    format!("bs={}", block_size),
+                    "iflag=fullblock".to_string(),
+                    "status=none".to_string(),
+                ];
+                output_command.extend(output_args);
+                pipe_commands.push(output_command);
+                None
+            }
+        };
+        vec![
+            "bash".to_string(),
+            "-o".to_string(),
+            "pipefail".to_string(),
+            "-c".to_string(),
+            shell_pipeline(&pipe_commands, output_file_path.as_deref()),
+        ]
+    }
+
+    /// Runs the `command_parts` writing the partial image, retrying up to `retries` times if it fails,
+    /// with a backoff growing by `RETRY_BACKOFF` per attempt. The partial file is removed after a failed
+    /// attempt, unless it is kept to resume it.
+    ///
+    /// A `dd` killed by `--dd-timeout` fails with `BackupError::CommandTimedOut`. With `conv=noerror`
+    /// a `dd` failing only because of read errors is taken as success, with a warning counting the
+    /// skipped blocks in `skipped_blocks`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(DdRun)`: The output of the successful attempt.
+    /// - `Err(BackupError)`: The error of the last attempt, if all of them failed.
+    fn run_with_retries(
+        &self,
+        command_parts: &[&str],
+        resume_blocks: Option<u64>,
+    ) -> Result<DdRun, BackupError> {
+        let description = format!("run dd command: {:?}", command_parts.join(" "));
+        let attempts = self.retries() + 1;
+        let mut attempt = 1;
+        loop {
+            let time_before_attempt = Instant::now();
+            if !self.backup_args.resume {
+                track_partial_file(self.partial_file_path());
+            }
+            let mut progress = Progress::new(
+                &self.backup_device.device_path,
+                self.backup_device.total_size(),
+                resume_blocks.unwrap_or(0) * self.dd_settings.block_size,
+            );
+            let output = command_output_with_stderr_lines(
+                command_parts.to_vec(),
+                description.as_str(),
+                Some(true),
+                |line| progress.update(line),
+            );
+            let copied_bytes = progress.finish();
+            let e = match output {
+                Ok(output) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                    let checksum =
+                        match self.hashes_while_writing(resume_blocks) && !print_commands_only() {
+                            true => Some(parse_sha256sum_output(&stdout)?),
+                            false => None,
+                        };
+                    return Ok(DdRun {
+                        stdout,
+                        checksum,
+                        copied_bytes,
+                        duration: time_before_attempt.elapsed(),
+                    });
+                }
+                Err(BackupError::CommandFailed { stderr, .. })
+                    if self.continues_on_read_errors() && read_error_count(&stderr) > 0 =>
+                {
+                    let skipped_blocks = read_error_count(&stderr);
+                    warn!(
+                        "dd skipped {} unreadable blocks of {} with conv=noerror, the backup is incomplete: {}",
+                        skipped_blocks, self.backup_device.device_path, stderr
+                    );
+                    self.skipped_blocks.set(skipped_blocks);
+                    // the output of the failed pipeline isn't captured, the image is verified by reading it again
+                    return Ok(DdRun {
+                        stdout: String::new(),
+                        checksum: None,
+                        copied_bytes,
+                        duration: time_before_attempt.elapsed(),
+                    });
+                }
+                Err(e) => match (e, self.backup_args.dd_timeout) {
+                    (
+                        BackupError::CommandFailed {
+                            command, status, ..
+                        },
+                        Some(dd_timeout),
+                    ) if status.code() == Some(TIMEOUT_EXIT_CODE) => BackupError::CommandTimedOut {
+                        command,
+                        timeout: Duration::from_secs(dd_timeout),
+                    },
+                    (e, _) => e,
+                },
+            };
+            self.remove_partial_file();
+            if attempt >= attempts {
+                return Err(e);
+            }
+            let backoff = RETRY_BACKOFF * attempt;
+            warn!(
+                "Attempt {} of {} backing up {} failed, retrying in {:?}: {}",
+                attempt, attempts, self.backup_device.device_path, backoff, e
+            );
+            thread::sleep(backoff);
+            attempt += 1;
+        }
+    }
+
+    /// Turns the partial image written by the successful `dd_run` into the backup file.
+    ///
+    /// A resumed image is checked to be as large as the source device and, with `verify`, the image is
+    /// verified, see `verify_image`, unless it was hashed while writing. Then it is fsynced, renamed to its final name and written a checksum
+    /// file and manifest for, both owned by the current user like the image.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command which wrote the image, recorded in the manifest.
+    /// * `resume_blocks` - The whole blocks of the partial image which were resumed, if any.
+    /// * `started_at` - The time the first attempt of `dd` started.
+    /// * `dd_run` - The output of the successful `dd` attempt.
+    fn finalize_image(
+        &self,
+        command: &str,
+        resume_blocks: Option<u64>,
+        started_at: DateTime<Local>,
+        dd_run: DdRun,
+    ) -> Result<(), BackupError> {
+        if resume_blocks.is_some() {
+            self.validate_resumed_size()?;
+        }
+        let checksum = match (
+            self.dd_settings.verify && !print_commands_only(),
+            dd_run.checksum,
+        ) {
+            (false, _) => None,
+            (true, Some(checksum)) => {
                 info!(
-                    "[DRY RUN] backup would run with command: {}",
-                    &command_parts.join(" "),
+                    "Hashed backup file {} while writing it, SHA-256 {}",
+                    self.partial_file_path(),
+                    checksum
                 );
-                Ok(())
+                Some(checksum)
             }
-            false => {
-                let time_before_dd = Local::now();
-                let output =
-                    command_output(command_parts.clone(), description.as_str(), Some(true))?;
-
-                if output.status.success() {
-                    let time_after_dd = Local::now();
-                    let diff = time_after_dd - time_before_dd;
-                    info!(
-                        "Success running backup with dd command {} for {}: {}",
-                        &command_parts.join(" "),
-                        diff.humanize(),
-                        String::from_utf8_lossy(&output.stdout).to_string()
-                    );
+            (true, None) => Some(self.verify_image()?),
+        };
+        self.sync_partial_file()?;
+        self.promote_partial_file()?;
+        if let Some(checksum) = &checksum {
+            self.write_checksum_file(checksum)?;
+        }
+        let manifest = Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            serial: self.backup_device.blockdevice.serial.clone(),
+            model: self.backup_device.blockdevice.model.clone(),
+            total_size: self.backup_device.total_size(),
+            block_size: self.dd_settings.block_size,
+            command: command.to_string(),
+            started_at: started_at.to_rfc3339(),
+            finished_at: Local::now().to_rfc3339(),
+            hostname: nix::unistd::gethostname()
+                .ok()
+                .and_then(|hostname| hostname.into_string().ok()),
+            tag: self.backup_args.tag.clone(),
+            owner: Some(current_owner()),
+            source_owner: Owner::of_path(&self.backup_device.device_path),
+            checksum,
+        };
+        self.write_manifest(&manifest)?;
+        // without statistics of dd, the written image tells the size
+        let copied_bytes = dd_run
+            .copied_bytes
+            .or_else(|| {
+                fs::metadata(self.backup_file_path())
+                    .ok()
+                    .map(|metadata| metadata.len())
+            })
+            .unwrap_or_default();
+        info!(
+            "Backed up {} → {}: {} in {} at {}",
+            self.backup_device
+                .blockdevice
+                .serial
+                .as_deref()
+                .unwrap_or(&self.backup_device.device_path),
+            self.backup_file_path(),
+            format_byte_size(copied_bytes),
+            format_duration(dd_run.duration),
+            format_rate(copied_bytes, dd_run.duration)
+        );
+        debug!(
+            "Success running backup with dd command {} (block size {}): {}",
+            command,
+            format_byte_size(self.dd_settings.block_size),
+            dd_run.stdout
+        );
 
-                    self.chown()
-                } else {
-                    Err(format!(
-                        "Error running dd command {}: {}",
-                        &command_parts.join(" "),
-                        String::from_utf8_lossy(&output.stderr)
-                    ))
-                }
+        self.chown(&self.backup_file_path())?;
+        if !print_commands_only() {
+            self.chown(&manifest_file_path(&self.backup_file_path()))?;
+        }
+        self.drop_cache()?;
+        self.sync_backup_dir()
+    }
+
+    /// Renames the partial image written by `dd` to the final backup file.
+    ///
+    /// The image is owned by root until it is chowned, so `mv` is run with sudo.
+    fn promote_partial_file(&self) -> Result<(), BackupError> {
+        let partial_file_path = self.partial_file_path();
+        let backup_file_path = self.backup_file_path();
+        command_output(
+            vec!["mv", &partial_file_path, &backup_file_path],
+            "rename partial backup file",
+            Some(true),
+        )?;
+        untrack_partial_file(&partial_file_path);
+        // the checksum of an overwritten image is stale, a verified one is written afterwards
+        let checksum_file_path = self.checksum_file_path();
+        if Path::new(&checksum_file_path).exists() {
+            fs::remove_file(&checksum_file_path).map_err(BackupError::io(format!(
+                "Failed to delete stale checksum file {}",
+                checksum_file_path
+            )))?;
+        }
+        Ok(())
+    }
+
+    /// Verifies the partial image by comparing its SHA-256 to the one of the source device,
+    /// read in a second pass. A compressed image is decompressed for hashing.
+    ///
+    /// If the checksums differ, the image is renamed to `<image>.corrupt`, so it isn't counted as a copy.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(String)`: The checksum, if the image matches the source device.
+    /// - `Err(BackupError)`: If the checksums differ or couldn't be computed.
+    fn verify_image(&self) -> Result<String, BackupError> {
+        let partial_file_path = self.partial_file_path();
+        info!(
+            "Verifying backup file {} against source device {}",
+            partial_file_path, self.backup_device.device_path
+        );
+
+        let image_checksum = match self.dd_settings.compression {
+            Some(compression) => sha256(
+                vec![
+                    "bash",
+                    "-o",
+                    "pipefail",
+                    "-c",
+                    &format!(
+                        "{} -dc {} | sha256sum",
+                        compression.binary(),
+                        shell_quote(&partial_file_path)
+                    ),
+                ],
+                "compute checksum of backup file",
+            )?,
+            None => sha256(
+                vec!["sha256sum", &partial_file_path],
+                "compute checksum of backup file",
+            )?,
+        };
+        let source_checksum = self.source_checksum()?;
+
+        if image_checksum == source_checksum {
+            info!(
+                "Verified backup file {} with SHA-256 {}",
+                partial_file_path, image_checksum
+            );
+            return Ok(image_checksum);
+        }
+
+        untrack_partial_file(&partial_file_path);
+        let corrupt_file_path = format!("{}{}", self.backup_file_path(), CORRUPT_FILE_EXTENSION);
+        let renamed = command_output(
+            vec!["mv", &partial_file_path, &corrupt_file_path],
+            "rename corrupt backup file",
+            Some(true),
+        );
+        Err(BackupError::Other(format!(
+            "Checksum {} of backup file {} differs from checksum {} of source device {}, {}",
+            image_checksum,
+            partial_file_path,
+            source_checksum,
+            self.backup_device.device_path,
+            match renamed {
+                Ok(_) => format!("renamed it to {}", corrupt_file_path),
+                Err(e) => format!("failed to rename it: {}", e),
             }
+        )))
+    }
+
+    /// Computes the SHA-256 of the whole source device, in a read pass of its own.
+    ///
+    /// The checksum is computed once per run and device, later backups of the device,
+    /// like the ones to its mirrors, reuse it.
+    fn source_checksum(&self) -> Result<String, BackupError> {
+        let device_path = &self.backup_device.device_path;
+        if let Some(checksum) = SOURCE_CHECKSUMS.lock().unwrap().get(device_path) {
+            debug!(
+                "Reusing checksum {} of source device {}",
+                checksum, device_path
+            );
+            return Ok(checksum.clone());
+        }
+        let checksum = sha256(
+            vec!["sha256sum", device_path],
+            "compute checksum of source device",
+        )?;
+        SOURCE_CHECKSUMS
+            .lock()
+            .unwrap()
+            .insert(device_path.clone(), checksum.clone());
+        Ok(checksum)
+    }
+
+    /// Fails with `BackupError::SourceUnchanged` if `skip_if_unchanged` is set and the source device has
+    /// the checksum of its most recent `dd` backup, read from its manifest or `<image>.sha256` file.
+    ///
+    /// A most recent backup without checksum, or whose manifest records another source size, counts as changed.
+    /// On dry runs the source isn't read, the backup is simulated as if it changed.
+    fn skip_if_unchanged(&self) -> Result<(), BackupError> {
+        if !self.dd_settings.skip_if_unchanged || self.backup_device.method != BackupMethod::Dd {
+            return Ok(());
+        }
+        let backup_dir_path = self.backup_dir_path();
+        if !Path::new(&backup_dir_path).is_dir() {
+            return Ok(());
+        }
+        let Some(last_backup) = self
+            .dst_filesystem
+            .present_backups_oldest_first(
+                &self.file_name_pattern(),
+                &backup_dir_path,
+                self.backup_device.method,
+            )?
+            .pop()
+        else {
+            return Ok(());
+        };
+        let last_backup_path = format!("{}/{}", backup_dir_path, last_backup);
+
+        let manifest = Manifest::read(&last_backup_path);
+        if let Some(manifest) = &manifest {
+            if manifest.total_size != self.backup_device.total_size() {
+                info!(
+                    "Source {} changed its size since backup {}, backing it up",
+                    self.backup_device.device_path, last_backup_path
+                );
+                return Ok(());
+            }
+        }
+        let last_checksum = manifest.and_then(|manifest| manifest.checksum).or_else(|| {
+            fs::read_to_string(format!("{}{}", last_backup_path, CHECKSUM_FILE_EXTENSION))
+                .ok()
+                .and_then(|content| parse_sha256sum_output(&content).ok())
+        });
+        let Some(last_checksum) = last_checksum else {
+            warn!(
+                "No checksum recorded for backup {}, enable `verify` to skip unchanged sources, backing up {}",
+                last_backup_path, self.backup_device.device_path
+            );
+            return Ok(());
+        };
+        if self.backup_args.dry_run {
+            info!(
+                "[DRY RUN] Would compare the checksum of source {} to the one of backup {}",
+                self.backup_device.device_path, last_backup_path
+            );
+            return Ok(());
+        }
+
+        info!(
+            "Comparing the checksum of source {} to the one of backup {}",
+            self.backup_device.device_path, last_backup_path
+        );
+        match self.source_checksum()? == last_checksum {
+            true => Err(BackupError::SourceUnchanged {
+                device: self.backup_device.device_path.clone(),
+                backup: last_backup_path,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Writes the `checksum` of the image into `<image>.sha256`, in the format of `sha256sum`.
+    fn write_checksum_file(&self, checksum: &str) -> Result<(), BackupError> {
+        let checksum_file_path = self.checksum_file_path();
+        fs::write(
+            &checksum_file_path,
+            format!("{}  {}\n", checksum, self.file_name()),
+        )
+        .map_err(BackupError::io(format!(
+            "Failed to write checksum file {}",
+            checksum_file_path
+        )))
+    }
+
+    /// Writes the `manifest` of the image into `<image>.json`, describing its source device and `dd` run.
+    ///
+    /// With `--print-commands-only` nothing is written, like no image is.
+    fn write_manifest(&self, manifest: &Manifest) -> Result<(), BackupError> {
+        if print_commands_only() {
+            return Ok(());
+        }
+        let manifest_file_path = manifest.write(&self.backup_file_path())?;
+        debug!("Wrote manifest file {}", manifest_file_path);
+        Ok(())
+    }
+
+    /// Returns the checksum of the image read from its `<image>.sha256` file, if it was verified.
+    pub fn read_checksum(&self) -> Option<String> {
+        fs::read_to_string(self.checksum_file_path())
+            .ok()
+            .and_then(|content| parse_sha256sum_output(&content).ok())
+    }
+
+    /// Returns whether `dd` continues past read errors, with `noerror` in a configured `conv` option.
+    fn continues_on_read_errors(&self) -> bool {
+        self.dd_settings.dd_options.iter().any(|dd_option| {
+            dd_option
+                .strip_prefix("conv=")
+                .is_some_and(|conv| conv.split(',').any(|flag| flag == "noerror"))
+        })
+    }
+
+    /// Returns whether the image is hashed while `dd` writes it with `--parallel-checksum`, instead of
+    /// being verified by reading it and the source device again.
+    ///
+    /// Only a raw image written from the start can be hashed in one pass. A compressed image is written
+    /// by the compressor and a sparse one by a second `dd` seeking over zero blocks, at the end of the
+    /// pipeline instead of `tee`, and a resumed one misses the blocks written before.
+    fn hashes_while_writing(&self, resume_blocks: Option<u64>) -> bool {
+        self.backup_args.parallel_checksum
+            && self.dd_settings.verify
+            && self.dd_settings.compression.is_none()
+            && !self.dd_settings.sparse
+            && resume_blocks.is_none()
+    }
+
+    /// Returns how often a failing `dd` is retried, `--retries` takes precedence over the config value.
+    fn retries(&self) -> u32 {
+        self.backup_args.retries.unwrap_or(self.dd_settings.retries)
+    }
+
+    /// Returns the bandwidth `dd` images are written with at most, `--rate-limit` takes precedence over the config value.
+    fn max_bandwidth(&self) -> Option<u64> {
+        self.backup_args
+            .rate_limit
+            .or(self.dd_settings.max_bandwidth)
+    }
+
+    /// Returns the path of the file holding the checksum of a verified image.
+    pub fn checksum_file_path(&self) -> String {
+        format!("{}{}", self.backup_file_path(), CHECKSUM_FILE_EXTENSION)
+    }
+
+    /// Returns the number of whole blocks of a partial image to resume with `--resume`, if any.
+    ///
+    /// A compressed image can't be continued at a block offset, so it is always written anew.
+    fn resume_blocks(&self) -> Option<u64> {
+        if !self.backup_args.resume || self.dd_settings.compression.is_some() {
+            return None;
         }
+        let partial_size = fs::metadata(self.partial_file_path()).ok()?.len();
+        Some(partial_size / self.dd_settings.block_size).filter(|&blocks| blocks > 0)
     }
 
-    /// Sets the owner of the backup file to the current user ID and group ID.
+    /// Checks that the resumed image is as large as the source device, assuming it didn't change.
+    fn validate_resumed_size(&self) -> Result<(), BackupError> {
+        let partial_file_path = self.partial_file_path();
+        let image_size = fs::metadata(&partial_file_path)
+            .map_err(BackupError::io(format!(
+                "Failed to read size of {}",
+                partial_file_path
+            )))?
+            .len();
+        let source_size = self.backup_device.total_size();
+        if image_size == source_size {
+            Ok(())
+        } else {
+            Err(BackupError::Other(format!(
+                "Resumed backup file {} has {} bytes, but the source device {} has {} bytes, keeping it as partial",
+                partial_file_path, image_size, self.backup_device.device_path, source_size
+            )))
+        }
+    }
+
+    /// Removes the partial image of a failed `dd` run, logging instead of returning errors.
+    /// With `--resume` the partial image is kept, to be continued on the next run.
+    fn remove_partial_file(&self) {
+        let partial_file_path = self.partial_file_path();
+        untrack_partial_file(&partial_file_path);
+        if !Path::new(&partial_file_path).exists() {
+            return;
+        }
+        if self.backup_args.resume {
+            info!(
+                "Keeping partial backup file {} to resume it",
+                partial_file_path
+            );
+            return;
+        }
+
+        match command_output(
+            vec!["rm", "-f", &partial_file_path],
+            "remove partial backup file",
+            Some(true),
+        ) {
+            Ok(_) => info!("Removed partial backup file {}", partial_file_path),
+            Err(e) => error!("Failed to remove partial backup file: {}", e),
+        }
+    }
+
+    /// Sets the owner of a backup file to the current user ID and group ID.
     ///
-    /// This function changes the owner of the backup file specified by `output_file_path`
+    /// This function changes the owner of the file at `output_file_path`, the image or its manifest,
     /// to the current user and group. It uses the `chown` command to perform the operation.
     ///
     /// # Returns
     ///
     /// - `Ok(())`: If the operation is successful.
-    /// - `Err(String)`: If an error occurs during the operation.
-    fn chown(&self) -> Result<(), String> {
-        let output_file_path = self.backup_file_path();
-
-        // Retrieve the current user and group IDs
-        let user_id = unsafe { libc::getuid() };
-        let group_id = unsafe { libc::getgid() };
-
-        let user_group_id_arg = format!("{}:{}", user_id, group_id);
-        let command_parts = vec!["chown", &user_group_id_arg, &output_file_path];
+    /// - `Err(BackupError)`: If an error occurs during the operation.
+    fn chown(&self, output_file_path: &str) -> Result<(), BackupError> {
+        let owner = current_owner();
+        let user_group_id_arg = format!("{}:{}", owner.uid, owner.gid);
+        let command_parts = vec!["chown", &user_group_id_arg, output_file_path];
         command_output(
             command_parts,
             "change owner of backup file to $UID",
@@ -113,18 +907,110 @@ impl<'a> Backup<'a> {
         Ok(())
     }
 
-    /// Returns the output dir path for the backup.
-    fn backup_dir_path(&self) -> String {
-        let relative_path =
-            RelativePath::new(&self.dst_filesystem.blockdevice.mountpoint.clone().unwrap())
-                .join_normalized(self.backup_device.destination_path.clone())
-                .to_string();
+    /// Evicts the backup file from the page cache, if configured with `drop_cache`.
+    ///
+    /// Only clean pages can be dropped, so the file is flushed with `fdatasync` first.
+    /// Unlike `oflag=direct` the write still goes through the cache and doesn't need aligned blocks.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: If the cache was dropped or dropping is disabled.
+    /// - `Err(BackupError)`: If the file couldn't be opened, synced or advised.
+    fn drop_cache(&self) -> Result<(), BackupError> {
+        if !self.backup_device.drop_cache || print_commands_only() {
+            return Ok(());
+        }
 
-        format!("/{}", relative_path)
+        let output_file_path = self.backup_file_path();
+        let file = File::open(&output_file_path).map_err(BackupError::io(format!(
+            "Failed to open backup file {}",
+            output_file_path
+        )))?;
+        file.sync_data().map_err(BackupError::io(format!(
+            "Failed to sync backup file {}",
+            output_file_path
+        )))?;
+        posix_fadvise(
+            file.as_raw_fd(),
+            0,
+            0,
+            PosixFadviseAdvice::POSIX_FADV_DONTNEED,
+        )
+        .map_err(|e| {
+            format!(
+                "Failed to drop page cache of backup file {}: {}",
+                output_file_path, e
+            )
+        })?;
+        debug!("Dropped page cache of backup file {}", output_file_path);
+        Ok(())
+    }
+
+    /// Flushes the content of the partial backup file to disk, before it is renamed to the backup file.
+    ///
+    /// Neither `dd` nor a compressor writing through the shell syncs the image, without it the rename
+    /// may reach a slow USB disk before the data. Does nothing if the destination isn't `durable`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: If the file was synced or syncing is disabled.
+    /// - `Err(BackupError)`: If the file couldn't be opened or synced.
+    fn sync_partial_file(&self) -> Result<(), BackupError> {
+        if !self.dst_filesystem.durable || print_commands_only() {
+            return Ok(());
+        }
+
+        let partial_file_path = self.partial_file_path();
+        let file = File::open(&partial_file_path).map_err(BackupError::io(format!(
+            "Failed to open partial backup file {}",
+            partial_file_path
+        )))?;
+        file.sync_all().map_err(BackupError::io(format!(
+            "Failed to fsync partial backup file {}",
+            partial_file_path
+        )))?;
+        debug!("Synced partial backup file {}", partial_file_path);
+        Ok(())
+    }
+
+    /// Flushes the directory entry of the new backup file to disk.
+    ///
+    /// The content of an image is synced by `sync_partial_file`, the directory inode holding the
+    /// entry of the new image needs an own `fsync`. Does nothing if the destination isn't `durable`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: If the directory was synced or syncing is disabled.
+    /// - `Err(BackupError)`: If the directory couldn't be opened or synced.
+    pub fn sync_backup_dir(&self) -> Result<(), BackupError> {
+        if !self.dst_filesystem.durable || print_commands_only() {
+            return Ok(());
+        }
+
+        let backup_dir_path = self.backup_dir_path();
+        let fd = nix::fcntl::open(
+            backup_dir_path.as_str(),
+            OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+            Mode::empty(),
+        )
+        .map_err(|e| format!("Failed to open backup dir {}: {}", backup_dir_path, e))?;
+        let synced = nix::unistd::fsync(fd);
+        let _ = nix::unistd::close(fd);
+
+        synced.map_err(|e| format!("Failed to fsync backup dir {}: {}", backup_dir_path, e))?;
+        debug!("Synced backup dir {}", backup_dir_path);
+        Ok(())
+    }
+
+    /// Returns the output dir path for the backup, with date placeholders expanded.
+    ///
+    /// If the destination filesystem isn't mounted (yet), the path below its mount path is returned.
+    pub fn backup_dir_path(&self) -> String {
+        backup_dir_path(self.dst_filesystem, self.backup_device)
     }
 
     /// Returns the output file path for the backup.
-    fn backup_file_path(&self) -> String {
+    pub fn backup_file_path(&self) -> String {
         let relative_path = RelativePath::new(&self.backup_dir_path())
             .join_normalized(self.file_name())
             .to_string();
@@ -132,135 +1018,1120 @@ impl<'a> Backup<'a> {
         format!("/{}", relative_path)
     }
 
-    /// Generates the file name for the backup image.
-    fn file_name(&self) -> String {
-        format!(
-            "{}_{}",
-            current_date(),
-            self.suffix_file_name_pattern().replace(' ', "-")
-        )
+    /// Returns the path `dd` writes the image to, before it is renamed to `backup_file_path()`.
+    pub fn partial_file_path(&self) -> String {
+        format!("{}{}", self.backup_file_path(), PARTIAL_FILE_EXTENSION)
     }
 
-    /// Generates the stable postfix file name for the backup image.
-    ///
-    /// The stable postfix file name is generated by combining the model and serial
-    /// number of the block device associated with the backup. Any spaces in the
-    /// names are replaced with hyphens.
-    ///
-    /// # Returns
-    ///
-    /// The stable postfix file name as a string.
-    fn suffix_file_name_pattern(&self) -> String {
-        format!(
-            "{}.img",
-            vec![
-                self.backup_device.name.clone(),
-                self.backup_device.blockdevice.model.clone(),
-                self.backup_device.blockdevice.serial.clone(),
-            ]
-            .into_iter()
-            .flatten()
-            .collect::<Vec<String>>()
-            .join("_")
-            .replace(' ', "-")
+    /// Generates the file name for the backup image, by expanding the date and time of the pattern.
+    pub fn file_name(&self) -> String {
+        self.file_name_pattern()
+            .replace("{date}", &self.created_at.format(DATE_FORMAT).to_string())
+            .replace("{time}", &self.created_at.format(TIME_FORMAT).to_string())
+    }
+
+    /// Generates the file name pattern shared by all backups of the device, its series,
+    /// see the free function `file_name_pattern`.
+    pub fn file_name_pattern(&self) -> String {
+        file_name_pattern(
+            self.dd_settings,
+            self.backup_device,
+            self.backup_args.tag.as_deref(),
         )
     }
 
-    /// Checks if the number of existing backups exceeds the specified number of copies.
+    /// Checks if existing backups need to be deleted, because they are older than the maximum age
+    /// or their number exceeds the specified number of copies.
+    /// If neither `max_age_days` nor `copies` is set then return false
+    pub fn needs_deletion(&self) -> bool {
+        !self.expired_backups().is_empty() || self.exceeds_copies(0)
+    }
+
+    /// Checks if the number of existing backups, minus `deleted` ones, exceeds the specified number of copies.
     /// If the copies is `None` then return false
-    fn needs_deletion(&self) -> bool {
-        let present_number_of_copies = self
-            .dst_filesystem
-            .present_number_of_copies(&self.suffix_file_name_pattern(), &self.backup_dir_path());
+    fn exceeds_copies(&self, deleted: usize) -> bool {
+        let present_number_of_copies = self.dst_filesystem.present_number_of_copies(
+            &self.file_name_pattern(),
+            &self.backup_dir_path(),
+            self.backup_device.method,
+        );
         match self.backup_device.copies {
-            Some(copies) => present_number_of_copies >= copies,
+            Some(copies) => present_number_of_copies.saturating_sub(deleted) >= copies,
             None => false,
         }
     }
 
+    /// Returns the names of the existing backups older than `max_age_days`, oldest first.
+    /// If the maximum age is `None` or the backups can't be read then return none
+    fn expired_backups(&self) -> Vec<String> {
+        match self.backup_device.max_age_days {
+            Some(max_age_days) => self
+                .dst_filesystem
+                .expired_backups(
+                    &self.file_name_pattern(),
+                    &self.backup_dir_path(),
+                    self.backup_device.method,
+                    Duration::from_secs(max_age_days * 24 * 60 * 60),
+                )
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
     /// Validates the state of the backup process by performing the following checks:
-    /// 1. Checks if the target file is already present. If it is, an error is returned.
-    /// 2. Checks if the oldest backup needs to be deleted based on the configured number of copies.
-    ///    If a deletion is required, the oldest backup is deleted.
-    /// 3. If no deletion is needed, checks if the target filesystem has enough space to accommodate
-    ///    the new backup. If there is insufficient space, an error is returned.
+    /// 1. Checks that the output dir is within the mounted filesystem. If it isn't, an error is returned.
+    /// 2. Creates the output dir, if it doesn't exist yet.
+    /// 3. Checks that the output dir is writable, except on a dry run. If it isn't, an error is returned.
+    /// 4. Checks if the target file is already present. If it is, an error is returned, unless it is overwritten.
+    /// 5. Checks if old backups need to be deleted based on the configured maximum age and number of copies.
+    ///    If a deletion is required, the expired backups and, if still too many, the oldest backup are deleted.
+    /// 6. Checks if the target filesystem has enough space to accommodate the new backup and the
+    ///    configured headroom, unless the space check is skipped. If there is insufficient space,
+    ///    an error is returned, or only a warning logged for `sparse` images.
+    ///
     /// If all checks pass, `Ok(())` is returned indicating that the state is valid and the backup
     /// process can proceed.
-    fn validate_state(&self) -> Result<(), String> {
-        self.target_file_is_present()?;
-        let needed_deletion = self.delete_oldest_backup_if_needed()?;
-        if !needed_deletion {
-            self.target_filesystem_has_enough_space()?;
+    fn validate_state(&self) -> Result<(), BackupError> {
+        self.backup_dir_is_contained()?;
+        self.create_backup_dir()?;
+        if !self.backup_args.dry_run {
+            probe_writable(&self.backup_dir_path(), Some(true))?;
+        }
+        let overwrites = self.target_file_is_present()?;
+        let deleted_space = self.delete_oldest_backup_if_needed(overwrites)?;
+        // the space of really deleted backups is already available, only a dry run has to count it
+        let freed_space = match self.backup_args.dry_run {
+            true => deleted_space,
+            false => 0,
+        };
+        // the size of an incremental rsync backup or a compressed image is unknown upfront
+        if self.backup_device.method == BackupMethod::Dd && self.dd_settings.compression.is_none() {
+            if self.dst_filesystem.skip_space_check || self.backup_args.skip_space_check {
+                info!(
+                    "Skipping space check on destination filesystem {}",
+                    self.dst_filesystem.device_path
+                );
+            } else {
+                match self.target_filesystem_has_enough_space(freed_space) {
+                    // holes of a sparse image take up no space, so the source size overstates it
+                    Err(e @ BackupError::InsufficientSpace { .. }) if self.dd_settings.sparse => {
+                        warn!("{}, writing sparse image anyway", e)
+                    }
+                    result => result?,
+                }
+            }
         }
         Ok(())
     }
 
-    /// Side-Effect: Deletes the oldest backup file if the number of existing backups exceeds the specified number of copies.
-    fn delete_oldest_backup_if_needed(&self) -> Result<bool, String> {
-        let needs_deletion = self.needs_deletion();
-        if needs_deletion {
-            if self.backup_args.dry_run {
+    /// Checks that the output dir is the mountpoint or below it.
+    ///
+    /// The destination path is already validated with the config, this guards against
+    /// expanded placeholders or mountpoints which would still lead outside.
+    fn backup_dir_is_contained(&self) -> Result<(), BackupError> {
+        let mountpoint = join_backup_dir_path(
+            self.dst_filesystem
+                .blockdevice
+                .mountpoint
+                .as_ref()
+                .unwrap_or(&self.dst_filesystem.mountpath),
+            "./",
+        );
+        let backup_dir_path = self.backup_dir_path();
+
+        if Path::new(&backup_dir_path).starts_with(&mountpoint) {
+            Ok(())
+        } else {
+            Err(BackupError::Other(format!(
+                "Backup dir {} is outside of the mounted filesystem {}",
+                backup_dir_path, mountpoint
+            )))
+        }
+    }
+
+    /// Side-Effect: Creates the output dir including its parents, if it doesn't exist yet.
+    ///
+    /// Since the `destination_path` may contain date placeholders, the dir of a new period
+    /// needs to be created on its first backup.
+    fn create_backup_dir(&self) -> Result<(), BackupError> {
+        let backup_dir_path = self.backup_dir_path();
+        if Path::new(&backup_dir_path).is_dir() {
+            return Ok(());
+        }
+
+        if self.backup_args.dry_run {
+            info!("[DRY RUN] Would create backup dir {}", backup_dir_path);
+            Ok(())
+        } else {
+            info!("Create backup dir {}", backup_dir_path);
+            fs::create_dir_all(&backup_dir_path).map_err(BackupError::io(format!(
+                "Failed to create backup dir {}",
+                backup_dir_path
+            )))
+        }
+    }
+
+    /// Side-Effect: Deletes the backups older than the maximum age, then the oldest backup file
+    /// if the number of remaining backups exceeds the specified number of copies.
+    /// A backup violating either rule is deleted.
+    /// A backup which `overwrites` the present one of today doesn't add a copy.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(u64)`: The size of the deleted backup files in bytes, on a dry run of the ones which would be deleted.
+    /// - `Err(BackupError)`: If a backup couldn't be deleted.
+    fn delete_oldest_backup_if_needed(&self, overwrites: bool) -> Result<u64, BackupError> {
+        let expired_backups = self.expired_backups();
+        let exceeds_copies = self.exceeds_copies(expired_backups.len() + usize::from(overwrites));
+        let needs_deletion = !expired_backups.is_empty() || exceeds_copies;
+        let oldest_backup = match exceeds_copies {
+            true => self.oldest_backup_after(&expired_backups)?,
+            false => None,
+        };
+        let mut freed_space = 0;
+        if self.backup_args.dry_run {
+            for expired_backup in &expired_backups {
+                info!(
+                    "[DRY RUN] Would delete backup {} in {}, older than {} days",
+                    expired_backup,
+                    self.backup_dir_path(),
+                    self.backup_device.max_age_days.unwrap_or_default()
+                );
+                freed_space += self.backup_size(expired_backup);
+            }
+            if let Some(oldest_backup) = &oldest_backup {
                 info!(
-                    "[DRY RUN] Would delete oldest backup file with suffix: {} in {}",
-                    self.suffix_file_name_pattern(),
+                    "[DRY RUN] Would delete oldest backup {} in {}",
+                    oldest_backup,
                     self.backup_dir_path()
                 );
-            } else {
-                self.dst_filesystem.delete_oldest_backup(
-                    &self.suffix_file_name_pattern(),
-                    &self.backup_dir_path(),
-                )?;
+                freed_space += self.backup_size(oldest_backup);
             }
+            self.deleted_oldest.set(needs_deletion);
+        } else if needs_deletion {
+            let mut deleted = false;
+            for expired_backup in &expired_backups {
+                info!(
+                    "Backup {} is older than {} days",
+                    expired_backup,
+                    self.backup_device.max_age_days.unwrap_or_default()
+                );
+                let size = self.backup_size(expired_backup);
+                if self.delete_backup_if_confirmed(expired_backup)? {
+                    freed_space += size;
+                    deleted = true;
+                }
+            }
+            if let Some(oldest_backup) = &oldest_backup {
+                let size = self.backup_size(oldest_backup);
+                if self.delete_backup_if_confirmed(oldest_backup)? {
+                    freed_space += size;
+                    deleted = true;
+                }
+            }
+            self.deleted_oldest.set(deleted);
         }
-        Ok(needs_deletion)
+        Ok(freed_space)
+    }
+
+    /// Returns the oldest backup which remains besides the `expired_backups`, if any.
+    fn oldest_backup_after(
+        &self,
+        expired_backups: &[String],
+    ) -> Result<Option<String>, BackupError> {
+        let backup_dir_path = self.backup_dir_path();
+        Ok(self
+            .dst_filesystem
+            .present_backup_files(
+                &self.file_name_pattern(),
+                &backup_dir_path,
+                self.backup_device.method,
+            )?
+            .into_iter()
+            .filter(|file_name| !expired_backups.contains(file_name))
+            .min_by_key(|file_name| backup_time(&Path::new(&backup_dir_path).join(file_name))))
+    }
+
+    /// Returns the size of the backup file `file_name` in the backup dir in bytes.
+    fn backup_size(&self, file_name: &str) -> u64 {
+        self.dst_filesystem
+            .backup_size(&self.backup_dir_path(), file_name)
+    }
+
+    /// Deletes the backup `file_name` from the backup directory, after asking for confirmation with `--interactive`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(true)`: The backup was deleted.
+    /// - `Ok(false)`: The deletion was declined, the backup is kept.
+    /// - `Err(BackupError)`: The backup couldn't be deleted or the answer couldn't be read.
+    fn delete_backup_if_confirmed(&self, file_name: &str) -> Result<bool, BackupError> {
+        if self.backup_args.confirms_deletions() && !self.confirm_deletion(file_name)? {
+            warn!("Keeping backup {} in {}", file_name, self.backup_dir_path());
+            return Ok(false);
+        }
+        self.dst_filesystem.delete_backup(
+            &self.backup_dir_path(),
+            file_name,
+            self.backup_device.method,
+        )?;
+        Ok(true)
+    }
+
+    /// Asks on the terminal whether to delete the backup `file_name`, showing its size and age.
+    fn confirm_deletion(&self, file_name: &str) -> Result<bool, BackupError> {
+        confirm(&format!(
+            "Delete old backup {}?",
+            self.describe_backup(file_name)
+        ))
+    }
+
+    /// Describes the backup `file_name` in the backup dir by its path, size and age.
+    fn describe_backup(&self, file_name: &str) -> String {
+        let file_path = Path::new(&self.backup_dir_path()).join(file_name);
+        let size = match self.backup_size(file_name) {
+            0 => "-".to_string(),
+            size => format_byte_size(size),
+        };
+        let age = DateTime::<Local>::from(backup_time(&file_path)).humanize();
+        format!("{} ({}, created {})", file_path.display(), size, age)
     }
 
     /// Checks if the target filesystem has enough space to accommodate the backup of the device.
-    /// It compares the available space on the filesystem with the total size of the device to be backed up.
-    /// If there is sufficient space, `Ok(())` is returned, indicating that the backup can proceed.
-    /// If there is not enough space or if it couldn't be read, an error is returned with a descriptive message.
-    /// If either available_space or needed_space is None then proceed with an Ok as well.
-    fn target_filesystem_has_enough_space(&self) -> Result<(), String> {
-        let available_space = self.dst_filesystem.available_space()?.ok_or(format!(
-            "Available space on {} not readable",
-            self.dst_filesystem.device_path
-        ))?;
-        let needed_space = self.backup_device.total_size()?.ok_or(format!(
-            "Needed space on {} not readable",
-            self.backup_device.device_path
-        ))?;
-
-        let remaining_space: i64 = available_space as i64 - needed_space as i64;
-        if remaining_space > 0 {
+    /// It compares the available space on the filesystem plus the `freed_space` of backups deleted
+    /// on a dry run, minus the space reserved by concurrently running backups and the configured headroom,
+    /// with the total size of the device to be backed up, all in exact bytes.
+    /// If there is sufficient space, it is reserved until the backup finished and `Ok(())` is returned,
+    /// indicating that the backup can proceed.
+    /// If there is not enough space or if it couldn't be read, an error is returned with all these numbers.
+    fn target_filesystem_has_enough_space(&self, freed_space: u64) -> Result<(), BackupError> {
+        let not_readable = || {
+            format!(
+                "Available space on {} not readable",
+                self.dst_filesystem.device_path
+            )
+        };
+        let available_space = self
+            .dst_filesystem
+            .available_space()?
+            .ok_or_else(not_readable)?;
+        let headroom = self.dd_settings.space_headroom.in_bytes(
+            self.dst_filesystem
+                .total_space()?
+                .ok_or_else(not_readable)?,
+        );
+        let usable_space = (available_space + freed_space).saturating_sub(headroom);
+        // a resumed image only needs the space of the remaining blocks
+        let needed_space = self
+            .backup_device
+            .total_size()
+            .saturating_sub(self.resume_blocks().unwrap_or(0) * self.dd_settings.block_size);
+
+        // checking and reserving under one lock, so concurrent backups can't both take the same space
+        let mut reserved_space = self
+            .dst_filesystem
+            .reserved_space
+            .lock()
+            .map_err(|e| format!("Failed to lock reserved space: {}", e))?;
+        if try_reserve_space(usable_space, &mut reserved_space, needed_space) {
+            self.reserved_space.set(needed_space);
             Ok(())
         } else {
-            Err(format!(
-                "Not enough space on destination filesystem {}, to backup device {}",
-                self.dst_filesystem.device_path, self.backup_device.device_path
-            ))
+            Err(BackupError::InsufficientSpace {
+                filesystem: self.dst_filesystem.device_path.clone(),
+                device: self.backup_device.device_path.clone(),
+                available: available_space,
+                freed: freed_space,
+                reserved: *reserved_space,
+                headroom,
+                needed: needed_space,
+            })
+        }
+    }
+
+    /// Releases the space reserved on the destination filesystem by the space check, if any.
+    fn release_reserved_space(&self) {
+        let reserved_space = self.reserved_space.take();
+        if reserved_space > 0 {
+            if let Ok(mut filesystem_reserved_space) = self.dst_filesystem.reserved_space.lock() {
+                *filesystem_reserved_space =
+                    filesystem_reserved_space.saturating_sub(reserved_space);
+            }
         }
     }
 
-    /// Checks if the target backup file is already present.
+    /// Checks if the target backup file or directory is already present.
     ///
-    /// If the backup file already exists at the specified output file path,
-    /// this function returns an error indicating that the backup should be skipped.
+    /// If the backup already exists at the specified output file path,
+    /// this function returns an error indicating that the backup should be skipped,
+    /// unless it is overwritten with `--overwrite`. Overwriting is confirmed on the terminal,
+    /// unless `--yes` is passed or it is a dry run.
     ///
     /// # Returns
     ///
-    /// - `Ok(())`: If the backup file does not exist and can proceed.
-    /// - `Err(String)`: If the backup file is already present.
-    fn target_file_is_present(&self) -> Result<(), String> {
+    /// - `Ok(false)`: If the backup file does not exist and can proceed.
+    /// - `Ok(true)`: If the backup file is present and will be replaced by the new backup.
+    /// - `Err(BackupError)`: If the backup file is already present and isn't overwritten.
+    fn target_file_is_present(&self) -> Result<bool, BackupError> {
         let file_path = self.backup_file_path();
         let path = Path::new(&file_path);
 
-        if path.exists() && path.is_file() {
-            Err(format!(
-                "Backup file for today is already present {}. Skipping it",
-                file_path
-            ))
-        } else {
-            Ok(())
+        if !path.exists() {
+            return Ok(false);
+        }
+        if !self.backup_args.overwrite {
+            return Err(BackupError::BackupPresent(file_path));
+        }
+        if self.backup_args.dry_run {
+            info!("[DRY RUN] Would overwrite backup {}", file_path);
+            return Ok(true);
+        }
+        if !self.backup_args.yes {
+            if !io::stdin().is_terminal() {
+                return Err(BackupError::Other(format!(
+                    "Backup file for today is already present {}, pass --yes to overwrite it without a terminal",
+                    file_path
+                )));
+            }
+            if !confirm(&format!(
+                "Overwrite backup {}?",
+                self.describe_backup(&self.file_name())
+            ))? {
+                return Err(BackupError::Other(format!(
+                    "Keeping present backup file {}, skipping it",
+                    file_path
+                )));
+            }
+        }
+        warn!("Overwriting backup {}", file_path);
+        Ok(true)
+    }
+}
+
+/// Returns the user and group IDs of the current user, who the backup files are chowned to.
+fn current_owner() -> Owner {
+    Owner {
+        uid: unsafe { libc::getuid() },
+        gid: unsafe { libc::getgid() },
+        mode: None,
+    }
+}
+
+/// Joins the `destination_path` onto the `mountpoint`, normalizing `.` and `..` components.
+///
+/// An absolute `destination_path` is treated as relative to the mountpoint as well,
+/// so `/backups` and `backups` both resolve to `<mountpoint>/backups`.
+pub(crate) fn join_backup_dir_path(mountpoint: &str, destination_path: &str) -> String {
+    let relative_path = RelativePath::new(mountpoint)
+        .join_normalized(destination_path.trim_start_matches('/'))
+        .to_string();
+
+    format!("/{}", relative_path)
+}
+
+/// Checks that `dir` is writable by creating and removing a probe file, with sudo like `dd`
+/// if `is_sudo_needed`.
+///
+/// A destination mounted read-only, e.g. because its filesystem is dirty, is detected before
+/// `dd` fails after a long time.
+fn probe_writable(dir: &str, is_sudo_needed: Option<bool>) -> Result<(), String> {
+    let probe_file_path = format!("{}/.dd_backup_write_probe", dir);
+    command_output(
+        vec!["touch", &probe_file_path],
+        "probe that the backup dir is writable",
+        is_sudo_needed,
+    )
+    .and_then(|_| {
+        command_output(
+            vec!["rm", "-f", &probe_file_path],
+            "remove write probe",
+            is_sudo_needed,
+        )
+    })
+    .map_err(|e| {
+        format!(
+            "Backup dir {} is not writable, the destination may be mounted read-only: {}",
+            dir, e
+        )
+    })?;
+    Ok(())
+}
+
+/// Returns the output dir path for the backups of `backup_device` on `dst_filesystem`, with date placeholders expanded.
+///
+/// If the destination filesystem isn't mounted (yet), the path below its mount path is returned.
+pub fn backup_dir_path(dst_filesystem: &Filesystem, backup_device: &Device) -> String {
+    join_backup_dir_path(
+        dst_filesystem
+            .blockdevice
+            .mountpoint
+            .as_ref()
+            .unwrap_or(&dst_filesystem.mountpath),
+        &expand_date_placeholders(&backup_device.destination_path),
+    )
+}
+
+/// Generates the file name pattern shared by all backups of the device, its series.
+///
+/// The pattern is the name template of the destination with the `tag` of the run, the name, and the model,
+/// serial number and UUID of the block device associated with the backup expanded,
+/// while `{date}` and `{time}` are left in. Any spaces in the values are replaced with hyphens,
+/// as well as slashes and underscores in the tag. Images end with `.img`, followed by the extension
+/// of the `compression`, like `.img.zst`, `rsync` backup directories have no extension.
+///
+/// # Returns
+///
+/// The file name pattern as a string.
+pub fn file_name_pattern(
+    dd_settings: &DdSettings,
+    backup_device: &Device,
+    tag: Option<&str>,
+) -> String {
+    let extension = match backup_device.method {
+        BackupMethod::Dd => format!(
+            ".img{}",
+            dd_settings
+                .compression
+                .map(|compression| compression.extension())
+                .unwrap_or("")
+        ),
+        BackupMethod::Rsync => "".to_string(),
+    };
+    let values = [
+        ("{tag}", tag.map(|tag| tag.replace(['/', '_'], "-"))),
+        ("{name}", backup_device.name.clone()),
+        ("{model}", backup_device.blockdevice.model.clone()),
+        ("{serial}", backup_device.blockdevice.serial.clone()),
+        ("{uuid}", backup_device.blockdevice.uuid.clone()),
+    ];
+    format!(
+        "{}{}",
+        expand_name_template(&dd_settings.name_template, &values),
+        extension
+    )
+}
+
+/// Expands the placeholders of the name `template` with their `values`, spaces replaced with hyphens.
+///
+/// A placeholder without value is dropped with the following `_` or `-`, or the preceding one at the end,
+/// so optional values don't leave doubled separators. `{date}` and `{time}` are left in.
+fn expand_name_template(template: &str, values: &[(&str, Option<String>)]) -> String {
+    let mut name = template.to_string();
+    for (placeholder, value) in values {
+        match value.as_deref().filter(|value| !value.is_empty()) {
+            Some(value) => name = name.replace(placeholder, &value.replace(' ', "-")),
+            None => {
+                while let Some(start) = name.find(placeholder) {
+                    let end = start + placeholder.len();
+                    let is_separator = |c: Option<char>| matches!(c, Some('_') | Some('-'));
+                    if is_separator(name[end..].chars().next()) {
+                        name.replace_range(start..end + 1, "");
+                    } else if is_separator(name[..start].chars().next_back()) {
+                        name.replace_range(start - 1..end, "");
+                    } else {
+                        name.replace_range(start..end, "");
+                    }
+                }
+            }
         }
     }
+    name
+}
+
+/// Returns the `dd` arguments to continue after `resume_blocks` blocks of the block size,
+/// the `skip` of the source and the `seek` of the image.
+fn resume_args(resume_blocks: u64) -> [String; 2] {
+    [
+        format!("skip={}", resume_blocks),
+        format!("seek={}", resume_blocks),
+    ]
+}
+
+/// Returns the `pv` command passing its input on with at most `max_bandwidth` bytes per second.
+fn throttle_command(max_bandwidth: u64) -> Vec<String> {
+    vec![
+        "pv".to_string(),
+        "-q".to_string(),
+        "-L".to_string(),
+        max_bandwidth.to_string(),
+    ]
+}
+
+/// Returns the shell pipeline piping the output of each of the `commands` into the next one,
+/// the last one writing into `output_file_path` if given, with every word quoted.
+fn shell_pipeline(commands: &[Vec<String>], output_file_path: Option<&str>) -> String {
+    let pipeline = commands
+        .iter()
+        .map(|command| {
+            command
+                .iter()
+                .map(|part| shell_quote(part))
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect::<Vec<String>>()
+        .join(" | ");
+    match output_file_path {
+        Some(output_file_path) => format!("{} > {}", pipeline, shell_quote(output_file_path)),
+        None => pipeline,
+    }
+}
+
+/// Asks the `question` on the terminal and returns whether it was answered with yes.
+///
+/// Prompts of concurrent backups are serialized, so their questions and answers don't interleave.
+fn confirm(question: &str) -> Result<bool, BackupError> {
+    static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+    let _prompt_lock = PROMPT_LOCK
+        .lock()
+        .map_err(|e| format!("Failed to lock the prompt: {}", e))?;
+    eprint!("{} [y/N] ", question);
+    io::stderr()
+        .flush()
+        .map_err(BackupError::io("Failed to print the prompt".to_string()))?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(BackupError::io("Failed to read the answer".to_string()))?;
+    Ok(is_confirmed(&answer))
+}
+
+/// Returns the number of read errors `dd` reported in its `stderr`, one per skipped block.
+fn read_error_count(stderr: &str) -> u64 {
+    stderr
+        .lines()
+        .filter(|line| line.starts_with("dd: error reading"))
+        .count() as u64
+}
+
+/// Runs the `sha256sum` command, with sudo to read devices, and returns the checksum it prints.
+fn sha256(command_parts: Vec<&str>, description: &str) -> Result<String, String> {
+    let output = command_output(command_parts, description, Some(true))?;
+    parse_sha256sum_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the checksum from an output line of `sha256sum`, like `<checksum>  <file>`.
+fn parse_sha256sum_output(output: &str) -> Result<String, String> {
+    output
+        .split_whitespace()
+        .next()
+        .filter(|checksum| checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|checksum| checksum.to_lowercase())
+        .ok_or(format!("Unexpected output of sha256sum: {}", output.trim()))
+}
+
+/// Returns whether `needed_space` bytes fit into `available_space` bytes.
+fn has_enough_space(available_space: u64, needed_space: u64) -> bool {
+    needed_space <= available_space
+}
+
+/// Adds `needed_space` to the `reserved_space`, if it fits into the `available_space` not reserved yet.
+fn try_reserve_space(available_space: u64, reserved_space: &mut u64, needed_space: u64) -> bool {
+    let fits = has_enough_space(
+        available_space.saturating_sub(*reserved_space),
+        needed_space,
+    );
+    if fits {
+        *reserved_space += needed_space;
+    }
+    fits
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::run::{
+        backup_run::lsblk::BlockDevice,
+        config::{BackupDevice, DestinationId, DEFAULT_NAME_TEMPLATE},
+    };
+
+    use super::*;
+
+    /// Returns the destination filesystem `sdy1` mounted at `dst_dir` and the source device `sdz`
+    /// with serial `SRC1` backed up to it.
+    fn generate_test_filesystem_and_device(dst_dir: &Path) -> (Filesystem, Device) {
+        let [dst_blockdevice, src_blockdevice]: [BlockDevice; 2] = serde_json::from_value(serde_json::json!([
+            {"name": "sdy1", "model": null, "serial": null, "uuid": "DST1", "mountpoint": dst_dir, "size": 1000},
+            {"name": "sdz", "model": "Disk", "serial": "SRC1", "uuid": null, "mountpoint": null, "size": 1000}
+        ]))
+        .unwrap();
+        let filesystem = Filesystem::new(
+            &DestinationId::Uuid("DST1".to_string()),
+            &BackupConfig::default(),
+            &[dst_blockdevice],
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        let device = Device::from_blockdevice(
+            &BackupDevice {
+                serial: Some("SRC1".to_string()),
+                ..Default::default()
+            },
+            &src_blockdevice,
+            &[],
+            "/.".to_string(),
+        );
+        (filesystem, device)
+    }
+
+    #[test]
+    fn test_build_pipeline() {
+        let dst_dir = Path::new("/mnt/backups");
+        let (filesystem, device) = generate_test_filesystem_and_device(dst_dir);
+        let backup_args = BackupArgs::default();
+        let dd_settings = DdSettings::new(&BackupConfig::default()).unwrap();
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        let output_arg = format!("of={}", backup.partial_file_path());
+        assert_eq!(
+            backup.build_pipeline(None),
+            [
+                "dd",
+                "if=/dev/sdz",
+                "bs=4194304",
+                "status=progress",
+                &output_arg
+            ]
+        );
+        assert_eq!(
+            backup.build_pipeline(Some(2)),
+            [
+                "dd",
+                "if=/dev/sdz",
+                "bs=4194304",
+                "skip=2",
+                "status=progress",
+                &output_arg,
+                "seek=2"
+            ]
+        );
+
+        let backup_args = BackupArgs {
+            dd_timeout: Some(60),
+            summary_only: true,
+            ..Default::default()
+        };
+        let dd_settings = DdSettings {
+            dd_options: vec!["conv=noerror".to_string()],
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        assert_eq!(
+            backup.build_pipeline(None),
+            [
+                "timeout",
+                "60",
+                "dd",
+                "if=/dev/sdz",
+                "bs=4194304",
+                "status=none",
+                "conv=noerror",
+                &output_arg
+            ]
+        );
+
+        // throttled sparse images are written by a second `dd`, which can seek
+        let backup_args = BackupArgs::default();
+        let dd_settings = DdSettings {
+            sparse: true,
+            max_bandwidth: Some(1000),
+            dd_options: vec![],
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        let source_command = ["dd", "if=/dev/sdz", "bs=4194304", "status=progress"]
+            .map(String::from)
+            .to_vec();
+        let output_command = [
+            "dd",
+            "bs=4194304",
+            "iflag=fullblock",
+            "status=none",
+            &output_arg,
+            "conv=sparse",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            backup.build_pipeline(None),
+            [
+                "bash",
+                "-o",
+                "pipefail",
+                "-c",
+                &shell_pipeline(
+                    &[
+                        source_command.clone(),
+                        throttle_command(1000),
+                        output_command
+                    ],
+                    None
+                )
+            ]
+        );
+
+        // only raw images written from the start are hashed while writing
+        let backup_args = BackupArgs {
+            parallel_checksum: true,
+            ..Default::default()
+        };
+        let dd_settings = DdSettings {
+            verify: true,
+            sparse: false,
+            max_bandwidth: None,
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        assert_eq!(
+            backup.build_pipeline(None),
+            [
+                "bash",
+                "-o",
+                "pipefail",
+                "-c",
+                &shell_pipeline(
+                    &[
+                        source_command.clone(),
+                        vec![
+                            "tee".to_string(),
+                            "--".to_string(),
+                            backup.partial_file_path()
+                        ],
+                        vec!["sha256sum".to_string()]
+                    ],
+                    None
+                )
+            ]
+        );
+        assert_eq!(backup.build_pipeline(Some(2))[0], "dd");
+
+        let dd_settings = DdSettings {
+            compression: Some(Compression::Zstd),
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        assert_eq!(
+            backup.build_pipeline(None),
+            [
+                "bash",
+                "-o",
+                "pipefail",
+                "-c",
+                &shell_pipeline(
+                    &[source_command, vec!["zstd".to_string(), "-c".to_string()]],
+                    Some(&backup.partial_file_path())
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_with_retries() {
+        let dst_dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_run_with_retries_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dst_dir).unwrap();
+        let (filesystem, device) = generate_test_filesystem_and_device(&dst_dir);
+        let backup_args = BackupArgs {
+            dd_timeout: Some(60),
+            summary_only: true,
+            ..Default::default()
+        };
+        let dd_settings = DdSettings::new(&BackupConfig::default()).unwrap();
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        let read_error = [
+            "bash",
+            "-c",
+            "echo 'dd: error reading /dev/sdz: Input/output error' >&2; exit 1",
+        ];
+
+        let dd_run = backup.run_with_retries(&["echo", "copied"], None).unwrap();
+        assert_eq!(dd_run.stdout, "copied\n");
+
+        // a failed attempt removes its partial file
+        fs::write(backup.partial_file_path(), "").unwrap();
+        assert!(matches!(
+            backup.run_with_retries(&read_error, None),
+            Err(BackupError::CommandFailed { .. })
+        ));
+        assert!(!Path::new(&backup.partial_file_path()).exists());
+        assert_eq!(backup.skipped_blocks.get(), 0);
+
+        assert!(matches!(
+            backup.run_with_retries(&["bash", "-c", "exit 124"], None),
+            Err(BackupError::CommandTimedOut { timeout, .. }) if timeout == Duration::from_secs(60)
+        ));
+
+        let dd_settings = DdSettings {
+            dd_options: vec!["conv=noerror,sync".to_string()],
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        assert!(backup.run_with_retries(&read_error, None).is_ok());
+        assert_eq!(backup.skipped_blocks.get(), 1);
+
+        let backup_args = BackupArgs {
+            parallel_checksum: true,
+            summary_only: true,
+            ..Default::default()
+        };
+        let dd_settings = DdSettings {
+            verify: true,
+            dd_options: vec![],
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        let write_and_hash = format!(
+            "printf image | tee -- {} | sha256sum",
+            shell_quote(&backup.partial_file_path())
+        );
+        let dd_run = backup
+            .run_with_retries(&["bash", "-c", &write_and_hash], None)
+            .unwrap();
+        assert_eq!(
+            dd_run.checksum.as_deref(),
+            Some("6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d")
+        );
+        assert_eq!(
+            fs::read_to_string(backup.partial_file_path()).unwrap(),
+            "image"
+        );
+
+        fs::remove_dir_all(&dst_dir).unwrap();
+    }
+
+    #[test]
+    fn test_finalize_image() {
+        let dst_dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_finalize_image_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dst_dir).unwrap();
+        let (filesystem, device) = generate_test_filesystem_and_device(&dst_dir);
+        let backup_args = BackupArgs::default();
+        let dd_settings = DdSettings::new(&BackupConfig::default()).unwrap();
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        fs::write(backup.partial_file_path(), "image").unwrap();
+
+        backup
+            .finalize_image(
+                "dd if=/dev/sdz",
+                None,
+                Local::now(),
+                DdRun {
+                    stdout: String::new(),
+                    checksum: None,
+                    copied_bytes: None,
+                    duration: Duration::from_secs(1),
+                },
+            )
+            .unwrap();
+        assert!(!Path::new(&backup.partial_file_path()).exists());
+        assert_eq!(
+            fs::read_to_string(backup.backup_file_path()).unwrap(),
+            "image"
+        );
+        let manifest = Manifest::read(&backup.backup_file_path()).unwrap();
+        assert_eq!(manifest.command, "dd if=/dev/sdz");
+        assert_eq!(manifest.serial.as_deref(), Some("SRC1"));
+        assert_eq!(manifest.checksum, None);
+
+        // a checksum hashed while writing is recorded without reading the missing source device
+        let dd_settings = DdSettings {
+            verify: true,
+            ..dd_settings
+        };
+        let backup = Backup::new(&filesystem, &dd_settings, &device, &backup_args);
+        fs::write(backup.partial_file_path(), "image").unwrap();
+        let checksum = "6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d";
+        backup
+            .finalize_image(
+                "dd if=/dev/sdz",
+                None,
+                Local::now(),
+                DdRun {
+                    stdout: String::new(),
+                    checksum: Some(checksum.to_string()),
+                    copied_bytes: None,
+                    duration: Duration::from_secs(1),
+                },
+            )
+            .unwrap();
+        assert_eq!(backup.read_checksum().as_deref(), Some(checksum));
+        let manifest = Manifest::read(&backup.backup_file_path()).unwrap();
+        assert_eq!(manifest.checksum.as_deref(), Some(checksum));
+
+        fs::remove_dir_all(&dst_dir).unwrap();
+    }
+
+    #[test]
+    fn test_join_backup_dir_path() {
+        assert_eq!(join_backup_dir_path("/mnt", "./"), "/mnt");
+        assert_eq!(join_backup_dir_path("/mnt", "./backups"), "/mnt/backups");
+        assert_eq!(
+            join_backup_dir_path("/mnt", "backups/../other"),
+            "/mnt/other"
+        );
+        // absolute paths are relative to the mountpoint too
+        assert_eq!(join_backup_dir_path("/mnt", "/"), "/mnt");
+        assert_eq!(join_backup_dir_path("/mnt", "/backups"), "/mnt/backups");
+        assert_eq!(join_backup_dir_path("/mnt", "//backups/"), "/mnt/backups");
+        // traversing upward leaves the mountpoint, which validation rejects
+        assert_eq!(join_backup_dir_path("/mnt", "../backups"), "/backups");
+        assert!(!Path::new(&join_backup_dir_path("/mnt", "../backups")).starts_with("/mnt"));
+    }
+
+    #[test]
+    fn test_probe_writable() {
+        let dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_probe_writable_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        // without sudo, the test dir is writable by the current user
+        assert_eq!(probe_writable(dir.to_str().unwrap(), Some(false)), Ok(()));
+        assert!(!dir.join(".dd_backup_write_probe").exists());
+
+        // a file instead of a dir isn't writable into, even for root
+        let not_a_dir = dir.join("not_a_dir");
+        fs::write(&not_a_dir, "").unwrap();
+        assert!(probe_writable(not_a_dir.to_str().unwrap(), Some(false))
+            .unwrap_err()
+            .starts_with("Backup dir"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dd_settings() {
+        let dd_settings = DdSettings::new(&BackupConfig::default()).unwrap();
+        assert_eq!(dd_settings.compression, None);
+        assert_eq!(dd_settings.block_size, 4 * 1024 * 1024);
+        assert_eq!(dd_settings.space_headroom, SpaceHeadroom::Bytes(0));
+        assert_eq!(dd_settings.name_template, DEFAULT_NAME_TEMPLATE);
+        assert_eq!(dd_settings.retries, 0);
+
+        let dd_settings = DdSettings::new(&BackupConfig {
+            compression: Some(Compression::Zstd),
+            block_size: Some("1M".to_string()),
+            retries: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(dd_settings.compression, Some(Compression::Zstd));
+        assert_eq!(dd_settings.block_size, 1024 * 1024);
+        assert_eq!(dd_settings.retries, 2);
+
+        assert!(DdSettings::new(&BackupConfig {
+            block_size: Some("0".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_expand_name_template() {
+        let values = [
+            ("{tag}", None),
+            ("{name}", Some("my desktop".to_string())),
+            ("{model}", Some("Disk".to_string())),
+            ("{serial}", Some("SRC1".to_string())),
+            ("{uuid}", None),
+        ];
+        assert_eq!(
+            expand_name_template("{date}_{tag}_{name}_{model}_{serial}", &values),
+            "{date}_my-desktop_Disk_SRC1"
+        );
+        assert_eq!(
+            expand_name_template("{serial}-{uuid}-{date}T{time}", &values),
+            "SRC1-{date}T{time}"
+        );
+        assert_eq!(
+            expand_name_template("{date}_{serial}_{uuid}", &values),
+            "{date}_SRC1"
+        );
+        assert_eq!(
+            expand_name_template("backup{uuid}{date}", &values),
+            "backup{date}"
+        );
+    }
+
+    #[test]
+    fn test_resume_args() {
+        assert_eq!(resume_args(3), ["skip=3", "seek=3"]);
+    }
+
+    #[test]
+    fn test_shell_pipeline() {
+        let dd_command = vec![
+            "dd".to_string(),
+            "if=/dev/sda".to_string(),
+            "status=progress".to_string(),
+        ];
+        let compress_command = vec!["zstd".to_string(), "-c".to_string()];
+        assert_eq!(
+            shell_pipeline(
+                &[dd_command.clone(), compress_command],
+                Some("/mnt/2023-06-15_My Disk.img.zst.partial")
+            ),
+            "dd if=/dev/sda status=progress | zstd -c > '/mnt/2023-06-15_My Disk.img.zst.partial'"
+        );
+        let output_command = vec!["dd".to_string(), "of=/mnt/My Disk.img.partial".to_string()];
+        assert_eq!(
+            shell_pipeline(&[dd_command, throttle_command(1000), output_command], None),
+            "dd if=/dev/sda status=progress | pv -q -L 1000 | dd 'of=/mnt/My Disk.img.partial'"
+        );
+    }
+
+    #[test]
+    fn test_read_error_count() {
+        let stderr = [
+            "dd: error reading '/dev/sdb': Input/output error",
+            "1024+0 records in",
+            "dd: error reading '/dev/sdb': Input/output error",
+            "2047+2 records in",
+            "2049+0 records out",
+        ]
+        .join("\n");
+        assert_eq!(read_error_count(&stderr), 2);
+        assert_eq!(read_error_count("dd: failed to open '/dev/sdb'"), 0);
+    }
+
+    #[test]
+    fn test_parse_sha256sum_output() {
+        let checksum = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(
+            parse_sha256sum_output(&format!("{}  /dev/sda\n", checksum)),
+            Ok(checksum.to_string())
+        );
+        assert_eq!(
+            parse_sha256sum_output(&format!("{}  -\n", checksum)),
+            Ok(checksum.to_string())
+        );
+        assert!(parse_sha256sum_output("").is_err());
+        assert!(parse_sha256sum_output("sha256sum: /dev/sda: Permission denied").is_err());
+    }
+
+    #[test]
+    fn test_has_enough_space() {
+        // a "3.7G" source, slightly smaller than the free space
+        assert!(has_enough_space(3_972_844_749, 3_972_844_748));
+        assert!(has_enough_space(3_972_844_748, 3_972_844_748));
+        assert!(!has_enough_space(3_972_844_747, 3_972_844_748));
+    }
+
+    #[test]
+    fn test_try_reserve_space() {
+        let mut reserved_space = 0;
+        assert!(try_reserve_space(100, &mut reserved_space, 60));
+        assert_eq!(reserved_space, 60);
+        // a concurrent backup doesn't fit into the remaining space
+        assert!(!try_reserve_space(100, &mut reserved_space, 60));
+        assert_eq!(reserved_space, 60);
+        assert!(try_reserve_space(100, &mut reserved_space, 40));
+        assert_eq!(reserved_space, 100);
+        // reserved space larger than the available one, e.g. after other writes
+        assert!(!try_reserve_space(50, &mut reserved_space, 1));
+    }
 }
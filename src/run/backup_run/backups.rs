@@ -1,20 +1,59 @@
-use crate::run::backup_run::backup::Backup;
-use crate::run::config::{BackupConfig, Config};
+use std::{
+    fmt::Display,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
+use chrono::{DateTime, Local};
+
+use crate::run::backup_run::backup::{Backup, DdSettings};
+use crate::run::catalog::{Catalog, CatalogEntry};
+use crate::run::config::{BackupConfig, Config, DestinationId, FsckPolicy, OnComplete};
+use crate::run::error::BackupError;
+use crate::run::history::HistoryRecord;
+use crate::run::utils::{expand_shell_placeholders, find_executable};
+
+use super::command_output::{command_output, print_commands_only};
 use super::device::Device;
 use super::filesystem::Filesystem;
+use super::lock::DestinationLock;
 use super::lsblk::Lsblk;
+use super::plan::PlanEntry;
+use super::summary::{BackupOutcome, BackupStatus, RunSummary};
 use super::BackupArgs;
 
 #[derive(Debug)]
 pub struct Backups<'a> {
     /// The destination filesystem for the backup.
     pub dst_filesystem: Filesystem,
+    /// The settings of `dd` backups to the destination filesystem.
+    pub dd_settings: DdSettings,
     /// The list of backup devices.
     pub backup_devices: Vec<Device>,
     /// The command line arguments for the backup operation.
     pub backup_args: &'a BackupArgs,
     pub skip_mount: bool,
+    /// The path of the catalog to record completed backups in, if any.
+    pub catalog_path: Option<String>,
+    /// The path of the history file to append backup outcomes to, if any.
+    pub history_file: Option<String>,
+    /// The shell command run before the destination filesystem is checked and mounted, if any.
+    pub pre_mount: Option<String>,
+    /// The shell command run after the destination filesystem is unmounted, if any.
+    pub post_unmount: Option<String>,
+    /// What to do with the drive of the destination filesystem after its backups succeeded.
+    pub on_complete: OnComplete,
+    /// The error of a failed filesystem check, which was ignored with the `warn` policy.
+    pub fsck_warning: Option<String>,
+    /// The configured devices which can't be backed up, as device path, serial and reason,
+    /// listed in the summary of a dry run.
+    pub unavailable_devices: Vec<(String, Option<String>, String)>,
 }
 
 impl<'a> Backups<'a> {
@@ -23,6 +62,8 @@ impl<'a> Backups<'a> {
     ///
     /// # Arguments
     ///
+    /// * `destination` - The UUID or label of the destination filesystem, the configured one, one of its
+    ///   mirrors or the own destination of devices, only the devices backed up to it are included.
     /// * `backup_config` - The backup configuration.
     /// * `lsblk` - The `Lsblk` instance containing available filesystems and devices.
     /// * `backup_args` - The command-line arguments for the backup operation.
@@ -31,48 +72,132 @@ impl<'a> Backups<'a> {
     /// # Returns
     ///
     /// - `Ok(Some(BackUps))`: If the destination filesystem is found and the backup is configured.
-    /// - `Ok(None)`: If the destination filesystem is not found or not configured for backup.
-    /// - `Err(String)`: If there is an error during the process.
+    /// - `Ok(None)`: If the destination filesystem is not found, not unique without `--strict`,
+    ///   or not configured for backup.
+    /// - `Err(BackupError)`: If a device can't be resolved, or the configuration of the destination is invalid.
     pub fn new(
+        destination: &DestinationId,
         backup_config: &BackupConfig,
         lsblk: &Lsblk,
         backup_args: &'a BackupArgs,
         config: &'a Config,
-    ) -> Result<Option<Backups<'a>>, String> {
-        let dst_filesystem = Filesystem::new(
+    ) -> Result<Option<Backups<'a>>, BackupError> {
+        let dst_filesystem = match Filesystem::new(
+            destination,
             backup_config,
             &lsblk.available_filesystems,
-            config.mountpath.clone(),
-        )?;
+            backup_config.mountpath.clone().or(config.mountpath.clone()),
+        ) {
+            // duplicate UUIDs are common after cloning a disk, only `--strict` fails the run for them
+            Err(
+                e @ (BackupError::NotUniqueUuid { .. }
+                | BackupError::NotUniqueDestinationLabel { .. }),
+            ) if !backup_args.strict => {
+                warn!("{}, skipping backups to it", e);
+                return Ok(None);
+            }
+            result => result?,
+        };
+        let dst_filesystem = dst_filesystem.map(|dst_filesystem| Filesystem {
+            command_timeout: backup_args.command_timeout.map(Duration::from_secs),
+            allowed_mountpaths: config.allowed_mountpaths.clone(),
+            ..dst_filesystem
+        });
 
         if let Some(dst_filesystem) = dst_filesystem {
-            let backup_devices_result: Result<Vec<_>, _> = backup_config
+            let backup_devices_result: Result<Vec<_>, BackupError> = backup_config
                 .backup_devices
                 .iter()
+                .filter(|backup_device| backup_device.is_backed_up_to(destination, backup_config))
+                .filter(|backup_device| {
+                    if !backup_device.is_enabled() {
+                        info!(
+                            "Device {} is disabled, skipping it",
+                            backup_device.identifier()
+                        );
+                    }
+                    backup_device.is_enabled()
+                })
+                .filter(|backup_device| {
+                    let selected = backup_args.is_device_selected(&backup_device.identifier());
+                    if !selected {
+                        info!(
+                            "Device {} is not selected by --only-serial or --exclude-serial, skipping it",
+                            backup_device.identifier()
+                        );
+                    }
+                    selected
+                })
                 .map(|backup_device| {
                     Device::new(
                         backup_device,
                         &lsblk.available_devices,
-                        backup_config
+                        &lsblk.available_filesystems,
+                        // `--destination-path` takes precedence over the configured one
+                        backup_args
                             .destination_path
                             .clone()
+                            .or(backup_config.destination_path.clone())
                             .unwrap_or("/.".to_string()),
+                        backup_args.strict,
                     )
+                    .map(|devices| (backup_device, devices))
                 })
                 .collect();
+            let backup_devices_result = backup_devices_result?;
 
-            // Unwrap the `Result<Vec<Device>, String>` and filter out any `None` values using `filter_map`
+            // the resolved devices which Device::new skipped are mounted or have an unexpected size
+            let mut unavailable_devices = Vec::new();
+            for (backup_device, devices) in &backup_devices_result {
+                let blockdevices =
+                    Device::resolve_blockdevices(backup_device, &lsblk.available_devices);
+                if blockdevices.is_empty() {
+                    unavailable_devices.push((
+                        "-".to_string(),
+                        Some(backup_device.identifier()),
+                        "Device not connected".to_string(),
+                    ));
+                }
+                for blockdevice in blockdevices {
+                    let device_path = format!("/dev/{}", blockdevice.name);
+                    if !devices
+                        .iter()
+                        .any(|device| device.device_path == device_path)
+                    {
+                        let reason = match Device::size_mismatch(backup_device, blockdevice)? {
+                            Some(size_mismatch) => size_mismatch.to_string(),
+                            None => "Device is mounted".to_string(),
+                        };
+                        unavailable_devices.push((device_path, blockdevice.serial.clone(), reason));
+                    }
+                }
+            }
+
+            // Flatten the devices of each config entry
             let backup_devices: Vec<Device> = backup_devices_result
-                .map_err(|e| format!("Failed to create Device object: {}", e))?
                 .into_iter()
-                .flatten()
+                .flat_map(|(_, devices)| devices)
                 .collect();
 
             let backups = Backups {
                 dst_filesystem,
+                dd_settings: DdSettings::new(backup_config)?,
                 backup_devices,
                 backup_args,
                 skip_mount: backup_config.skip_mount.unwrap_or(false),
+                catalog_path: backup_args
+                    .catalog_path
+                    .clone()
+                    .or(config.catalog_path.clone()),
+                history_file: backup_args
+                    .history_file
+                    .clone()
+                    .or(config.history_file.clone()),
+                pre_mount: backup_config.pre_mount.clone(),
+                post_unmount: backup_config.post_unmount.clone(),
+                on_complete: backup_config.on_complete.unwrap_or_default(),
+                fsck_warning: None,
+                unavailable_devices,
             };
             debug!("{:?}", backups);
             Ok(Some(backups))
@@ -81,42 +206,653 @@ impl<'a> Backups<'a> {
         }
     }
 
-    /// Executes the backup process.
+    /// Describes what the backup of each device would do, without changing anything.
+    pub fn plan(&self) -> Vec<PlanEntry> {
+        // unless mounting is skipped, the backup is written below the mount path after remounting
+        let mut target_filesystem = self.dst_filesystem.clone();
+        if !self.skip_mount {
+            target_filesystem.blockdevice.mountpoint = None;
+        }
+
+        self.backup_devices
+            .iter()
+            .map(|backup_device| {
+                let target = Backup::new(
+                    &target_filesystem,
+                    &self.dd_settings,
+                    backup_device,
+                    self.backup_args,
+                )
+                .backup_file_path();
+                PlanEntry::new(
+                    &Backup::new(
+                        &self.dst_filesystem,
+                        &self.dd_settings,
+                        backup_device,
+                        self.backup_args,
+                    ),
+                    target,
+                )
+            })
+            .collect()
+    }
+
+    /// Executes the backup process, enclosed by the `pre_mount` and `post_unmount` hooks.
+    /// If the `pre_mount` hook fails, the backups to dst_filesystem fail without being run, see `push_failed`.
+    /// A failing `post_unmount` hook is only logged.
+    /// If no device backup failed, the `on_complete` action is run last, see `run_on_complete`.
+    /// The outcome of each device backup is added to `run_summary`.
+    /// The destination is locked for the whole process, so a second instance fails instead of backing up to it too.
+    /// Returns `Ok(())` if the backup process is successful, otherwise the `BackupError` which
+    /// stopped it, like `AlreadyRunning`, `NotMounted` or `MountFailed`.
+    pub fn run(mut self, run_summary: &mut RunSummary) -> Result<(), BackupError> {
+        // a dry run mounts and unmounts the destination as well, only printing commands touches nothing
+        let _lock = match print_commands_only() {
+            true => None,
+            false => Some(DestinationLock::acquire(
+                self.dst_filesystem
+                    .blockdevice
+                    .uuid
+                    .as_deref()
+                    .unwrap_or_default(),
+            )?),
+        };
+
+        if self.backup_args.dry_run {
+            for (device_path, serial, message) in &self.unavailable_devices {
+                run_summary.push(BackupOutcome {
+                    device_path: device_path.clone(),
+                    serial: serial.clone(),
+                    destination_uuid: self.dst_filesystem.blockdevice.uuid.clone(),
+                    image_path: String::new(),
+                    size: None,
+                    deleted_oldest: false,
+                    duration: None,
+                    status: BackupStatus::Skipped,
+                    message: Some(message.clone()),
+                });
+            }
+        }
+
+        if let Err(e) = self.run_hook(&self.pre_mount, "pre_mount") {
+            error!(
+                "{}, failing backups for filesystem {}",
+                e, self.dst_filesystem.device_path
+            );
+            self.push_failed(run_summary, &BackupError::Other(e));
+            return Ok(());
+        }
+
+        let first_outcome = run_summary.outcomes.len();
+        let result = self.check_mount_and_back_up(run_summary);
+
+        if let Err(e) = self.run_hook(&self.post_unmount, "post_unmount") {
+            error!("{}", e);
+        }
+        let has_failures = run_summary.outcomes[first_outcome..]
+            .iter()
+            .any(|outcome| outcome.status == BackupStatus::Failed);
+        if result.is_ok() && !has_failures {
+            self.run_on_complete();
+        }
+        result
+    }
+
+    /// Runs the `on_complete` action on the drive holding the destination filesystem, e.g. powers it off.
+    ///
+    /// The action is skipped on dry runs, and with `skip_mount`, since the filesystem stays mounted.
+    /// A missing or failing command is only logged, since the backups are done anyway.
+    fn run_on_complete(&self) {
+        let drive_path = self.dst_filesystem.drive_path();
+        let (command_parts, action) = match self.on_complete {
+            OnComplete::None => return,
+            OnComplete::Eject => (vec!["eject", drive_path.as_str()], "eject"),
+            OnComplete::PowerOff => (
+                vec![
+                    "udisksctl",
+                    "power-off",
+                    "--no-user-interaction",
+                    "-b",
+                    drive_path.as_str(),
+                ],
+                "power off",
+            ),
+        };
+        if self.backup_args.dry_run {
+            info!("[DRY RUN] Would {} drive {}", action, drive_path);
+            return;
+        }
+        if self.skip_mount {
+            warn!(
+                "Not running the on_complete action to {} drive {}, since its filesystem stays mounted with skip_mount",
+                action, drive_path
+            );
+            return;
+        }
+        if find_executable(command_parts[0]).is_none() {
+            warn!(
+                "{} not found in PATH, not running the on_complete action to {} drive {}",
+                command_parts[0], action, drive_path
+            );
+            return;
+        }
+        match command_output(
+            command_parts,
+            &format!("{} drive {}", action, drive_path),
+            Some(true),
+        ) {
+            Ok(_) => info!(
+                "Ran the on_complete action to {} drive {}",
+                action, drive_path
+            ),
+            Err(e) => error!(
+                "Failed to {} drive {}, the backups are done anyway: {}",
+                action, drive_path, e
+            ),
+        }
+    }
+
     /// Checks filesystem with `fsck` before mounting it (eventually unmount first).
     /// If fsck was successfull, do backups pairs matching the conditions, unmount
-    /// If fsck was not successfull, dst_filesystem will be skipped
-    /// Returns `Ok(())` if the backup process is successful, otherwise returns an error message.
-    pub fn run(mut self) -> Result<(), String> {
-        if !self.skip_mount && self.dst_filesystem.is_mounted() {
-            self.dst_filesystem.unmount()?;
+    /// The unmount is attempted even if a backup panics, see `always_unmount`.
+    /// If fsck was not successfull, dst_filesystem will be skipped, unless the `fsck_policy` is `warn` or `--force` is set
+    /// With `skip_mount` the filesystem is neither mounted nor unmounted, but needs to be mounted already.
+    fn check_mount_and_back_up(&mut self, run_summary: &mut RunSummary) -> Result<(), BackupError> {
+        match (self.skip_mount, self.dst_filesystem.is_mounted()) {
+            (false, true) => self.dst_filesystem.unmount()?,
+            (true, false) => {
+                return Err(BackupError::NotMounted(
+                    self.dst_filesystem.device_path.clone(),
+                ))
+            }
+            _ => (),
         }
 
-        match self.dst_filesystem.validate_fsck_or_skip() {
-            Ok(()) => {
-                if !self.skip_mount {
-                    self.dst_filesystem.mount()?;
-                }
+        let fsck_result = match self.dst_filesystem.validate_fsck_or_skip() {
+            Err(e)
+                if self.dst_filesystem.fsck_policy == FsckPolicy::Warn
+                    || self.backup_args.force =>
+            {
+                warn!(
+                    "{}, backing up to filesystem {} anyway",
+                    e, self.dst_filesystem.device_path
+                );
+                self.fsck_warning = Some(e.to_string());
+                Ok(())
+            }
+            fsck_result => fsck_result,
+        };
 
-                for backup_device in &self.backup_devices {
-                    if let Err(err) =
-                        Backup::new(&self.dst_filesystem, backup_device, self.backup_args).run()
-                    {
-                        error!("Error performing backup: {}", err);
-                    }
+        match fsck_result {
+            Ok(()) => {
+                if self.skip_mount {
+                    self.back_up_devices(run_summary);
+                    return Ok(());
                 }
 
-                if !self.skip_mount {
-                    self.dst_filesystem.unmount()?;
-                }
-                Ok(())
+                self.dst_filesystem.mount()?;
+                always_unmount(
+                    self,
+                    |backups| {
+                        backups.back_up_devices(run_summary);
+                        Ok(())
+                    },
+                    |backups| backups.dst_filesystem.unmount(),
+                )
             }
             Err(e) => {
                 error!(
-                    "{}, skipping backups for filesystem {}",
+                    "{}, skipping backups for filesystem {}, pass --force to back up anyway",
                     e, self.dst_filesystem.device_path
                 );
+                self.push_not_run(run_summary, BackupStatus::Skipped, &e.to_string());
                 Ok(())
             }
         }
     }
+
+    /// Runs the backups of all devices, sequentially or with `--jobs` in parallel,
+    /// and adds their outcomes to `run_summary`.
+    fn back_up_devices(&self, run_summary: &mut RunSummary) {
+        if self.backup_args.jobs > 1 {
+            for (backup, result, outcome) in self.back_up_devices_in_parallel() {
+                self.record(&backup, &result, outcome, run_summary);
+            }
+        } else {
+            for backup_device in &self.backup_devices {
+                let (backup, result, outcome) = self.back_up_device(backup_device);
+                self.record(&backup, &result, outcome, run_summary);
+            }
+        }
+    }
+
+    /// Runs the backup of a single device, returning it with its result and outcome.
+    fn back_up_device<'b>(
+        &'b self,
+        backup_device: &'b Device,
+    ) -> (Backup<'b>, Result<(), BackupError>, BackupOutcome) {
+        let backup = Backup::new(
+            &self.dst_filesystem,
+            &self.dd_settings,
+            backup_device,
+            self.backup_args,
+        );
+        let time_before_backup = Local::now();
+        let result = backup.run();
+        let outcome = self.outcome(&backup, &result, time_before_backup);
+        (backup, result, outcome)
+    }
+
+    /// Runs the device backups on up to `--jobs` threads, each taking the next pending device.
+    /// A failing backup doesn't stop the others.
+    ///
+    /// # Returns
+    ///
+    /// The backups with their results and outcomes, in the order of the devices.
+    fn back_up_devices_in_parallel(
+        &self,
+    ) -> Vec<(Backup<'_>, Result<(), BackupError>, BackupOutcome)> {
+        let next_index = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::new());
+        let jobs = (self.backup_args.jobs as usize).min(self.backup_devices.len());
+
+        thread::scope(|scope| {
+            for _ in 0..jobs {
+                scope.spawn(|| loop {
+                    let index = next_index.fetch_add(1, Ordering::SeqCst);
+                    let Some(backup_device) = self.backup_devices.get(index) else {
+                        break;
+                    };
+                    let backup_result = self.back_up_device(backup_device);
+                    if let Ok(mut results) = results.lock() {
+                        results.push((index, backup_result));
+                    }
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results.sort_by_key(|(index, _)| *index);
+        results
+            .into_iter()
+            .map(|(_, backup_result)| backup_result)
+            .collect()
+    }
+
+    /// Adds the `outcome` of a device backup to `run_summary`, logs its error and records it
+    /// in the catalog and the history.
+    fn record(
+        &self,
+        backup: &Backup,
+        result: &Result<(), BackupError>,
+        outcome: BackupOutcome,
+        run_summary: &mut RunSummary,
+    ) {
+        run_summary.push(outcome);
+        match result {
+            Ok(()) => {
+                if let Err(err) = self.record_in_catalog(backup) {
+                    error!("Error recording backup in catalog: {}", err);
+                }
+            }
+            Err(err @ BackupError::SourceUnchanged { .. }) => info!("{}", err),
+            Err(err) => {
+                error!("Error performing backup: {}", err);
+                // a backup present since an earlier run of today doesn't fail reruns
+                if !matches!(err, BackupError::BackupPresent(_)) {
+                    run_summary.exit_code.get_or_insert(err.backup_exit_code());
+                }
+            }
+        }
+        if let Err(err) = self.append_to_history(backup, result) {
+            error!("Error appending backup to history: {}", err);
+        }
+    }
+
+    /// Adds a failed outcome with the `error` for every device backup to `run_summary`,
+    /// and sets the exit code of the run from it, so the run fails even if no device is attached.
+    fn push_failed(&self, run_summary: &mut RunSummary, error: &BackupError) {
+        self.push_not_run(run_summary, BackupStatus::Failed, &error.to_string());
+        run_summary
+            .exit_code
+            .get_or_insert(error.backup_exit_code());
+    }
+
+    /// Adds an outcome with the `status` and the reason `message` for every device backup,
+    /// which isn't run, to `run_summary`.
+    fn push_not_run(&self, run_summary: &mut RunSummary, status: BackupStatus, message: &str) {
+        for backup_device in &self.backup_devices {
+            let backup = Backup::new(
+                &self.dst_filesystem,
+                &self.dd_settings,
+                backup_device,
+                self.backup_args,
+            );
+            run_summary.push(BackupOutcome {
+                status,
+                duration: None,
+                message: Some(message.to_string()),
+                ..self.outcome(&backup, &Ok(()), Local::now())
+            });
+        }
+    }
+
+    /// Runs a hook `command` of the destination filesystem with `sh -c`, if one is configured.
+    ///
+    /// The UUID and device path of the destination filesystem are passed as the env vars
+    /// `DD_BACKUP_UUID` and `DD_BACKUP_DEVICE`. The placeholders `{uuid}`, `{device_path}`
+    /// and `{mountpath}` in the command are replaced with the shell-quoted values
+    /// of the destination filesystem, never of a source device.
+    fn run_hook(&self, command: &Option<String>, hook_name: &str) -> Result<(), String> {
+        let command = match command {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+
+        let uuid = self
+            .dst_filesystem
+            .blockdevice
+            .uuid
+            .clone()
+            .unwrap_or_default();
+        let mountpath = self
+            .dst_filesystem
+            .blockdevice
+            .mountpoint
+            .as_ref()
+            .unwrap_or(&self.dst_filesystem.mountpath);
+        let command = expand_shell_placeholders(
+            command,
+            &[
+                ("{uuid}", &uuid),
+                ("{device_path}", &self.dst_filesystem.device_path),
+                ("{mountpath}", mountpath),
+            ],
+        );
+        let uuid_env = format!("DD_BACKUP_UUID={}", uuid);
+        let device_env = format!("DD_BACKUP_DEVICE={}", self.dst_filesystem.device_path);
+        command_output(
+            vec!["env", &uuid_env, &device_env, "sh", "-c", &command],
+            &format!("run {} hook", hook_name),
+            Some(false),
+        )
+        .map_err(|e| format!("Error running {} hook: {}", hook_name, e))?;
+        info!(
+            "Ran {} hook of filesystem {}",
+            hook_name, self.dst_filesystem.device_path
+        );
+        Ok(())
+    }
+
+    /// Describes the outcome of a device backup, which was started at `started_at`.
+    fn outcome(
+        &self,
+        backup: &Backup,
+        result: &Result<(), BackupError>,
+        started_at: DateTime<Local>,
+    ) -> BackupOutcome {
+        let image_path = backup.backup_file_path();
+        let (status, size) = match result {
+            Ok(()) if self.backup_args.dry_run || print_commands_only() => (
+                BackupStatus::DryRun,
+                Some(backup.backup_device.total_size()),
+            ),
+            Ok(()) => (
+                BackupStatus::Success,
+                fs::metadata(&image_path)
+                    .map(|metadata| metadata.len())
+                    .ok(),
+            ),
+            Err(BackupError::SourceUnchanged { .. }) => (BackupStatus::Skipped, None),
+            Err(_) => (BackupStatus::Failed, None),
+        };
+        BackupOutcome {
+            device_path: backup.backup_device.device_path.clone(),
+            serial: backup.backup_device.blockdevice.serial.clone(),
+            destination_uuid: self.dst_filesystem.blockdevice.uuid.clone(),
+            image_path,
+            size,
+            deleted_oldest: backup.deleted_oldest.get(),
+            duration: Some(Local::now() - started_at),
+            status,
+            message: result
+                .as_ref()
+                .err()
+                .map(BackupError::to_string)
+                .or(self.fsck_warning.clone())
+                .or((backup.skipped_blocks.get() > 0).then(|| {
+                    format!(
+                        "dd skipped {} unreadable blocks",
+                        backup.skipped_blocks.get()
+                    )
+                })),
+        }
+    }
+
+    /// Records a completed backup in the catalog, if a catalog is configured and it isn't a dry run.
+    fn record_in_catalog(&self, backup: &Backup) -> Result<(), String> {
+        let catalog_path = match &self.catalog_path {
+            Some(catalog_path) if !self.backup_args.dry_run && !print_commands_only() => {
+                catalog_path
+            }
+            _ => return Ok(()),
+        };
+
+        let image_path = backup.backup_file_path();
+        let entry = CatalogEntry {
+            timestamp: Local::now().to_rfc3339(),
+            serial: backup.backup_device.blockdevice.serial.clone(),
+            model: backup.backup_device.blockdevice.model.clone(),
+            destination_uuid: self
+                .dst_filesystem
+                .blockdevice
+                .uuid
+                .clone()
+                .unwrap_or_default(),
+            size: fs::metadata(&image_path)
+                .map(|metadata| metadata.len())
+                .ok(),
+            image_path,
+            checksum: backup.read_checksum(),
+            status: "success".to_string(),
+        };
+        Catalog::open(catalog_path)?.insert(&entry)?;
+        debug!("Recorded backup in catalog {}", catalog_path);
+        Ok(())
+    }
+
+    /// Appends the outcome of a backup to the history file, if one is configured and it isn't a dry run.
+    fn append_to_history(
+        &self,
+        backup: &Backup,
+        result: &Result<(), BackupError>,
+    ) -> Result<(), String> {
+        let history_file = match &self.history_file {
+            Some(history_file) if !self.backup_args.dry_run && !print_commands_only() => {
+                history_file
+            }
+            _ => return Ok(()),
+        };
+
+        let image_path = backup.backup_file_path();
+        let record = HistoryRecord {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: Local::now().to_rfc3339(),
+            serial: backup.backup_device.blockdevice.serial.clone(),
+            model: backup.backup_device.blockdevice.model.clone(),
+            device_path: backup.backup_device.device_path.clone(),
+            destination_uuid: self.dst_filesystem.blockdevice.uuid.clone(),
+            size: match result {
+                Ok(()) => fs::metadata(&image_path)
+                    .map(|metadata| metadata.len())
+                    .ok(),
+                Err(_) => None,
+            },
+            image_path,
+            status: match result {
+                Ok(()) => "success".to_string(),
+                Err(BackupError::SourceUnchanged { .. }) => "skipped".to_string(),
+                Err(_) => "failure".to_string(),
+            },
+            error: result.as_ref().err().map(BackupError::to_string),
+        };
+        record.append(history_file)
+    }
+}
+
+/// Runs `back_up` on the mounted `state` and afterwards always `unmount`, also if `back_up` fails or panics,
+/// so the destination isn't left mounted.
+///
+/// A failing unmount is only logged if `back_up` failed or panicked, whose error or panic takes precedence.
+fn always_unmount<S, T, E: Display>(
+    state: &mut S,
+    back_up: impl FnOnce(&S) -> Result<T, E>,
+    unmount: impl FnOnce(&mut S) -> Result<(), E>,
+) -> Result<T, E> {
+    let back_up_result = panic::catch_unwind(AssertUnwindSafe(|| back_up(state)));
+    let unmount_result = unmount(state);
+    match (back_up_result, unmount_result) {
+        (Ok(Ok(value)), unmount_result) => unmount_result.map(|_| value),
+        (Ok(Err(e)), Err(unmount_error)) => {
+            error!(
+                "Failed to unmount after the backups failed: {}",
+                unmount_error
+            );
+            Err(e)
+        }
+        (Ok(Err(e)), Ok(())) => Err(e),
+        (Err(panic), unmount_result) => {
+            if let Err(unmount_error) = unmount_result {
+                error!(
+                    "Failed to unmount after the backups panicked: {}",
+                    unmount_error
+                );
+            }
+            panic::resume_unwind(panic)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::error::EXIT_BACKUP_FAILED;
+
+    #[test]
+    fn test_always_unmount() {
+        let mut unmounted = false;
+        let result: Result<(), String> = always_unmount(
+            &mut unmounted,
+            |_| Err("Backup failed".to_string()),
+            |unmounted| {
+                *unmounted = true;
+                Err("Target is busy".to_string())
+            },
+        );
+        assert_eq!(result, Err("Backup failed".to_string()));
+        assert!(unmounted);
+
+        let mut unmounted = false;
+        let result = always_unmount(
+            &mut unmounted,
+            |_| Ok(1),
+            |_| Err("Target is busy".to_string()),
+        );
+        assert_eq!(result, Err("Target is busy".to_string()));
+
+        let mut unmounted = false;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            always_unmount(
+                &mut unmounted,
+                |_| -> Result<(), String> { panic!("Backup panicked mid-loop") },
+                |unmounted| {
+                    *unmounted = true;
+                    Ok(())
+                },
+            )
+        }));
+        assert!(result.is_err());
+        assert!(unmounted);
+    }
+
+    /// Returns the unmounted source device `sdz` with serial `SRC1` and the unmounted destination
+    /// filesystem `sdy1` with `uuid`.
+    fn generate_test_lsblk(uuid: &str) -> Lsblk {
+        serde_json::from_value(serde_json::json!({
+            "available_devices": [
+                {"name": "sdz", "model": "Disk", "serial": "SRC1", "uuid": null, "mountpoint": null, "size": 1000}
+            ],
+            "available_filesystems": [
+                {"name": "sdy1", "model": null, "serial": null, "uuid": uuid, "mountpoint": null, "size": 1000}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run_fails_backups_if_pre_mount_fails() {
+        let uuid = "dd-backup-test-pre-mount";
+        let lsblk = generate_test_lsblk(uuid);
+        let backup_args = BackupArgs {
+            jobs: 1,
+            ..Default::default()
+        };
+
+        // `pre_command` is an alias of `pre_mount`, failing the backups the same way
+        for hook_field in ["pre_mount", "pre_command"] {
+            let config: Config = serde_json::from_value(serde_json::json!({
+                "backups": [{"uuid": uuid, "backup_devices": [{"serial": "SRC1"}], hook_field: "exit 3"}]
+            }))
+            .unwrap();
+
+            let backups = Backups::new(
+                &config.backups[0].destination_id(),
+                &config.backups[0],
+                &lsblk,
+                &backup_args,
+                &config,
+            )
+            .unwrap()
+            .unwrap();
+            let mut run_summary = RunSummary::default();
+            assert!(backups.run(&mut run_summary).is_ok());
+            assert_eq!(run_summary.exit_code, Some(EXIT_BACKUP_FAILED));
+            assert_eq!(run_summary.outcomes.len(), 1);
+            assert_eq!(run_summary.outcomes[0].status, BackupStatus::Failed);
+            assert!(run_summary.outcomes[0]
+                .message
+                .as_ref()
+                .unwrap()
+                .starts_with("Error running pre_mount hook"));
+        }
+    }
+
+    #[test]
+    fn test_run_keeps_error_kind() {
+        let uuid = "dd-backup-test-not-mounted";
+        let lsblk = generate_test_lsblk(uuid);
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "backups": [{"uuid": uuid, "backup_devices": [{"serial": "SRC1"}], "skip_mount": true}]
+        }))
+        .unwrap();
+        let backup_args = BackupArgs {
+            jobs: 1,
+            ..Default::default()
+        };
+
+        let backups = Backups::new(
+            &config.backups[0].destination_id(),
+            &config.backups[0],
+            &lsblk,
+            &backup_args,
+            &config,
+        )
+        .unwrap()
+        .unwrap();
+        let mut run_summary = RunSummary::default();
+        assert!(matches!(
+            backups.run(&mut run_summary),
+            Err(BackupError::NotMounted(device_path)) if device_path == "/dev/sdy1"
+        ));
+    }
 }
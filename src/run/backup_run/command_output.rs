@@ -1,7 +1,46 @@
-use std::process::{Command, Output, Stdio};
+use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::run::error::BackupError;
+
+/// The exit code of the `timeout` command if it stopped the command with `SIGTERM`.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// The exit code of the `timeout` command if it had to kill the command with `SIGKILL`.
+const TIMEOUT_KILLED_EXIT_CODE: i32 = 137;
+
+/// The seconds `timeout` waits after `SIGTERM` before it kills a command with `SIGKILL`.
+const TIMEOUT_KILL_AFTER_SECS: u64 = 5;
+
+/// Whether every executed command is logged at `debug` instead of `trace` level.
+static VERBOSE_COMMAND: AtomicBool = AtomicBool::new(false);
+
+/// Whether commands are only printed to stdout as a plan instead of being executed.
+static PRINT_COMMANDS_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Enables logging of every executed command at `debug` level, see `--verbose-command`.
+pub fn set_verbose_command(verbose_command: bool) {
+    VERBOSE_COMMAND.store(verbose_command, Ordering::Relaxed);
+}
+
+/// Enables printing commands instead of executing them, see `--print-commands-only`.
+pub fn set_print_commands_only(print_commands_only: bool) {
+    PRINT_COMMANDS_ONLY.store(print_commands_only, Ordering::Relaxed);
+}
+
+/// Returns whether commands are only printed, in which case no other side effects may happen either.
+pub fn print_commands_only() -> bool {
+    PRINT_COMMANDS_ONLY.load(Ordering::Relaxed)
+}
 
 /// Executes a command and captures its output.
 /// Command output is still printed to stdout and stderr.
+/// With `--print-commands-only` the command is printed to stdout instead and an empty,
+/// successful output is returned.
 ///
 /// # Arguments
 ///
@@ -17,7 +56,216 @@ pub fn command_output(
     command_parts: Vec<&str>,
     description: &str,
     is_sudo_needed: Option<bool>,
-) -> Result<Output, String> {
+) -> Result<Output, BackupError> {
+    command_output_with_timeout(command_parts, description, is_sudo_needed, None)
+}
+
+/// Executes a command like `command_output`, killing it if it runs longer than `timeout`.
+///
+/// The command is run by `timeout`, as part of the sudo command, so a command running as root
+/// is stopped as well, with `SIGTERM` and `TIMEOUT_KILL_AFTER_SECS` later with `SIGKILL`.
+/// Its output is read while it runs, so a command writing more than a pipe buffer doesn't block.
+///
+/// # Arguments
+///
+/// * `command_parts` - The parts of the command.
+/// * `description` - The description of the command.
+/// * `is_sudo_needed` - Indicates whether sudo should be used for the command (if available).
+/// * `timeout` - The maximum duration of the command, `None` waits indefinitely.
+///
+/// # Returns
+///
+/// * `Ok(output)` if the command executes successfully in time and captures the output.
+/// * `Err` with an error message if the command encounters an error or times out.
+pub fn command_output_with_timeout(
+    command_parts: Vec<&str>,
+    description: &str,
+    is_sudo_needed: Option<bool>,
+    timeout: Option<Duration>,
+) -> Result<Output, BackupError> {
+    let command = command_parts.join(" ");
+    let timeout_parts = timeout.map(|timeout| {
+        [
+            "timeout".to_string(),
+            format!("--kill-after={}", TIMEOUT_KILL_AFTER_SECS),
+            timeout.as_secs_f64().to_string(),
+        ]
+    });
+    let command_parts = timeout_parts
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .chain(command_parts)
+        .collect();
+    let command_parts = prepare_command_parts(command_parts, description, is_sudo_needed);
+    if print_commands_only() {
+        return Ok(printed_command_output(&command_parts));
+    }
+    let output = spawn_command(&command_parts, Stdio::inherit(), Stdio::inherit())?
+        .wait_with_output()
+        .map_err(BackupError::io(wait_context(&command_parts)))?;
+    match (output.status.code(), timeout) {
+        (_, _) if output.status.success() => Ok(output),
+        (Some(TIMEOUT_EXIT_CODE | TIMEOUT_KILLED_EXIT_CODE), Some(timeout)) => {
+            Err(BackupError::CommandTimedOut { command, timeout })
+        }
+        _ => Err(command_failed(
+            &command_parts,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )),
+    }
+}
+
+/// Executes a command like `command_output`, writing `input` to its stdin.
+/// Its stderr is captured as well, to be part of the error message.
+///
+/// Secrets like tokens can be passed as `input`, since unlike the command they are neither logged
+/// nor visible in the process list.
+///
+/// # Arguments
+///
+/// * `command_parts` - The parts of the command.
+/// * `description` - The description of the command.
+/// * `is_sudo_needed` - Indicates whether sudo should be used for the command (if available).
+/// * `input` - The bytes written to stdin, which is closed afterwards.
+///
+/// # Returns
+///
+/// * `Ok(output)` if the command executes successfully and captures the output.
+/// * `Err` with an error message if the command encounters an error.
+pub fn command_output_with_input(
+    command_parts: Vec<&str>,
+    description: &str,
+    is_sudo_needed: Option<bool>,
+    input: &[u8],
+) -> Result<Output, BackupError> {
+    let command_parts = prepare_command_parts(command_parts, description, is_sudo_needed);
+    if print_commands_only() {
+        return Ok(printed_command_output(&command_parts));
+    }
+    let mut child = spawn_command(&command_parts, Stdio::piped(), Stdio::piped())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a command exiting early closes its stdin, its exit status tells what went wrong
+        let _ = stdin.write_all(input);
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(BackupError::io(wait_context(&command_parts)))?;
+    match output.status.success() {
+        true => Ok(output),
+        false => Err(command_failed(
+            &command_parts,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )),
+    }
+}
+
+/// Executes a command like `command_output`, capturing its stderr line by line.
+///
+/// Lines are split at newlines and carriage returns, so each update of a progress output,
+/// like the one of `dd status=progress`, is passed to `on_stderr_line` on its own.
+/// Lines for which it returns `false` are collected as stderr of the output, the others are dropped.
+///
+/// # Arguments
+///
+/// * `command_parts` - The parts of the command.
+/// * `description` - The description of the command.
+/// * `is_sudo_needed` - Indicates whether sudo should be used for the command (if available).
+/// * `on_stderr_line` - Handles a line written to stderr, returns whether it was consumed.
+///
+/// # Returns
+///
+/// * `Ok(output)` if the command executes successfully and captures the output.
+/// * `Err` with an error message if the command encounters an error.
+pub fn command_output_with_stderr_lines(
+    command_parts: Vec<&str>,
+    description: &str,
+    is_sudo_needed: Option<bool>,
+    mut on_stderr_line: impl FnMut(&str) -> bool,
+) -> Result<Output, BackupError> {
+    let command_parts = prepare_command_parts(command_parts, description, is_sudo_needed);
+    if print_commands_only() {
+        return Ok(printed_command_output(&command_parts));
+    }
+
+    let mut child = spawn_command(&command_parts, Stdio::inherit(), Stdio::piped())?;
+
+    // stdout is read concurrently, so a full pipe can't block the command
+    let mut stdout = child.stdout.take().ok_or("Failed to capture stdout")?;
+    let stdout_reader = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        stdout.read_to_end(&mut buffer).map(|_| buffer)
+    });
+
+    let mut stderr = child.stderr.take().ok_or("Failed to capture stderr")?;
+    let mut kept_lines = Vec::new();
+    let mut line = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut handle_line = |line: &mut Vec<u8>| {
+        if !line.is_empty() {
+            let text = String::from_utf8_lossy(line).to_string();
+            if !on_stderr_line(&text) {
+                kept_lines.push(text);
+            }
+            line.clear();
+        }
+    };
+    loop {
+        let read = stderr
+            .read(&mut buffer)
+            .map_err(BackupError::io(wait_context(&command_parts)))?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buffer[..read] {
+            match byte {
+                b'\n' | b'\r' => handle_line(&mut line),
+                _ => line.push(byte),
+            }
+        }
+    }
+    handle_line(&mut line);
+
+    let status = child
+        .wait()
+        .map_err(BackupError::io(wait_context(&command_parts)))?;
+    let stdout = stdout_reader
+        .join()
+        .map_err(|_| "Failed to read stdout")?
+        .map_err(BackupError::io(wait_context(&command_parts)))?;
+    let stderr = kept_lines.join("\n");
+    match status.success() {
+        true => Ok(Output {
+            status,
+            stdout,
+            stderr: stderr.into_bytes(),
+        }),
+        false => Err(command_failed(&command_parts, status, stderr)),
+    }
+}
+
+/// Returns the error of a command which exited unsuccessfully with `status`.
+fn command_failed(command_parts: &[&str], status: ExitStatus, stderr: String) -> BackupError {
+    BackupError::CommandFailed {
+        command: command_parts.join(" "),
+        status,
+        stderr,
+    }
+}
+
+/// Returns the context of an error waiting for or reading from a running command.
+fn wait_context(command_parts: &[&str]) -> String {
+    format!("Failed to wait for {}", command_parts.join(" "))
+}
+
+/// Prepends sudo to the command parts if needed and logs the command.
+fn prepare_command_parts<'a>(
+    command_parts: Vec<&'a str>,
+    description: &str,
+    is_sudo_needed: Option<bool>,
+) -> Vec<&'a str> {
     let command_parts = {
         if is_sudo_needed.unwrap_or(false) {
             append_sudo_if_available(command_parts, Some(description))
@@ -26,24 +274,55 @@ pub fn command_output(
         }
     };
 
-    trace!("Command: {}", command_parts.join(" "));
-    match Command::new(command_parts[0])
+    if VERBOSE_COMMAND.load(Ordering::Relaxed) {
+        debug!("Command: {:?}", command_parts);
+    } else {
+        trace!("Command: {}", command_parts.join(" "));
+    }
+    command_parts
+}
+
+/// Starts the command with its stdout piped, the only place commands are started.
+///
+/// With `--print-commands-only` no command may run, the callers print it with `printed_command_output` instead.
+fn spawn_command(
+    command_parts: &[&str],
+    stdin: Stdio,
+    stderr: Stdio,
+) -> Result<Child, BackupError> {
+    if print_commands_only() {
+        return Err(BackupError::Other(format!(
+            "Not running {} with --print-commands-only",
+            command_parts.join(" ")
+        )));
+    }
+    Command::new(command_parts[0])
         .args(&command_parts[1..])
+        .stdin(stdin)
         .stdout(Stdio::piped())
+        .stderr(stderr)
         .spawn()
-    {
-        Ok(child) => {
-            let output = child.wait_with_output().map_err(|e| e.to_string())?;
-            match output.status.success() {
-                true => Ok(output),
-                false => Err(format!(
-                    "Error running {}: {}",
-                    &command_parts.join(" "),
-                    String::from_utf8_lossy(&output.stderr)
-                )),
-            }
-        }
-        Err(err) => Err(format!("{}: {}", err, command_parts.join(" "))),
+        .map_err(BackupError::io(command_parts.join(" ")))
+}
+
+/// Prints the command for `--print-commands-only` and returns an empty, successful output.
+fn printed_command_output(command_parts: &[&str]) -> Output {
+    println!("{}", command_parts.join(" "));
+    Output {
+        status: ExitStatus::from_raw(0),
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    }
+}
+
+/// Describes how a command exited, distinguishing an exit code from a termination by signal.
+///
+/// A process killed by a signal (e.g. by the OOM killer) has no exit code.
+pub fn exit_status_description(status: &ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exit code {}", code),
+        (None, Some(signal)) => format!("killed by signal {}", signal),
+        (None, None) => "unknown exit status".to_string(),
     }
 }
 
@@ -53,7 +332,8 @@ fn append_sudo_if_available<'a>(
 ) -> Vec<&'a str> {
     let mut updated_command_parts = Vec::new();
 
-    if is_sudo_available() {
+    // the printed commands are run by hand, so those needing root get sudo without checking for it
+    if print_commands_only() || is_sudo_available() {
         updated_command_parts.push("sudo");
         let sudo_message = "Sudo is needed";
         match description {
@@ -67,5 +347,116 @@ fn append_sudo_if_available<'a>(
 }
 
 fn is_sudo_available() -> bool {
-    Command::new("sudo").arg("--version").output().is_ok()
+    spawn_command(&["sudo", "--version"], Stdio::null(), Stdio::null())
+        .is_ok_and(|child| child.wait_with_output().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status_description() {
+        assert_eq!(
+            exit_status_description(&ExitStatus::from_raw(1 << 8)),
+            "exit code 1"
+        );
+        assert_eq!(
+            exit_status_description(&ExitStatus::from_raw(9)),
+            "killed by signal 9"
+        );
+    }
+
+    #[test]
+    fn test_command_output_exit_code() {
+        let result = command_output(vec!["sh", "-c", "exit 3"], "exit with code 3", None);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error running sh -c exit 3 (exit code 3): "
+        );
+    }
+
+    #[test]
+    fn test_command_output_with_timeout() {
+        let result = command_output_with_timeout(
+            vec!["sleep", "5"],
+            "sleep",
+            None,
+            Some(Duration::from_millis(200)),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Timed out after 200ms running sleep 5"
+        );
+
+        let result = command_output_with_timeout(
+            vec!["echo", "hi"],
+            "echo",
+            None,
+            Some(Duration::from_secs(5)),
+        );
+        assert_eq!(result.unwrap().stdout, b"hi\n");
+
+        // more output than fits into a pipe buffer
+        let result = command_output_with_timeout(
+            vec!["head", "-c", "200000", "/dev/zero"],
+            "write much output",
+            None,
+            Some(Duration::from_secs(5)),
+        );
+        assert_eq!(result.unwrap().stdout.len(), 200000);
+    }
+
+    #[test]
+    fn test_command_output_with_input() {
+        let output = command_output_with_input(vec!["cat"], "echo input", None, b"secret");
+        assert_eq!(output.unwrap().stdout, b"secret");
+
+        let result = command_output_with_input(
+            vec!["sh", "-c", "echo failed >&2; exit 1"],
+            "fail",
+            None,
+            b"secret",
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error running sh -c echo failed >&2; exit 1 (exit code 1): failed\n"
+        );
+    }
+
+    #[test]
+    fn test_command_output_with_stderr_lines() {
+        let mut consumed = Vec::new();
+        let output = command_output_with_stderr_lines(
+            vec![
+                "sh",
+                "-c",
+                "printf '1 copied\\r2 copied\\rdone\\n' >&2; echo out",
+            ],
+            "write to stderr",
+            None,
+            |line| {
+                let is_progress = line.ends_with("copied");
+                if is_progress {
+                    consumed.push(line.to_string());
+                }
+                is_progress
+            },
+        )
+        .unwrap();
+        assert_eq!(consumed, vec!["1 copied", "2 copied"]);
+        assert_eq!(output.stderr, b"done");
+        assert_eq!(output.stdout, b"out\n");
+
+        let result = command_output_with_stderr_lines(
+            vec!["sh", "-c", "echo failed >&2; exit 1"],
+            "fail",
+            None,
+            |_| false,
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error running sh -c echo failed >&2; exit 1 (exit code 1): failed"
+        );
+    }
 }
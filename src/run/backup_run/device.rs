@@ -3,11 +3,14 @@ use std::{
     io::{BufRead, BufReader},
 };
 
-use crate::run::{config::BackupDevice, utils::convert_to_byte_size};
+use crate::run::{
+    config::{BackupDevice, BackupMethod, DeviceMatch},
+    error::BackupError,
+};
 
 use super::lsblk::BlockDevice;
 
-/// Represents a device identified by its serial number.
+/// Represents a device or partition identified by its serial number, WWN, PARTUUID, label or match patterns.
 #[derive(Debug)]
 pub struct Device {
     /// The underlying block device information.
@@ -20,105 +23,284 @@ pub struct Device {
     pub destination_path: String,
     /// The number of copies to be kept for this device.
     pub copies: Option<usize>,
+    /// The maximum age of backups of this device in days.
+    pub max_age_days: Option<u64>,
+    /// The method used to back up the device.
+    pub method: BackupMethod,
+    /// Whether the written image is evicted from the page cache.
+    pub drop_cache: bool,
+    /// The filesystem `rsync` copies, the device itself if it holds one, otherwise its only partition with one.
+    pub filesystem: Option<BlockDevice>,
 }
 
+/// The deviation from the `expected_size` of a device in percent, up to which its size still matches.
+const EXPECTED_SIZE_TOLERANCE_PERCENT: u64 = 1;
+
 impl Device {
-    /// Creates a new `Device` instance with the specified serial number and optional name.
+    /// Creates new `Device` instances for the configured backup device.
     ///
-    /// It validates the uniqueness of the serial number among the available devices
-    /// and returns `Some(Device)` if a unique match is found, or `None` otherwise.
-    /// Additionally, it checks if the device is currently mounted and filters out mounted devices.
+    /// A device configured by serial number, WWN, PARTUUID or label is validated to be unique among the available devices.
+    /// A device configured by `match` may resolve to several devices, matching none is only a warning.
+    /// Additionally, it checks if the device is currently mounted and filters out mounted devices backed up
+    /// with `dd`, as well as devices whose size doesn't match their `expected_size`. `rsync` copies the files
+    /// of a mounted filesystem in place, so its devices may be mounted.
     ///
     /// # Arguments
     ///
-    /// * `serial` - The serial number of the device.
-    /// * `name` - The optional name of the device.
+    /// * `backup_device` - The configured device, identified by serial number, WWN, PARTUUID, label or match patterns.
     /// * `available_devices` - The list of available block devices.
+    /// * `available_filesystems` - The list of available filesystems, holding the partitions of the devices.
     /// * `destination_path` - The optional destination path for the device from the configuration.
+    /// * `strict` - Whether a size mismatch fails instead of skipping the device.
     ///
     /// # Returns
     ///
-    /// - `Ok(Some(Device))`: If a unique device is found matching the serial number and if it isn't mounted.
-    /// - `Ok(None)`: If no device is found matching the serial number or all matching devices are mounted.
-    /// - `Err(String)`: If the serial number is not unique among the available devices.
+    /// - `Ok(Vec<Device>)`: The found devices which aren't mounted, unless backed up with `rsync`,
+    ///   and have the expected size, may be empty.
+    /// - `Err(BackupError)`: If an error occurs while checking the mount state, or a size mismatches with `strict`.
     pub fn new(
         backup_device: &BackupDevice,
         available_devices: &[BlockDevice],
+        available_filesystems: &[BlockDevice],
         destination_path: String,
-    ) -> Result<Option<Device>, String> {
-        match Self::validate_serial(&backup_device.serial, available_devices) {
-            Ok(blockdevice) => {
-                if !Self::is_device_mounted(&format!("/dev/{}", &blockdevice.name))? {
-                    Ok(Some(Device {
-                        blockdevice: blockdevice.clone(),
-                        device_path: format!("/dev/{}", &blockdevice.name),
-                        name: backup_device.name.clone(),
-                        copies: backup_device.copies,
-                        destination_path,
-                    }))
-                } else {
-                    Ok(None)
+        strict: bool,
+    ) -> Result<Vec<Device>, BackupError> {
+        let is_mount_allowed = backup_device.method == Some(BackupMethod::Rsync);
+        let mut devices = Vec::new();
+        for blockdevice in Self::resolve_blockdevices(backup_device, available_devices) {
+            let device_path = format!("/dev/{}", &blockdevice.name);
+            if !is_mount_allowed && Self::is_device_mounted(&device_path)? {
+                error!("Device {} is mounted, skipping it", device_path);
+            } else if let Some(size_mismatch) = Self::size_mismatch(backup_device, blockdevice)? {
+                if strict {
+                    return Err(size_mismatch);
                 }
+                warn!("{}, skipping it", size_mismatch);
+            } else {
+                devices.push(Self::from_blockdevice(
+                    backup_device,
+                    blockdevice,
+                    available_filesystems,
+                    destination_path.clone(),
+                ));
             }
+        }
+        Ok(devices)
+    }
+
+    /// Creates the `Device` of the configured device resolved to `blockdevice`, without checking its state.
+    pub fn from_blockdevice(
+        backup_device: &BackupDevice,
+        blockdevice: &BlockDevice,
+        available_filesystems: &[BlockDevice],
+        destination_path: String,
+    ) -> Device {
+        Device {
+            blockdevice: blockdevice.clone(),
+            device_path: format!("/dev/{}", &blockdevice.name),
+            name: backup_device.name.clone(),
+            copies: backup_device.copies,
+            max_age_days: backup_device.max_age_days,
+            method: backup_device.method.unwrap_or_default(),
+            drop_cache: backup_device.drop_cache.unwrap_or(false),
+            filesystem: Self::filesystem_of(blockdevice, available_filesystems).cloned(),
+            destination_path,
+        }
+    }
+
+    /// Returns the filesystem on `blockdevice`, which is the device itself if it has a UUID,
+    /// like a partition or a disk formatted without partition table, otherwise its only partition
+    /// with a filesystem. A disk with several of them has none, its partitions need to be configured.
+    pub fn filesystem_of<'a>(
+        blockdevice: &'a BlockDevice,
+        available_filesystems: &'a [BlockDevice],
+    ) -> Option<&'a BlockDevice> {
+        if blockdevice.uuid.is_some() {
+            return Some(blockdevice);
+        }
+        let mut partitions = available_filesystems
+            .iter()
+            .filter(|filesystem| filesystem.pkname.as_deref() == Some(blockdevice.name.as_str()));
+        match (partitions.next(), partitions.next()) {
+            (Some(partition), None) => Some(partition),
+            _ => None,
+        }
+    }
+
+    /// Returns the mismatch of the size of `blockdevice` with the `expected_size` of the configured device,
+    /// if it differs by more than `EXPECTED_SIZE_TOLERANCE_PERCENT`.
+    pub fn size_mismatch(
+        backup_device: &BackupDevice,
+        blockdevice: &BlockDevice,
+    ) -> Result<Option<BackupError>, BackupError> {
+        Ok(backup_device
+            .expected_size_in_bytes()?
+            .filter(|expected_size| !is_within_tolerance(blockdevice.size, *expected_size))
+            .map(|expected_size| BackupError::SizeMismatch {
+                device: format!("/dev/{}", blockdevice.name),
+                size: blockdevice.size,
+                expected: expected_size,
+            }))
+    }
+
+    /// Returns the available block devices the configured device resolves to, mounted or not.
+    ///
+    /// A device configured by serial number, WWN, PARTUUID or label resolves to at most one device,
+    /// which is validated to be unique. Devices which aren't found are warned about.
+    pub fn resolve_blockdevices<'a>(
+        backup_device: &BackupDevice,
+        available_devices: &'a [BlockDevice],
+    ) -> Vec<&'a BlockDevice> {
+        let validated = if let Some(serial) = &backup_device.serial {
+            Self::validate_serial(serial, available_devices)
+        } else if let Some(wwn) = &backup_device.wwn {
+            Self::validate_wwn(wwn, available_devices)
+        } else if let Some(partuuid) = &backup_device.partuuid {
+            Self::validate_identifier(
+                partuuid,
+                available_devices,
+                |blockdevice| blockdevice.partuuid.as_deref(),
+                BackupError::NotUniquePartuuid,
+            )
+        } else if let Some(partlabel) = &backup_device.partlabel {
+            Self::validate_identifier(
+                partlabel,
+                available_devices,
+                |blockdevice| blockdevice.label.as_deref(),
+                BackupError::NotUniqueLabel,
+            )
+        } else if let Some(device_match) = &backup_device.device_match {
+            let matched = Self::matching_devices(device_match, available_devices);
+            if matched.is_empty() {
+                warn!(
+                    "No device found for {}, skipping it",
+                    backup_device.identifier()
+                );
+            }
+            return matched;
+        } else {
+            return vec![];
+        };
+        match validated {
+            Ok(blockdevice) => vec![blockdevice],
             Err(e) => {
                 warn!("{}, skipping it", e);
-                Ok(None)
+                vec![]
             }
         }
     }
 
+    /// Filters the available devices to those matching all patterns of `device_match`.
+    fn matching_devices<'a>(
+        device_match: &DeviceMatch,
+        available_devices: &'a [BlockDevice],
+    ) -> Vec<&'a BlockDevice> {
+        available_devices
+            .iter()
+            .filter(|blockdevice| {
+                let model_matches = device_match
+                    .model
+                    .as_ref()
+                    .is_none_or(|model| blockdevice.model.as_ref() == Some(model));
+                let serial_matches = device_match.serial_prefix.as_ref().is_none_or(|prefix| {
+                    blockdevice
+                        .serial
+                        .as_ref()
+                        .is_some_and(|serial| serial.starts_with(prefix))
+                });
+                model_matches && serial_matches
+            })
+            .collect()
+    }
+
     /// Filters the available devices to those with the specified serial number,
-    /// ensuring uniqueness and presence of device
-    fn validate_serial<'a>(
+    /// ensuring uniqueness and presence of device.
+    /// If no device has the serial number, it is looked up as WWN instead, see `validate_wwn`.
+    pub fn validate_serial<'a>(
         serial: &str,
         available_devices: &'a [BlockDevice],
-    ) -> Result<&'a BlockDevice, String> {
-        let serial_filtered_lsblk: Vec<&BlockDevice> = available_devices
+    ) -> Result<&'a BlockDevice, BackupError> {
+        match Self::validate_identifier(
+            serial,
+            available_devices,
+            |blockdevice| blockdevice.serial.as_deref(),
+            BackupError::NotUniqueSerial,
+        ) {
+            Err(BackupError::DeviceNotFound(_)) => Self::validate_wwn(serial, available_devices),
+            validated => validated,
+        }
+    }
+
+    /// Filters the available devices to those with the specified WWN,
+    /// ensuring uniqueness and presence of device.
+    pub fn validate_wwn<'a>(
+        wwn: &str,
+        available_devices: &'a [BlockDevice],
+    ) -> Result<&'a BlockDevice, BackupError> {
+        Self::validate_identifier(
+            wwn,
+            available_devices,
+            |blockdevice| blockdevice.wwn.as_deref(),
+            BackupError::NotUniqueWwn,
+        )
+    }
+
+    /// Returns the one available device whose identifier, read by `identifier_of`, equals `identifier`.
+    /// Several matching devices are reported with `not_unique`.
+    fn validate_identifier<'a>(
+        identifier: &str,
+        available_devices: &'a [BlockDevice],
+        identifier_of: impl Fn(&BlockDevice) -> Option<&str>,
+        not_unique: fn(String) -> BackupError,
+    ) -> Result<&'a BlockDevice, BackupError> {
+        let filtered_lsblk: Vec<&BlockDevice> = available_devices
             .iter()
-            .filter(|blockdevice| blockdevice.serial.clone().unwrap() == serial)
+            .filter(|blockdevice| identifier_of(blockdevice) == Some(identifier))
             .collect();
 
-        let is_device_serial_uniq = serial_filtered_lsblk.len() <= 1;
-        let is_device_available = !serial_filtered_lsblk.is_empty();
-
-        if is_device_available {
-            if is_device_serial_uniq {
-                return Ok(serial_filtered_lsblk[0]);
-            } else {
-                return Err(format!("Device has not a unique serial: {}", serial));
-            }
+        match filtered_lsblk.len() {
+            0 => Err(BackupError::DeviceNotFound(identifier.to_string())),
+            1 => Ok(filtered_lsblk[0]),
+            _ => Err(not_unique(identifier.to_string())),
         }
-        Err(format!("Device not found: {}", serial))
     }
 
     /// Checks if the specified device is currently mounted by querying `/proc/mounts`.
     ///
     /// Returns `Ok(true)` if the device is mounted, `Ok(false)` if it is not mounted,
-    /// or `Err(String)` if an error occurred while checking.
-    fn is_device_mounted(device_path: &str) -> Result<bool, String> {
-        let file = File::open("/proc/mounts")
-            .map_err(|e| format!("Failed to open /proc/mounts: {}", e))?;
+    /// or `Err(BackupError::Io)` if an error occurred while checking.
+    pub fn is_device_mounted(device_path: &str) -> Result<bool, BackupError> {
+        let file = File::open("/proc/mounts").map_err(BackupError::io(
+            "Failed to open /proc/mounts, which needs Linux with the proc filesystem mounted",
+        ))?;
         let reader = BufReader::new(file);
 
-        for line in reader.lines().flatten() {
+        for line in reader.lines().map_while(Result::ok) {
             let fields: Vec<&str> = line.split(' ').collect();
             if fields.len() >= 2 && fields[0].contains(device_path) {
-                error!("Device {} is mounted, skipping it", device_path);
                 return Ok(true);
             }
         }
         Ok(false)
     }
 
-    /// Returns the total size of the block device, converted to bytes, or None if the size is unavailable.
+    /// Returns the total size of the block device in bytes.
     /// This value is static in one run
-    pub fn total_size(&self) -> Result<Option<u64>, String> {
-        convert_to_byte_size(&self.blockdevice.size)
+    pub fn total_size(&self) -> u64 {
+        self.blockdevice.size
     }
 }
 
+/// Returns whether `size` deviates from `expected_size` by at most `EXPECTED_SIZE_TOLERANCE_PERCENT`.
+fn is_within_tolerance(size: u64, expected_size: u64) -> bool {
+    size.abs_diff(expected_size) as u128 * 100
+        <= expected_size as u128 * EXPECTED_SIZE_TOLERANCE_PERCENT as u128
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use super::*;
 
     fn generate_test_devices() -> Vec<BlockDevice> {
@@ -127,32 +309,55 @@ mod tests {
                 name: "sda1".to_string(),
                 model: Some("model1".to_string()),
                 serial: Some("serial1".to_string()),
+                wwn: Some("0x5000000000000001".to_string()),
+                partuuid: None,
+                label: None,
+                pkname: None,
                 uuid: Some("uuid1".to_string()),
                 mountpoint: Some("/mnt/sda1".to_string()),
-                size: "100GB".to_string(),
-                fsavail: Some("50GB".to_string()),
+                size: 100_000_000_000,
+                fsavail: Some(50_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
             BlockDevice {
                 name: "sdb1".to_string(),
                 model: Some("model2".to_string()),
                 serial: Some("serial2".to_string()),
+                wwn: Some("0x5000000000000002".to_string()),
+                partuuid: None,
+                label: None,
+                pkname: None,
                 uuid: Some("uuid2".to_string()),
                 mountpoint: Some("/mnt/sdb1".to_string()),
-                size: "200GB".to_string(),
-                fsavail: Some("100GB".to_string()),
+                size: 200_000_000_000,
+                fsavail: Some(100_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
             BlockDevice {
                 name: "sdc1".to_string(),
                 model: Some("model3".to_string()),
                 serial: Some("serial2".to_string()), // Duplicate serial
+                wwn: None,
+                partuuid: Some("0d4e1c2a-02".to_string()),
+                label: Some("data".to_string()),
+                pkname: None,
                 uuid: Some("uuid3".to_string()),
                 mountpoint: Some("/mnt/sdc1".to_string()),
-                size: "300GB".to_string(),
-                fsavail: Some("150GB".to_string()),
+                size: 300_000_000_000,
+                fsavail: Some(150_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
         ]
     }
 
+    #[test]
+    fn test_is_within_tolerance() {
+        assert!(is_within_tolerance(500_107_862_016, 500_000_000_000));
+        assert!(is_within_tolerance(1000, 1010));
+        assert!(!is_within_tolerance(1000, 1020));
+        assert!(!is_within_tolerance(1_000_204_886_016, 500_000_000_000));
+    }
+
     #[test]
     fn test_validate_serial() {
         let devices = generate_test_devices();
@@ -166,13 +371,163 @@ mod tests {
         // Serial exists but is not unique
         match Device::validate_serial("serial2", &devices) {
             Ok(_) => panic!("Should have failed due to non-unique serial"),
-            Err(msg) => assert!(msg.contains("not a unique serial")),
+            Err(e) => assert!(matches!(e, BackupError::NotUniqueSerial(_))),
         }
 
         // Serial does not exist
         match Device::validate_serial("serial3", &devices) {
             Ok(_) => panic!("Should have failed due to non-existent serial"),
-            Err(msg) => assert!(msg.contains("Device not found")),
+            Err(e) => assert!(matches!(e, BackupError::DeviceNotFound(_))),
+        }
+
+        // Serial is not found, but the WWN of a device with a duplicated serial
+        match Device::validate_serial("0x5000000000000002", &devices) {
+            Ok(device) => assert_eq!(device.name, "sdb1"),
+            Err(e) => panic!("Error: {:?}", e),
         }
     }
+
+    #[test]
+    fn test_validate_wwn() {
+        let devices = generate_test_devices();
+
+        assert_eq!(
+            Device::validate_wwn("0x5000000000000001", &devices)
+                .unwrap()
+                .name,
+            "sda1"
+        );
+        assert!(matches!(
+            Device::validate_wwn("serial1", &devices),
+            Err(BackupError::DeviceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_partitions() {
+        let devices = generate_test_devices();
+
+        let backup_device = BackupDevice {
+            partuuid: Some("0d4e1c2a-02".to_string()),
+            ..Default::default()
+        };
+        let resolved = Device::resolve_blockdevices(&backup_device, &devices);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].name, "sdc1");
+
+        let backup_device = BackupDevice {
+            partlabel: Some("data".to_string()),
+            ..Default::default()
+        };
+        let resolved = Device::resolve_blockdevices(&backup_device, &devices);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].name, "sdc1");
+
+        let backup_device = BackupDevice {
+            partlabel: Some("other".to_string()),
+            ..Default::default()
+        };
+        assert!(Device::resolve_blockdevices(&backup_device, &devices).is_empty());
+    }
+
+    #[test]
+    fn test_filesystem_of() {
+        let partition = |name: &str, pkname: &str| BlockDevice {
+            name: name.to_string(),
+            pkname: Some(pkname.to_string()),
+            uuid: Some(format!("uuid-{}", name)),
+            ..generate_test_devices().remove(0)
+        };
+        let disk = |name: &str| BlockDevice {
+            name: name.to_string(),
+            uuid: None,
+            ..generate_test_devices().remove(0)
+        };
+        let filesystems = vec![
+            partition("sdd1", "sdd"),
+            partition("sde1", "sde"),
+            partition("sde2", "sde"),
+        ];
+
+        let name = |filesystem: Option<&BlockDevice>| filesystem.map(|f| f.name.clone());
+        assert_eq!(
+            name(Device::filesystem_of(&disk("sdd"), &filesystems)),
+            Some("sdd1".to_string())
+        );
+        assert_eq!(
+            name(Device::filesystem_of(&disk("sde"), &filesystems)),
+            None
+        );
+        assert_eq!(
+            name(Device::filesystem_of(&disk("sdf"), &filesystems)),
+            None
+        );
+        assert_eq!(
+            name(Device::filesystem_of(&filesystems[1], &filesystems)),
+            Some("sde1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_new_keeps_mounted_rsync_devices() {
+        let mounts = std::fs::read_to_string("/proc/mounts").unwrap();
+        // any mounted block device, like the root filesystem
+        let Some(name) = mounts
+            .lines()
+            .filter_map(|line| line.split(' ').next()?.strip_prefix("/dev/"))
+            .next()
+        else {
+            return;
+        };
+        let blockdevice = BlockDevice {
+            name: name.to_string(),
+            serial: Some("mounted-serial".to_string()),
+            ..generate_test_devices().remove(0)
+        };
+        let new = |method: BackupMethod| {
+            let backup_device = BackupDevice {
+                serial: Some("mounted-serial".to_string()),
+                method: Some(method),
+                ..Default::default()
+            };
+            Device::new(
+                &backup_device,
+                std::slice::from_ref(&blockdevice),
+                &[],
+                "/.".to_string(),
+                false,
+            )
+            .unwrap()
+        };
+
+        assert!(new(BackupMethod::Dd).is_empty());
+        let devices = new(BackupMethod::Rsync);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device_path, format!("/dev/{}", name));
+    }
+
+    #[test]
+    fn test_matching_devices() {
+        let devices = generate_test_devices();
+
+        let device_match = DeviceMatch {
+            model: None,
+            serial_prefix: Some("serial".to_string()),
+        };
+        assert_eq!(Device::matching_devices(&device_match, &devices).len(), 3);
+
+        let device_match = DeviceMatch {
+            model: Some("model2".to_string()),
+            serial_prefix: Some("serial".to_string()),
+        };
+        let matched = Device::matching_devices(&device_match, &devices);
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].name, "sdb1");
+
+        let device_match = DeviceMatch {
+            model: Some("model1".to_string()),
+            serial_prefix: Some("serial2".to_string()),
+        };
+        assert!(Device::matching_devices(&device_match, &devices).is_empty());
+    }
 }
@@ -1,14 +1,29 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{NaiveDate, NaiveTime};
+use nix::sys::statvfs::statvfs;
 
-use crate::run::{config::BackupConfig, utils::convert_to_byte_size};
+use crate::run::{
+    config::{BackupConfig, BackupMethod, DestinationId, FsckPolicy},
+    error::BackupError,
+    manifest::manifest_file_path,
+    utils::{DATE_FORMAT, TIME_FORMAT},
+};
 
 use super::{
-    command_output::command_output,
-    lsblk::{BlockDevice, Lsblk},
+    backup::CHECKSUM_FILE_EXTENSION,
+    command_output::{command_output, command_output_with_timeout},
+    lsblk::BlockDevice,
+    signal::set_mountpoint,
 };
 
 /// Represents a filesystem associated with a block device.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Filesystem {
     /// The underlying block device information.
     pub blockdevice: BlockDevice,
@@ -16,37 +31,54 @@ pub struct Filesystem {
     pub device_path: String,
     /// The mount path for the filesystem.
     pub mountpath: String,
-    // The available size of the block device
-    pub fsavail: Option<u64>,
     pub fsck_command: String,
     pub skip_fsck: bool,
+    /// The options passed to `mount -o`, if any.
+    pub mount_options: Option<String>,
+    /// The filesystem type passed to `mount -t`, if any.
+    pub mount_type: Option<String>,
+    /// What to do when the filesystem check fails.
+    pub fsck_policy: FsckPolicy,
+    /// Whether the image and the destination directory are fsynced after writing a backup.
+    pub durable: bool,
+    /// Whether the free space check before a backup is skipped.
+    pub skip_space_check: bool,
+    /// The maximum duration of mounting, unmounting and `fsck`, `None` waits indefinitely.
+    pub command_timeout: Option<Duration>,
+    /// The only paths the filesystem may be mounted on, `None` allows any.
+    pub allowed_mountpaths: Option<Vec<String>>,
+    /// The bytes reserved by running backups, which passed the space check but may not be written yet.
+    pub reserved_space: Arc<Mutex<u64>>,
 }
 
 impl Filesystem {
-    /// Creates a new `Filesystem` instance for the specified UUID, using the provided `Lsblk` instance.
+    /// Creates a new `Filesystem` instance for the specified UUID or label, using the provided `Lsblk` instance.
     ///
-    /// It returns `Ok(Some(Filesystem))` if the UUID is unique and associated with a block device,
-    /// `Ok(None)` if the UUID is not found in the available filesystems,
-    /// or an error message if the UUID is not unique.
+    /// It returns `Ok(Some(Filesystem))` if the UUID or label is unique and associated with a block device,
+    /// `Ok(None)` if it is not found in the available filesystems,
+    /// or an error message if it is not unique.
     ///
     /// # Arguments
     ///
-    /// * `uuid` - The UUID of the filesystem.
+    /// * `destination` - The UUID or label of the filesystem, the configured one or one of its mirrors.
+    /// * `backup_config` - The backup configuration.
     /// * `available_filesystems` - The list of available block devices to search for a matching UUID.
     /// * `mountpath` - The optional mount path of the filesystem.
     ///
     /// # Returns
     ///
-    /// - `Ok(Some(Filesystem))`: If a unique match is found based on the UUID.
-    /// - `Ok(None)`: If no match is found based on the UUID.
-    /// - `Err(String)`: If the UUID is not unique among the available filesystems.
+    /// - `Ok(Some(Filesystem))`: If a unique match is found based on the UUID or label.
+    /// - `Ok(None)`: If no match is found based on the UUID or label.
+    /// - `Err(BackupError::NotUniqueUuid)`: If the UUID is not unique among the available filesystems,
+    ///   `Err(BackupError::NotUniqueDestinationLabel)` if the label isn't.
     pub fn new(
+        destination: &DestinationId,
         backup_config: &BackupConfig,
         available_filesystems: &[BlockDevice],
         mountpath: Option<String>,
-    ) -> Result<Option<Filesystem>, String> {
+    ) -> Result<Option<Filesystem>, BackupError> {
         let uuid_filtered_lsblk =
-            Self::validate_uuid_uniq(&backup_config.uuid, available_filesystems)?;
+            Self::validate_destination_uniq(destination, available_filesystems)?;
 
         match Self::validate_present_uuid(uuid_filtered_lsblk) {
             Some(blockdevice) => {
@@ -54,25 +86,26 @@ impl Filesystem {
                     blockdevice: blockdevice.clone(),
                     device_path: format!("/dev/{}", &blockdevice.name),
                     mountpath: mountpath.unwrap_or("/mnt".to_string()),
-                    fsavail: blockdevice
-                        .fsavail
-                        .clone()
-                        .map(|fsavail| convert_to_byte_size(&fsavail).unwrap_or(None))
-                        .unwrap_or(None),
                     fsck_command: backup_config
                         .fsck_command
                         .clone()
                         .unwrap_or("fsck -n".to_string()),
-                    skip_fsck: backup_config.skip_fsck.unwrap_or(false),
+                    skip_fsck: backup_config.skip_fsck.unwrap_or(false)
+                        || backup_config.fsck_policy == Some(FsckPolicy::Skip),
+                    mount_options: backup_config.mount_options.clone(),
+                    mount_type: backup_config.mount_type.clone(),
+                    fsck_policy: backup_config.fsck_policy.unwrap_or_default(),
+                    durable: backup_config.durable.unwrap_or(true),
+                    skip_space_check: backup_config.skip_space_check.unwrap_or(false),
+                    command_timeout: None,
+                    allowed_mountpaths: None,
+                    reserved_space: Arc::new(Mutex::new(0)),
                 };
                 debug!("{:?}", filesystem);
                 Ok(Some(filesystem))
             }
             None => {
-                info!(
-                    "Filesystem with uuid {}, not found, skipping it",
-                    &backup_config.uuid
-                );
+                info!("Filesystem with {}, not found, skipping it", destination);
                 Ok(None)
             }
         }
@@ -89,21 +122,41 @@ impl Filesystem {
         }
     }
 
-    /// Validates if the UUID is unique among the available filesystems.
-    /// Returns a filtered list of block devices with the specified UUID, or an error if the UUID is not unique.
-    fn validate_uuid_uniq<'b>(
-        uuid: &str,
+    /// Validates if the UUID or label is unique among the available filesystems.
+    /// Returns a filtered list of block devices with the specified UUID or label, or an error if it is not unique.
+    fn validate_destination_uniq<'b>(
+        destination: &DestinationId,
         available_filesystems: &'b [BlockDevice],
-    ) -> Result<Vec<&'b BlockDevice>, String> {
+    ) -> Result<Vec<&'b BlockDevice>, BackupError> {
         let uuid_filtered_lsblk: Vec<&BlockDevice> = available_filesystems
             .iter()
-            .filter(|filesystem| filesystem.uuid.as_deref() == Some(uuid))
+            .filter(|filesystem| is_destination(filesystem, destination))
             .collect::<Vec<&BlockDevice>>();
 
         if uuid_filtered_lsblk.len() <= 1 {
-            Ok(uuid_filtered_lsblk)
-        } else {
-            Err(format!("Not a unique UUID: {}", uuid))
+            return Ok(uuid_filtered_lsblk);
+        }
+        let device_paths = uuid_filtered_lsblk
+            .iter()
+            .map(|filesystem| format!("/dev/{}", filesystem.name))
+            .collect();
+        Err(match destination {
+            DestinationId::Uuid(uuid) => BackupError::NotUniqueUuid {
+                uuid: uuid.clone(),
+                device_paths,
+            },
+            DestinationId::Label(label) => BackupError::NotUniqueDestinationLabel {
+                label: label.clone(),
+                device_paths,
+            },
+        })
+    }
+
+    /// Returns the path of the drive holding the filesystem, the parent device of a partition.
+    pub fn drive_path(&self) -> String {
+        match &self.blockdevice.pkname {
+            Some(pkname) => format!("/dev/{}", pkname),
+            None => self.device_path.clone(),
         }
     }
 
@@ -113,36 +166,60 @@ impl Filesystem {
         self.blockdevice.mountpoint.is_some()
     }
 
-    /// Mounts the device.
+    /// Mounts the device, with the `mount_type` and `mount_options`, if configured.
+    /// Refuses to mount on a path which isn't in `allowed_mountpaths`, if configured.
     /// Returns `Ok(())` if the device is mounted successfully, otherwise returns an error message.
-    pub fn mount(&mut self) -> Result<(), String> {
-        let output = command_output(
-            vec!["mount", &self.device_path, &self.mountpath],
+    pub fn mount(&mut self) -> Result<(), BackupError> {
+        if !is_mountpath_allowed(&self.mountpath, &self.allowed_mountpaths) {
+            return Err(BackupError::Other(format!(
+                "Mount path {} is not in allowed_mountpaths, refusing to mount filesystem {}",
+                self.mountpath, self.device_path
+            )));
+        }
+
+        let mut command_parts = vec!["mount"];
+        if let Some(mount_type) = &self.mount_type {
+            command_parts.extend(["-t", mount_type]);
+        }
+        if let Some(mount_options) = &self.mount_options {
+            command_parts.extend(["-o", mount_options]);
+        }
+        command_parts.extend([self.device_path.as_str(), self.mountpath.as_str()]);
+
+        let output = command_output_with_timeout(
+            command_parts,
             &format!(
                 "mount filesystem {} at {}",
                 self.device_path, self.mountpath
             ),
             Some(true),
-        )?;
+            self.command_timeout,
+        )
+        .map_err(|e| BackupError::MountFailed {
+            device_path: self.device_path.clone(),
+            mountpath: self.mountpath.clone(),
+            source: Box::new(e),
+        })?;
 
         if output.status.success() {
             self.blockdevice.mountpoint = Some(self.mountpath.clone());
+            set_mountpoint(Some(self.mountpath.clone()));
             info!(
                 "Filesystem {} mounted successfully on {}",
                 self.device_path, self.mountpath
             );
             Ok(())
         } else {
-            Err(format!(
+            Err(BackupError::Other(format!(
                 "Error mounting filesystem {} on {}",
                 self.device_path, self.mountpath
-            ))
+            )))
         }
     }
 
     /// Unmounts the device.
     /// Returns `Ok(())` if the device is unmounted successfully, otherwise returns an error message.
-    pub fn unmount(&mut self) -> Result<(), String> {
+    pub fn unmount(&mut self) -> Result<(), BackupError> {
         let mountpoint = self
             .blockdevice
             .mountpoint
@@ -151,107 +228,207 @@ impl Filesystem {
 
         command_output(vec!["sync"], "execute sync", Some(false))?;
 
-        let output = command_output(
+        let output = command_output_with_timeout(
             vec!["umount", &mountpoint],
             &format!("unmount filesystem {} at {}", self.device_path, &mountpoint),
             Some(true),
-        )?;
+            self.command_timeout,
+        )
+        .map_err(|e| BackupError::UnmountFailed {
+            device_path: self.device_path.clone(),
+            mountpath: mountpoint.clone(),
+            source: Box::new(e),
+        })?;
 
         if output.status.success() {
             self.blockdevice.mountpoint = None;
+            set_mountpoint(None);
             info!("Filesystem {} unmounted successfully", self.device_path);
             Ok(())
         } else {
-            Err(format!(
+            Err(BackupError::Other(format!(
                 "Error unmounting filesystem {} at {}: {}",
                 self.device_path,
                 &mountpoint,
                 String::from_utf8_lossy(&output.stderr)
-            ))
+            )))
         }
     }
 
-    /// Checks if the number of existing backups exceeds the specified number of copies.
+    /// Returns the number of existing backups matching the suffix in the backup dir.
     pub fn present_number_of_copies(
         &self,
-        suffix_file_name_pattern: &str,
+        file_name_pattern: &str,
         backup_dst_dir: &str,
+        method: BackupMethod,
     ) -> usize {
-        let backup_files = match fs::read_dir(backup_dst_dir) {
-            Ok(files) => files
-                .filter_map(|entry| {
-                    entry.ok().and_then(|e| {
-                        e.file_name()
-                            .to_str()
-                            .map(|s| s.to_string())
-                            .filter(|s| s.contains(suffix_file_name_pattern))
-                    })
-                })
-                .collect::<Vec<String>>(),
-            Err(_) => Vec::new(),
-        };
+        self.present_backup_files(file_name_pattern, backup_dst_dir, method)
+            .map(|backup_files| backup_files.len())
+            .unwrap_or(0)
+    }
 
-        backup_files.len() // >= self.backup_device.copies as usize
+    /// Returns the name of the oldest backup file, or directory for `rsync` backups, if any.
+    pub fn oldest_backup(
+        &self,
+        file_name_pattern: &str,
+        backup_dst_path: &str,
+        method: BackupMethod,
+    ) -> Result<Option<String>, BackupError> {
+        Ok(self
+            .present_backups_oldest_first(file_name_pattern, backup_dst_path, method)?
+            .into_iter()
+            .next())
     }
 
-    /// Deletes the oldest backup file.
-    pub fn delete_oldest_backup(
+    /// Returns the names of the backup files, or directories for `rsync` backups, oldest first.
+    pub fn present_backups_oldest_first(
         &self,
-        suffix_file_name_pattern: &str,
+        file_name_pattern: &str,
         backup_dst_path: &str,
-    ) -> Result<(), String> {
-        let present_backup_files =
-            self.present_backup_files(suffix_file_name_pattern, backup_dst_path)?;
-        if let Some(oldest_file) = present_backup_files.iter().min_by_key(|&file_name| {
-            let file_path = Path::new(backup_dst_path).join(file_name);
-            if let Ok(metadata) = fs::metadata(file_path) {
-                if let Ok(created) = metadata.created() {
-                    return created;
+        method: BackupMethod,
+    ) -> Result<Vec<String>, BackupError> {
+        let mut present_backup_files =
+            self.present_backup_files(file_name_pattern, backup_dst_path, method)?;
+        present_backup_files
+            .sort_by_key(|file_name| backup_time(&Path::new(backup_dst_path).join(file_name)));
+        Ok(present_backup_files)
+    }
+
+    /// Returns the names of the backups older than `max_age`, oldest first.
+    pub fn expired_backups(
+        &self,
+        file_name_pattern: &str,
+        backup_dst_path: &str,
+        method: BackupMethod,
+        max_age: Duration,
+    ) -> Result<Vec<String>, BackupError> {
+        let threshold = SystemTime::now()
+            .checked_sub(max_age)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut expired_backups: Vec<(SystemTime, String)> = self
+            .present_backup_files(file_name_pattern, backup_dst_path, method)?
+            .into_iter()
+            .map(|file_name| {
+                (
+                    backup_time(&Path::new(backup_dst_path).join(&file_name)),
+                    file_name,
+                )
+            })
+            .filter(|(time, _)| *time < threshold)
+            .collect();
+        expired_backups.sort();
+        Ok(expired_backups
+            .into_iter()
+            .map(|(_, file_name)| file_name)
+            .collect())
+    }
+
+    /// Deletes the backup file `file_name` including its checksum file, or directory for `rsync` backups.
+    pub fn delete_backup(
+        &self,
+        backup_dst_path: &str,
+        file_name: &str,
+        method: BackupMethod,
+    ) -> Result<(), BackupError> {
+        let file_path = format!("{}/{}", backup_dst_path, file_name);
+        match method {
+            BackupMethod::Dd => {
+                info!("Delete old back up file: {}", file_path);
+                fs::remove_file(&file_path).map_err(BackupError::io(format!(
+                    "Failed to delete old backup file '{}'",
+                    file_path
+                )))?;
+                for sidecar_file_path in [
+                    format!("{}{}", file_path, CHECKSUM_FILE_EXTENSION),
+                    manifest_file_path(&file_path),
+                ] {
+                    if Path::new(&sidecar_file_path).exists() {
+                        fs::remove_file(&sidecar_file_path).map_err(BackupError::io(format!(
+                            "Failed to delete sidecar file '{}'",
+                            sidecar_file_path
+                        )))?;
+                    }
                 }
+                Ok(())
+            }
+            BackupMethod::Rsync => {
+                // files copied by rsync keep their owners, so removing them needs sudo
+                info!("Delete old back up dir: {}", file_path);
+                command_output(
+                    vec!["rm", "-rf", &file_path],
+                    "delete old backup dir",
+                    Some(true),
+                )?;
+                Ok(())
             }
-            // fallback value to ensure consistent ordering
-            std::time::UNIX_EPOCH
-        }) {
-            let file_path = format!("{}/{}", backup_dst_path, oldest_file);
-            info!("Delete old back up file: {}", file_path);
-            fs::remove_file(&file_path)
-                .map_err(|e| format!("Failed to delete oldest backup file '{}': {}", file_path, e))
-        } else {
-            Ok(())
         }
     }
 
-    /// Returns the available space of the block device, converted to bytes, or None if the size is unavailable / readable.
-    pub fn available_space(&self) -> Result<Option<u64>, String> {
-        let device_uuid = self.blockdevice.uuid.clone();
-        // needs a new lsblk instance, since the filesystem size is only accessible if mounted
-        let lsblk = Lsblk::new()?;
-        let filesystem = lsblk
-            .available_filesystems
-            .iter()
-            .find(|fs| fs.uuid == device_uuid)
-            .unwrap();
+    /// Returns the available space of the mounted filesystem in bytes, or None if it isn't mounted.
+    ///
+    /// The value is read with `statvfs` on the mountpoint, counting only blocks available
+    /// to unprivileged users.
+    pub fn available_space(&self) -> Result<Option<u64>, BackupError> {
+        Ok(self.space()?.map(|(available_space, _)| available_space))
+    }
 
-        Ok(filesystem
-            .fsavail
-            .clone()
-            .map(|fsavail| convert_to_byte_size(&fsavail).unwrap_or(None))
-            .unwrap_or(None))
+    /// Returns the total size of the mounted filesystem in bytes, or None if it isn't mounted.
+    pub fn total_space(&self) -> Result<Option<u64>, BackupError> {
+        Ok(self.space()?.map(|(_, total_space)| total_space))
     }
 
-    fn present_backup_files(
+    /// Reads the available and total space of the mounted filesystem in bytes with `statvfs`,
+    /// or None if it isn't mounted.
+    fn space(&self) -> Result<Option<(u64, u64)>, BackupError> {
+        match &self.blockdevice.mountpoint {
+            Some(mountpoint) => {
+                let stat = statvfs(mountpoint.as_str()).map_err(|e| {
+                    format!(
+                        "Failed to read filesystem statistics of {}: {}",
+                        mountpoint, e
+                    )
+                })?;
+                Ok(Some((
+                    stat.blocks_available() as u64 * stat.fragment_size() as u64,
+                    stat.blocks() as u64 * stat.fragment_size() as u64,
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the size of the backup file `file_name` in bytes, 0 for `rsync` directories or if it isn't readable.
+    pub fn backup_size(&self, backup_dst_path: &str, file_name: &str) -> u64 {
+        fs::metadata(Path::new(backup_dst_path).join(file_name))
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    /// Returns the names of the backups matching the suffix in the backup dir.
+    /// Backups are files for `dd` and directories for `rsync`.
+    ///
+    /// The name needs to be the date followed by exactly the suffix, so tagged backups, whose
+    /// suffix starts with the tag, form a series of their own. Partial images don't match.
+    pub fn present_backup_files(
         &self,
-        suffix_file_name_pattern: &str,
+        file_name_pattern: &str,
         backup_dst_path: &str,
-    ) -> Result<Vec<String>, String> {
+        method: BackupMethod,
+    ) -> Result<Vec<String>, BackupError> {
         let present_backup_files = fs::read_dir(backup_dst_path)
-            .map_err(|e| format!("Failed to read backup directory: {}", e))?
+            .map_err(BackupError::io("Failed to read backup directory"))?
             .filter_map(|entry| {
                 entry.ok().and_then(|e| {
+                    let is_dir = e.file_type().is_ok_and(|file_type| file_type.is_dir());
+                    if is_dir != (method == BackupMethod::Rsync) {
+                        return None;
+                    }
                     e.file_name()
                         .to_str()
                         .map(|s| s.to_string())
-                        .filter(|s| s.contains(suffix_file_name_pattern))
+                        .filter(|s| is_backup_of_series(s, file_name_pattern))
                 })
             })
             .collect::<Vec<String>>();
@@ -262,9 +439,11 @@ impl Filesystem {
     ///
     /// If the `skip_fsck` field is set to `true`, this function returns `Ok(())` without performing any checks.
     /// If the `skip_fsck` field is set to `false` or not specified, this function executes the `fsck` command
-    /// specified in the `fsck_command` (otherwise `fsck -n /dev/path1`) field and checks if the command succeeded.
-    /// If the command succeeds, it returns `Ok(())`. Otherwise, it returns an `Err` with an error message.
-    pub fn validate_fsck_or_skip(&self) -> Result<(), String> {
+    /// specified in the `fsck_command` (otherwise `fsck -n /dev/path1`) field and checks if the command succeeded,
+    /// killing it after `command_timeout`.
+    /// If the command succeeds, it returns `Ok(())`. Otherwise, it returns an `Err` naming the device path and
+    /// the error of the command, including its exit status.
+    pub fn validate_fsck_or_skip(&self) -> Result<(), BackupError> {
         match self.skip_fsck {
             true => Ok(()),
             false => {
@@ -272,20 +451,104 @@ impl Filesystem {
                 let mut command_parts: Vec<&str> = fsck_command.split(' ').collect();
                 command_parts.push(self.device_path.as_str());
 
-                let output = command_output(command_parts, "check fs", Some(true))?;
-
-                if output.status.success() {
-                    Ok(())
-                } else {
-                    Err("ATTENTION: fsck was not successfull".to_string())
-                }
+                command_output_with_timeout(
+                    command_parts,
+                    "check fs",
+                    Some(true),
+                    self.command_timeout,
+                )
+                .map_err(|e| {
+                    BackupError::Other(format!(
+                        "ATTENTION: fsck of {} was not successful: {}",
+                        self.device_path, e
+                    ))
+                })?;
+                Ok(())
             }
         }
     }
 }
 
+/// Returns whether `file_name` is a backup matching the `file_name_pattern`.
+///
+/// The pattern is the expanded name template with the placeholders `{date}` and `{time}` left in,
+/// which match any valid date (YYYY-MM-DD) and time (HH-MM-SS), while the rest needs to match exactly.
+/// So the backups of a device whose model or serial is a prefix of another one's, or sidecar files
+/// like `<image>.sha256`, don't count as backups of its series.
+pub(crate) fn is_backup_of_series(file_name: &str, file_name_pattern: &str) -> bool {
+    let next_placeholder = ["{date}", "{time}"]
+        .into_iter()
+        .filter_map(|placeholder| Some((file_name_pattern.find(placeholder)?, placeholder)))
+        .min();
+    match next_placeholder {
+        Some((start, placeholder)) => {
+            let value_len = match placeholder {
+                "{date}" => "YYYY-MM-DD".len(),
+                _ => "HH-MM-SS".len(),
+            };
+            let is_valid_value = |value: &str| match placeholder {
+                "{date}" => NaiveDate::parse_from_str(value, DATE_FORMAT).is_ok(),
+                _ => NaiveTime::parse_from_str(value, TIME_FORMAT).is_ok(),
+            };
+            file_name.starts_with(&file_name_pattern[..start])
+                && file_name
+                    .get(start..start + value_len)
+                    .is_some_and(is_valid_value)
+                && is_backup_of_series(
+                    &file_name[start + value_len..],
+                    &file_name_pattern[start + placeholder.len()..],
+                )
+        }
+        None => file_name == file_name_pattern,
+    }
+}
+
+/// Returns the date in the name of the backup `file_name` of the series with `file_name_pattern`,
+/// at the position of its `{date}` placeholder.
+pub(crate) fn backup_date(file_name: &str, file_name_pattern: &str) -> Option<NaiveDate> {
+    let date_start = file_name_pattern.find("{date}")?;
+    // a time before the date is longer in the name than its placeholder
+    let time_count = file_name_pattern[..date_start].matches("{time}").count();
+    let start = date_start + time_count * ("HH-MM-SS".len() - "{time}".len());
+    NaiveDate::parse_from_str(
+        file_name.get(start..start + "YYYY-MM-DD".len())?,
+        DATE_FORMAT,
+    )
+    .ok()
+}
+
+/// Returns the creation time of a backup, which orders the backups.
+///
+/// Falls back to the modification time on filesystems without creation times,
+/// and to `UNIX_EPOCH` if the metadata isn't readable.
+pub(crate) fn backup_time(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Returns whether the `filesystem` is the `destination`, comparing a UUID with its UUID and a label with its label.
+fn is_destination(filesystem: &BlockDevice, destination: &DestinationId) -> bool {
+    match destination {
+        DestinationId::Uuid(uuid) => filesystem.uuid.as_deref() == Some(uuid.as_str()),
+        DestinationId::Label(label) => filesystem.label.as_deref() == Some(label.as_str()),
+    }
+}
+
+/// Returns whether `mountpath` is one of the `allowed_mountpaths`, or if there is no such restriction.
+fn is_mountpath_allowed(mountpath: &str, allowed_mountpaths: &Option<Vec<String>>) -> bool {
+    match allowed_mountpaths {
+        Some(allowed_mountpaths) => allowed_mountpaths
+            .iter()
+            .any(|allowed_mountpath| Path::new(allowed_mountpath) == Path::new(mountpath)),
+        None => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use super::*;
 
     fn generate_test_filesystems() -> Vec<BlockDevice> {
@@ -294,28 +557,43 @@ mod tests {
                 name: "sda1".to_string(),
                 model: Some("model1".to_string()),
                 serial: Some("serial1".to_string()),
+                wwn: None,
+                partuuid: None,
+                label: None,
+                pkname: None,
                 uuid: Some("uuid1".to_string()),
                 mountpoint: Some("/mnt/sda1".to_string()),
-                size: "100GB".to_string(),
-                fsavail: Some("50GB".to_string()),
+                size: 100_000_000_000,
+                fsavail: Some(50_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
             BlockDevice {
                 name: "sdb1".to_string(),
                 model: Some("model2".to_string()),
                 serial: Some("serial2".to_string()),
+                wwn: None,
+                partuuid: None,
+                label: None,
+                pkname: None,
                 uuid: Some("uuid2".to_string()),
                 mountpoint: Some("/mnt/sdb1".to_string()),
-                size: "200GB".to_string(),
-                fsavail: Some("100GB".to_string()),
+                size: 200_000_000_000,
+                fsavail: Some(100_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
             BlockDevice {
                 name: "sdc1".to_string(),
                 model: Some("model3".to_string()),
                 serial: Some("serial3".to_string()),
+                wwn: None,
+                partuuid: None,
+                label: None,
+                pkname: None,
                 uuid: Some("uuid2".to_string()), // Duplicate UUID
                 mountpoint: Some("/mnt/sdc1".to_string()),
-                size: "300GB".to_string(),
-                fsavail: Some("150GB".to_string()),
+                size: 300_000_000_000,
+                fsavail: Some(150_000_000_000),
+                extra_columns: BTreeMap::new(),
             },
         ]
     }
@@ -338,11 +616,229 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_uuid_uniq() {
+    fn test_validate_destination_uniq() {
         let filesystems = generate_test_filesystems();
+        let uuid = |uuid: &str| DestinationId::Uuid(uuid.to_string());
+        let label = |label: &str| DestinationId::Label(label.to_string());
+
+        assert!(Filesystem::validate_destination_uniq(&uuid("uuid1"), &filesystems).is_ok());
+        assert_eq!(
+            Filesystem::validate_destination_uniq(&uuid("uuid2"), &filesystems)
+                .unwrap_err()
+                .to_string(),
+            "Not a unique UUID: uuid2, shared by /dev/sdb1, /dev/sdc1"
+        );
+        // UUID not present
+        assert!(
+            Filesystem::validate_destination_uniq(&uuid("uuid3"), &filesystems)
+                .unwrap()
+                .is_empty()
+        );
+
+        let mut filesystems = filesystems;
+        filesystems[0].label = Some("backup".to_string());
+        assert_eq!(
+            Filesystem::validate_destination_uniq(&label("backup"), &filesystems).unwrap()[0].name,
+            "sda1"
+        );
+        // a label is only compared with the labels, a UUID only with the UUIDs
+        assert!(
+            Filesystem::validate_destination_uniq(&label("uuid1"), &filesystems)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            Filesystem::validate_destination_uniq(&uuid("backup"), &filesystems)
+                .unwrap()
+                .is_empty()
+        );
+        filesystems[1].label = Some("backup".to_string());
+        assert_eq!(
+            Filesystem::validate_destination_uniq(&label("backup"), &filesystems)
+                .unwrap_err()
+                .to_string(),
+            "Not a unique label: backup, shared by /dev/sda1, /dev/sdb1"
+        );
+    }
+
+    #[test]
+    fn test_present_backup_files() {
+        let blockdevice = generate_test_filesystems().remove(0);
+        let filesystem = Filesystem {
+            device_path: format!("/dev/{}", blockdevice.name),
+            blockdevice,
+            mountpath: "/mnt".to_string(),
+            fsck_command: "fsck -n".to_string(),
+            skip_fsck: false,
+            mount_options: None,
+            mount_type: None,
+            fsck_policy: FsckPolicy::Abort,
+            durable: true,
+            skip_space_check: false,
+            command_timeout: None,
+            allowed_mountpaths: None,
+            reserved_space: Arc::new(Mutex::new(0)),
+        };
+
+        let backup_dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_present_backup_files_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(backup_dir.join("2023-06-15_model_serial")).unwrap();
+        fs::write(backup_dir.join("2023-06-15_model_serial.img"), "").unwrap();
+        fs::write(backup_dir.join("2023-06-16_model_serial.img"), "").unwrap();
+        fs::write(backup_dir.join("2023-06-17_model_serial.img.partial"), "").unwrap();
+        fs::write(backup_dir.join("2023-06-16_model_other.img"), "").unwrap();
+        fs::write(backup_dir.join("2023-06-16_tag_model_serial.img"), "").unwrap();
+        let backup_dir_path = backup_dir.to_str().unwrap();
+
+        let count = |pattern: &str, method: BackupMethod| {
+            filesystem.present_number_of_copies(pattern, backup_dir_path, method)
+        };
+        assert_eq!(count("{date}_model_serial.img", BackupMethod::Dd), 2);
+        assert_eq!(count("{date}_tag_model_serial.img", BackupMethod::Dd), 1);
+        assert_eq!(count("{date}_model_serial", BackupMethod::Rsync), 1);
+        assert_eq!(count("{date}_model_other", BackupMethod::Rsync), 0);
+
+        let expired = |max_age: Duration| {
+            filesystem
+                .expired_backups(
+                    "{date}_model_serial.img",
+                    backup_dir_path,
+                    BackupMethod::Dd,
+                    max_age,
+                )
+                .unwrap()
+        };
+        assert_eq!(expired(Duration::from_secs(24 * 60 * 60)).len(), 0);
+        assert_eq!(expired(Duration::ZERO).len(), 2);
+
+        fs::remove_dir_all(backup_dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_date() {
+        let date = NaiveDate::from_ymd_opt(2023, 6, 15);
+        assert_eq!(
+            backup_date("2023-06-15_model_serial.img", "{date}_model_serial.img"),
+            date
+        );
+        assert_eq!(
+            backup_date("host_2023-06-15_model.img", "host_{date}_model.img"),
+            date
+        );
+        assert_eq!(
+            backup_date("12-00-00_2023-06-15_model.img", "{time}_{date}_model.img"),
+            date
+        );
+        assert_eq!(backup_date("model.img", "model.img"), None);
+    }
+
+    #[test]
+    fn test_is_backup_of_series() {
+        assert!(is_backup_of_series(
+            "2023-06-15_model_serial.img",
+            "{date}_model_serial.img"
+        ));
+        assert!(is_backup_of_series(
+            "desktop-2023-06-15T12-30-00.img",
+            "desktop-{date}T{time}.img"
+        ));
+        assert!(!is_backup_of_series(
+            "2023-06-15_model_serial.img.partial",
+            "{date}_model_serial.img"
+        ));
+        assert!(!is_backup_of_series(
+            "2023-06-15_tag_model_serial.img",
+            "{date}_model_serial.img"
+        ));
+        assert!(!is_backup_of_series(
+            "copy_model_serial.img",
+            "{date}_model_serial.img"
+        ));
+        assert!(!is_backup_of_series("2023-06", "{date}"));
+        assert!(!is_backup_of_series(
+            "2023-13-45_model_serial.img",
+            "{date}_model_serial.img"
+        ));
+        assert!(!is_backup_of_series(
+            "----------_model_serial.img",
+            "{date}_model_serial.img"
+        ));
+        assert!(!is_backup_of_series(
+            "desktop-2023-06-15T25-00-00.img",
+            "desktop-{date}T{time}.img"
+        ));
+    }
+
+    #[test]
+    fn test_present_backup_files_of_overlapping_devices() {
+        let backup_dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_overlapping_devices_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&backup_dir).unwrap();
+        // the serial and model of the first device are prefixes of the ones of the second device
+        for file_name in [
+            "2023-06-15_Disk_SRC1.img",
+            "2023-06-15_Disk_SRC1.img.sha256",
+            "2023-06-15_Disk_SRC1.img.json",
+            "2023-06-16_Disk_SRC1.img",
+            "2023-06-15_Disk_SRC10.img",
+            "2023-06-15_Disk_Pro_SRC1.img",
+            "2023-06-16_Disk_Pro_SRC1.img",
+            "2023-06-17_Disk_Pro_SRC1.img",
+            "notes_about_Disk_SRC1.img.txt",
+            "old_2023-06-14_Disk_SRC1.img",
+        ] {
+            fs::write(backup_dir.join(file_name), "").unwrap();
+        }
+        let backup_dir_path = backup_dir.to_str().unwrap();
+        let filesystem = Filesystem::new(
+            &DestinationId::Uuid("uuid1".to_string()),
+            &BackupConfig::default(),
+            &generate_test_filesystems(),
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let count = |pattern: &str| {
+            filesystem.present_number_of_copies(pattern, backup_dir_path, BackupMethod::Dd)
+        };
+        assert_eq!(count("{date}_Disk_SRC1.img"), 2);
+        assert_eq!(count("{date}_Disk_SRC10.img"), 1);
+        assert_eq!(count("{date}_Disk_Pro_SRC1.img"), 3);
+        let oldest_backup = filesystem
+            .oldest_backup("{date}_Disk_SRC1.img", backup_dir_path, BackupMethod::Dd)
+            .unwrap()
+            .unwrap();
+        assert!(["2023-06-15_Disk_SRC1.img", "2023-06-16_Disk_SRC1.img"]
+            .contains(&oldest_backup.as_str()));
+        filesystem
+            .delete_backup(
+                backup_dir_path,
+                "2023-06-15_Disk_SRC10.img",
+                BackupMethod::Dd,
+            )
+            .unwrap();
+        assert_eq!(count("{date}_Disk_SRC10.img"), 0);
+        assert_eq!(count("{date}_Disk_SRC1.img"), 2);
+        assert_eq!(count("{date}_Disk_Pro_SRC1.img"), 3);
+
+        fs::remove_dir_all(backup_dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_mountpath_allowed() {
+        assert!(is_mountpath_allowed("/home", &None));
 
-        assert!(Filesystem::validate_uuid_uniq("uuid1", &filesystems).is_ok());
-        assert!(Filesystem::validate_uuid_uniq("uuid2", &filesystems).is_err());
-        assert!(Filesystem::validate_uuid_uniq("uuid3", &filesystems).is_ok()); // UUID not present
+        let allowed_mountpaths = Some(vec!["/mnt".to_string(), "/media/backup/".to_string()]);
+        assert!(is_mountpath_allowed("/mnt", &allowed_mountpaths));
+        assert!(is_mountpath_allowed("/mnt/", &allowed_mountpaths));
+        assert!(is_mountpath_allowed("/media/backup", &allowed_mountpaths));
+        assert!(!is_mountpath_allowed("/home", &allowed_mountpaths));
+        assert!(!is_mountpath_allowed("/mnt/nested", &allowed_mountpaths));
+        assert!(!is_mountpath_allowed("/mnt", &Some(vec![])));
     }
 }
@@ -0,0 +1,53 @@
+use super::command_output::command_output;
+
+/// Pings a healthchecks.io-style monitoring `url`, with the `path` appended, like `/start` or `/fail`.
+///
+/// The request is sent with `curl`, limited to a few seconds. A failing ping is only logged as a
+/// warning, since the monitoring must never fail the backup itself.
+pub fn ping(url: &str, path: &str) {
+    let ping_url = ping_url(url, path);
+    match command_output(
+        vec![
+            "curl",
+            "-fsS",
+            "-m",
+            "10",
+            "--retry",
+            "2",
+            "-o",
+            "/dev/null",
+            &ping_url,
+        ],
+        "ping healthcheck",
+        Some(false),
+    ) {
+        Ok(_) => debug!("Pinged healthcheck {}", ping_url),
+        Err(e) => warn!("Failed to ping healthcheck {}: {}", ping_url, e),
+    }
+}
+
+/// Appends `path` to `url`, without doubling the slash between them.
+fn ping_url(url: &str, path: &str) -> String {
+    format!("{}{}", url.trim_end_matches('/'), path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_url() {
+        assert_eq!(
+            ping_url("https://hc-ping.com/uuid", "/start"),
+            "https://hc-ping.com/uuid/start"
+        );
+        assert_eq!(
+            ping_url("https://hc-ping.com/uuid/", "/fail"),
+            "https://hc-ping.com/uuid/fail"
+        );
+        assert_eq!(
+            ping_url("https://hc-ping.com/uuid/", ""),
+            "https://hc-ping.com/uuid"
+        );
+    }
+}
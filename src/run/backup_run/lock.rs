@@ -0,0 +1,96 @@
+use std::{
+    fs::{File, OpenOptions},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+};
+
+use crate::run::error::BackupError;
+
+/// The dir holding the lock files, shared by all users, falling back to the temp dir if it is missing.
+const LOCK_DIR: &str = "/run/lock";
+
+/// An exclusive advisory lock on a destination filesystem, held until it is dropped.
+///
+/// The lock is a `flock` on the lock file, which the kernel releases when the file is closed,
+/// also if the process crashes, so a lock file left behind never blocks a later run.
+#[derive(Debug)]
+pub struct DestinationLock {
+    /// The open lock file, closing it releases the lock.
+    _file: File,
+}
+
+impl DestinationLock {
+    /// Acquires the lock of the destination filesystem with `uuid`, without waiting for it.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(DestinationLock)`: The acquired lock.
+    /// - `Err(BackupError)`: If another process holds the lock, or the lock file couldn't be opened.
+    pub fn acquire(uuid: &str) -> Result<DestinationLock, BackupError> {
+        let lock_file_path = lock_file_path(uuid);
+        let lock_file_path_str = lock_file_path.display().to_string();
+        // a lock file created by another user can't be opened for writing, but locked read-only
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_file_path)
+            .or_else(|_| File::open(&lock_file_path))
+            .map_err(BackupError::io(format!(
+                "Failed to open lock file {}",
+                lock_file_path_str
+            )))?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {
+                debug!("Locked {}", lock_file_path_str);
+                Ok(DestinationLock { _file: file })
+            }
+            Err(Errno::EWOULDBLOCK) => Err(BackupError::AlreadyRunning {
+                uuid: uuid.to_string(),
+                lock_file_path: lock_file_path_str,
+            }),
+            Err(e) => Err(BackupError::Other(format!(
+                "Failed to lock {}: {}",
+                lock_file_path_str, e
+            ))),
+        }
+    }
+}
+
+/// Returns the path of the lock file of the destination filesystem with `uuid`.
+fn lock_file_path(uuid: &str) -> PathBuf {
+    let lock_dir = match Path::new(LOCK_DIR).is_dir() {
+        true => PathBuf::from(LOCK_DIR),
+        false => std::env::temp_dir(),
+    };
+    lock_dir.join(format!("dd_backup_{}.lock", uuid.replace('/', "_")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let uuid = format!("test-lock-{}", std::process::id());
+
+        let lock = DestinationLock::acquire(&uuid).unwrap();
+        assert!(matches!(
+            DestinationLock::acquire(&uuid),
+            Err(BackupError::AlreadyRunning { .. })
+        ));
+        drop(lock);
+        let lock = DestinationLock::acquire(&uuid).unwrap();
+        drop(lock);
+
+        fs::remove_file(lock_file_path(&uuid)).unwrap();
+    }
+}
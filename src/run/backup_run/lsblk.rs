@@ -1,6 +1,14 @@
-use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env};
+
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json;
 
+use crate::run::{
+    config::Config,
+    error::BackupError,
+    utils::{convert_to_byte_size, find_executable},
+};
+
 use super::command_output::command_output;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -11,14 +19,75 @@ pub struct BlockDevice {
     pub model: Option<String>,
     /// The serial number of the block device.
     pub serial: Option<String>,
+    /// The world wide name of the block device, stable even if the serial is missing or duplicated.
+    #[serde(default)]
+    pub wwn: Option<String>,
+    /// The UUID of the partition in the partition table, only present for partitions.
+    #[serde(default)]
+    pub partuuid: Option<String>,
+    /// The label of the filesystem on the block device.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The name of the parent device, the drive holding a partition.
+    #[serde(default)]
+    pub pkname: Option<String>,
     /// The UUID of the block device.
     pub uuid: Option<String>,
     /// The mount point of the block device.
     pub mountpoint: Option<String>,
-    /// The size of the block device.
-    pub size: String,
-    // The available size of the block device
-    pub fsavail: Option<String>,
+    /// The size of the block device in bytes.
+    #[serde(deserialize_with = "deserialize_byte_size")]
+    pub size: u64,
+    /// The available size of the filesystem in bytes, only present if mounted.
+    /// Since the snapshot is taken before mounting, `Filesystem::available_space` reads it with `statvfs` instead.
+    #[serde(default, deserialize_with = "deserialize_optional_byte_size")]
+    pub fsavail: Option<u64>,
+    /// The values of the additionally configured `lsblk_columns`.
+    #[serde(flatten)]
+    pub extra_columns: BTreeMap<String, serde_json::Value>,
+}
+
+/// The columns every `BlockDevice` is read with.
+const LSBLK_COLUMNS: &str =
+    "NAME,MODEL,SERIAL,WWN,PARTUUID,LABEL,PKNAME,SIZE,MOUNTPOINT,UUID,FSAVAIL";
+
+/// A size as printed by `lsblk`, a number of bytes with `--bytes`, but a string
+/// in the JSON output of older versions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LsblkSize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl LsblkSize {
+    /// Returns the size in bytes, parsing a string as digits or as human readable size like "3.7G".
+    fn into_bytes(self) -> Result<u64, String> {
+        match self {
+            LsblkSize::Bytes(bytes) => Ok(bytes),
+            LsblkSize::Text(text) => match text.trim().parse::<u64>() {
+                Ok(bytes) => Ok(bytes),
+                Err(_) => {
+                    convert_to_byte_size(&text)?.ok_or(format!("Unknown unit of size {}", text))
+                }
+            },
+        }
+    }
+}
+
+fn deserialize_byte_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    LsblkSize::deserialize(deserializer)?
+        .into_bytes()
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_byte_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    Option::<LsblkSize>::deserialize(deserializer)?
+        .map(LsblkSize::into_bytes)
+        .transpose()
+        .map_err(serde::de::Error::custom)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,13 +109,23 @@ impl Lsblk {
     ///
     /// It captures the output of the `lsblk` command, filters and stores the available devices
     /// and available filesystems.
+    /// The `lsblk` binary is taken from the `LSBLK` env var, the `lsblk_path` config value or `PATH`,
+    /// the configured `lsblk_columns` are requested additionally.
+    ///
+    /// A run takes a single snapshot, which isn't refreshed. Mounting and unmounting a destination
+    /// update its `mountpoint` in place, see `Filesystem::mount`.
     ///
     /// Returns:
     /// - `Ok(Lsblk)`: If the `lsblk` command was successful and the output was parsed correctly.
-    /// - `Err(String)`: If there was an error executing or parsing the `lsblk` command.
-    pub fn new() -> Result<Lsblk, String> {
-        let lsblk_output =
-            Self::capture_lsblk().map_err(|e| format!("Failed to read JSON from lsblk: {}", e))?;
+    /// - `Err(BackupError)`: If there was an error executing or parsing the `lsblk` command.
+    pub fn new(config: &Config) -> Result<Lsblk, BackupError> {
+        let lsblk_path = Self::lsblk_path(config)?;
+        let columns = std::iter::once(LSBLK_COLUMNS.to_string())
+            .chain(config.lsblk_columns.iter().flatten().cloned())
+            .collect::<Vec<String>>()
+            .join(",");
+        let lsblk_output = Self::capture_lsblk(&lsblk_path, &columns)
+            .map_err(|e| format!("Failed to read JSON from lsblk: {}", e))?;
 
         let available_devices = Self::available_devices(&lsblk_output);
         let available_filesystems = Self::available_filesystems(&lsblk_output);
@@ -59,12 +138,31 @@ impl Lsblk {
         Ok(lsblk)
     }
 
-    /// Filters and returns the available devices from the lsblk output.
+    /// Returns the path of the `lsblk` binary, validating that it exists.
+    /// A missing binary is reported with how to provide it, since it is the first external command run.
+    fn lsblk_path(config: &Config) -> Result<String, BackupError> {
+        let lsblk_path = env::var("LSBLK")
+            .ok()
+            .or(config.lsblk_path.clone())
+            .unwrap_or("lsblk".to_string());
+
+        find_executable(&lsblk_path)
+            .map(|path| path.to_string_lossy().to_string())
+            .ok_or(BackupError::Other(format!(
+                "lsblk binary not found: {}. Install util-linux, which provides it, or set its path with the `lsblk_path` config value or the LSBLK env var",
+                lsblk_path
+            )))
+    }
+
+    /// Filters and returns the available devices from the lsblk output, those with a serial or WWN
+    /// and partitions with a PARTUUID or label.
     fn available_devices(lsblk_output: &LsblkOutput) -> Vec<BlockDevice> {
         lsblk_output
             .blockdevices
             .iter()
-            .filter(|a| a.serial.is_some())
+            .filter(|a| {
+                a.serial.is_some() || a.wwn.is_some() || a.partuuid.is_some() || a.label.is_some()
+            })
             .cloned()
             .collect()
     }
@@ -80,18 +178,14 @@ impl Lsblk {
     }
 
     /// Executes the lsblk command and captures the output as a JSON string.
+    /// Sizes are printed in exact bytes (`-b`), to compare them without rounding errors.
     ///
     /// Returns:
     /// - `Ok(LsblkOutput)`: If the lsblk command was successful and the JSON output was parsed correctly.
-    /// - `Err(String)`: If there was an error executing or parsing the lsblk command.
-    fn capture_lsblk() -> Result<LsblkOutput, String> {
+    /// - `Err(BackupError)`: If there was an error executing or parsing the lsblk command.
+    fn capture_lsblk(lsblk_path: &str, columns: &str) -> Result<LsblkOutput, BackupError> {
         let output = command_output(
-            vec![
-                "lsblk",
-                "-lJ",
-                "-o",
-                "NAME,MODEL,SERIAL,SIZE,MOUNTPOINT,UUID,FSAVAIL",
-            ],
+            vec![lsblk_path, "-lJb", "-o", columns],
             "execute lsblk",
             Some(false),
         )?;
@@ -103,7 +197,38 @@ impl Lsblk {
 
             Ok(lsblk_output)
         } else {
-            Err("Execution of lsblk failed".to_string())
+            Err("Execution of lsblk failed".into())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_sizes() {
+        let lsblk_output: LsblkOutput = serde_json::from_str(
+            r#"{"blockdevices": [
+                {"name": "sda", "model": null, "serial": "serial1", "size": 3972844748, "mountpoint": null, "uuid": null, "fsavail": null},
+                {"name": "sdb", "model": null, "serial": "serial2", "size": "3972844748", "mountpoint": "/mnt", "uuid": "uuid", "fsavail": "1024"},
+                {"name": "sdc", "model": null, "serial": "serial3", "size": "3.7G", "mountpoint": null, "uuid": null}
+            ]}"#,
+        )
+        .unwrap();
+
+        let sizes: Vec<(u64, Option<u64>)> = lsblk_output
+            .blockdevices
+            .iter()
+            .map(|blockdevice| (blockdevice.size, blockdevice.fsavail))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (3972844748, None),
+                (3972844748, Some(1024)),
+                (3972844749, None)
+            ]
+        );
+    }
+}
@@ -1,23 +1,62 @@
-mod backup;
+pub(crate) mod backup;
 mod backups;
-mod command_output;
-mod device;
-mod filesystem;
-mod lsblk;
+pub(crate) mod command_output;
+pub(crate) mod device;
+pub(crate) mod filesystem;
+mod healthcheck;
+pub(crate) mod lock;
+pub(crate) mod lsblk;
+mod notification;
+mod plan;
+mod progress;
+mod rsync;
+mod signal;
+mod summary;
+mod webhook;
 
 use super::backup_run::backups::Backups;
+use super::backup_run::command_output::{set_print_commands_only, set_verbose_command};
 use super::backup_run::lsblk::Lsblk;
-use super::config::{BackupDevice, Config};
+use super::backup_run::plan::PlanEntry;
+use super::backup_run::summary::{OutputFormat, RunSummary};
+use super::config::{
+    validate_destination_path, BackupDevice, BackupMethod, Compression, Config, DestinationId,
+    FsckPolicy, OnComplete,
+};
 use crate::run::config::BackupConfig;
+use crate::run::error::{BackupError, Failure, EXIT_BACKUP_FAILED};
+use crate::run::utils::parse_bandwidth;
+
+use std::io::{self, IsTerminal};
 
 use clap::Args;
 
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Default)]
 pub struct BackupArgs {
     #[clap(short = 'n', long, default_value = "false")]
     /// Performs a dry run, simulating backup operations without making any changes.
     pub dry_run: bool,
 
+    #[clap(long)]
+    /// Only prints warnings, errors and a final summary table of all device backups.
+    pub summary_only: bool,
+
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with_all = ["summary_only", "plan", "print_commands_only"])]
+    /// The format of the result printed to stdout, `json` prints a single document summarizing all device backups.
+    pub output: OutputFormat,
+
+    #[clap(long)]
+    /// Logs every executed external command at debug level.
+    pub verbose_command: bool,
+
+    #[clap(long)]
+    /// Prints a table of what each device backup would do before running, exits after it on a dry run.
+    pub plan: bool,
+
+    #[clap(long, conflicts_with = "dry_run")]
+    /// Prints the commands the backup would execute, in order, without executing them or any other change.
+    pub print_commands_only: bool,
+
     #[clap(flatten)]
     /// Command-line arguments for file-based configuration.
     pub file_config_args: Option<FileConfigArgs>,
@@ -29,37 +68,156 @@ pub struct BackupArgs {
     #[clap(short, long)]
     /// The mount path of the destination filesystem, overwrites config value.
     pub mountpath: Option<String>,
+
+    #[clap(long)]
+    /// The path below the mount path the backups are stored in, like backups/{year}, overwrites config value.
+    pub destination_path: Option<String>,
+
+    #[clap(long)]
+    /// Continues a partial image of an interrupted `dd` backup, assuming the source didn't change.
+    pub resume: bool,
+
+    #[clap(long)]
+    /// Hashes verified `dd` images while writing them, instead of reading the image and source again afterwards.
+    pub parallel_checksum: bool,
+
+    #[clap(long)]
+    /// A label inserted into the names of this run's backups, which are kept as a separate series of copies.
+    pub tag: Option<String>,
+
+    #[clap(long, alias = "mount-timeout")]
+    /// The maximum number of seconds mounting, unmounting or checking a destination may take, no limit by default.
+    pub command_timeout: Option<u64>,
+
+    #[clap(long)]
+    /// The maximum number of seconds a `dd` backup may take, no limit by default.
+    pub dd_timeout: Option<u64>,
+
+    #[clap(long)]
+    /// Continues with the next destination if one fails, exits with an error at the end.
+    pub keep_going: bool,
+
+    #[clap(long)]
+    /// How often a failing `dd` is retried with a short backoff, overwrites config value.
+    pub retries: Option<u32>,
+
+    #[clap(long, alias = "max-bandwidth", value_parser = parse_bandwidth)]
+    /// The bandwidth `dd` images are written with at most, like 50M per second, with `pv`, overwrites config value.
+    pub rate_limit: Option<u64>,
+
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    /// The maximum number of devices backed up to a destination at the same time.
+    pub jobs: u64,
+
+    #[clap(long)]
+    /// Skips the check for enough free space on all destinations, overwrites config value.
+    pub skip_space_check: bool,
+
+    #[clap(long)]
+    /// Backs up to destinations whose filesystem check (`fsck`) fails, like the `fsck_policy` `warn`.
+    pub force: bool,
+
+    #[clap(long)]
+    /// The path of the SQLite catalog recording completed backups, overwrites config value.
+    pub catalog_path: Option<String>,
+
+    #[clap(long)]
+    /// The URL of a healthchecks.io-style monitor pinged on start, success and failure, overwrites config value.
+    pub healthcheck_url: Option<String>,
+
+    #[clap(long)]
+    /// The path of a JSON-lines file recording the outcome of every backup, overwrites config value.
+    pub history_file: Option<String>,
+
+    #[clap(long)]
+    /// Only backs up the configured devices with this serial (or other identifier), repeatable.
+    pub only_serial: Vec<String>,
+
+    #[clap(long)]
+    /// Skips the configured devices with this serial (or other identifier), repeatable.
+    pub exclude_serial: Vec<String>,
+
+    #[clap(long)]
+    /// Asks for confirmation before deleting each old backup to keep the configured copies, if stdin is a terminal.
+    pub interactive: bool,
+
+    #[clap(short, long)]
+    /// Deletes old backups and overwrites present ones without asking for confirmation.
+    pub yes: bool,
+
+    #[clap(long)]
+    /// Fails instead of skipping devices whose size differs from their `expected_size`, and destinations whose UUID isn't unique.
+    pub strict: bool,
+
+    #[clap(long)]
+    /// Overwrites a present backup of today instead of skipping the device, after confirmation unless `--yes`.
+    pub overwrite: bool,
 }
 
-#[derive(Args, Debug, Clone)]
+impl BackupArgs {
+    /// Returns whether the configured device with `identifier` is selected by `--only-serial` and `--exclude-serial`.
+    pub fn is_device_selected(&self, identifier: &str) -> bool {
+        is_selected(identifier, &self.only_serial, &self.exclude_serial)
+    }
+
+    /// Returns whether the deletion of old backups has to be confirmed interactively.
+    ///
+    /// Only asks with `--interactive` and without `--yes` if stdin is a terminal,
+    /// so runs from cron or systemd keep deleting automatically.
+    pub fn confirms_deletions(&self) -> bool {
+        self.interactive && !self.yes && io::stdin().is_terminal()
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
 pub struct FileConfigArgs {
     #[clap(short, long, group = "file-config-args")]
-    /// The path to the configuration file.
+    /// The path to the configuration file, `-` reads it as JSON from stdin.
     pub config_file_path: Option<String>,
+
+    #[clap(long, group = "file-config-args")]
+    /// The directory of configuration files, whose `*.json` files are merged, with the top-level fields of `main.json`.
+    pub config_dir: Option<String>,
 }
 
-#[derive(Args, Debug, Clone)]
+#[derive(Args, Debug, Clone, Default)]
 pub struct SingleBackupArgs {
     #[clap(long, conflicts_with = "file-config-args")]
     /// The UUID of the destination backup filesystem or partition, single-back-up-only.
     pub destination_uuid: Option<String>,
 
     #[clap(long)]
-    /// The serial number of the source device to be backed up, single-back-up-only.
-    pub source_serial: Option<String>,
+    /// The UUID of a further destination filesystem receiving the same backup, single-back-up-only.
+    pub mirror_uuid: Vec<String>,
 
-    #[clap(long, default_value = "./")]
-    /// The destination path where the backup will be stored, single-back-up-only.
-    pub destination_path: Option<String>,
+    #[clap(long)]
+    /// The serial number, or WWN, of the source device to be backed up, single-back-up-only.
+    pub source_serial: Option<String>,
 
     #[clap(long, default_value = None)]
     /// The number of backup copies to maintain, single-back-up-only.
     pub copies: Option<usize>,
 
+    #[clap(long)]
+    /// The maximum age of backups in days, older ones are deleted, single-back-up-only.
+    pub max_age_days: Option<u64>,
+
+    #[clap(long)]
+    /// The size the source device is expected to have, like 500GB, it is skipped otherwise, single-back-up-only.
+    pub expected_size: Option<String>,
+
     #[clap(long)]
     /// The name of the backup, single-back-up-only.
     pub name: Option<String>,
 
+    #[clap(long, value_enum)]
+    /// The method used to back up the device, `dd` images or `rsync` file copies, single-back-up-only.
+    pub method: Option<BackupMethod>,
+
+    #[clap(long)]
+    /// Flag to evict the written image from the page cache, single-back-up-only.
+    pub drop_cache: bool,
+
     #[clap(long, default_value = "fsck -n")]
     /// Alternative command to perform filesystem check (`fsck -n`), single-back-up-only.
     pub fsck_command: String,
@@ -68,9 +226,73 @@ pub struct SingleBackupArgs {
     /// Flag to skip filesystem check (`fsck`), single-back-up-only.
     pub skip_fsck: bool,
 
+    #[clap(long, value_enum)]
+    /// What to do when the filesystem check (`fsck`) fails, single-back-up-only.
+    pub fsck_policy: Option<FsckPolicy>,
+
     #[clap(long)]
     /// Flag to skip mounting, single-back-up-only.
     pub skip_mount: bool,
+
+    #[clap(long)]
+    /// The options to mount the destination filesystem with, passed as `mount -o`, single-back-up-only.
+    pub mount_options: Option<String>,
+
+    #[clap(long)]
+    /// The type of the destination filesystem, passed as `mount -t`, single-back-up-only.
+    pub mount_type: Option<String>,
+
+    #[clap(long)]
+    /// Flag to skip syncing the backup image and destination directory after writing, single-back-up-only.
+    pub no_durable: bool,
+
+    #[clap(long, value_enum)]
+    /// The compressor `dd` images are piped through, single-back-up-only.
+    pub compression: Option<Compression>,
+
+    #[clap(long, conflicts_with = "compression")]
+    /// Flag to write sparse images, skipping zero blocks with `conv=sparse`, single-back-up-only.
+    pub sparse: bool,
+
+    #[clap(long)]
+    /// Flag to verify images against the source device with SHA-256 after writing, single-back-up-only.
+    pub verify: bool,
+
+    #[clap(long)]
+    /// Flag to skip the backup if the source has the checksum of its most recent backup, single-back-up-only.
+    pub skip_if_unchanged: bool,
+
+    #[clap(long)]
+    /// The block size `dd` reads and writes with, like 4M or 1MiB, defaults to 4M, single-back-up-only.
+    pub block_size: Option<String>,
+
+    #[clap(long)]
+    /// The space which has to remain free after the backup, like 10G or 5%, single-back-up-only.
+    pub space_headroom: Option<String>,
+
+    #[clap(long)]
+    /// An operand appended to the dd command, like conv=noerror,sync, repeatable, single-back-up-only.
+    pub dd_option: Vec<String>,
+
+    #[clap(long)]
+    /// The template of backup file names, like {date}_{name}_{serial}, single-back-up-only.
+    pub name_template: Option<String>,
+
+    #[clap(long)]
+    /// Flag to include the time after the date in backup file names, single-back-up-only.
+    pub include_time: bool,
+
+    #[clap(long, alias = "pre-command")]
+    /// A shell command run before the destination is mounted, single-back-up-only.
+    pub pre_mount: Option<String>,
+
+    #[clap(long, alias = "post-command")]
+    /// A shell command run after the destination is unmounted, single-back-up-only.
+    pub post_unmount: Option<String>,
+
+    #[clap(long, value_enum)]
+    /// What to do with the destination drive after the backup succeeded, single-back-up-only.
+    pub on_complete: Option<OnComplete>,
 }
 
 /// Runs the backup process based on the provided command-line arguments.
@@ -85,19 +307,253 @@ pub struct SingleBackupArgs {
 ///
 /// # Returns
 ///
-/// An `Ok` variant if the backup process completes successfully, or an `Err` variant with an error message as `String`
-/// if an error occurs during the backup process.
-pub fn run(backup_args: &BackupArgs) -> Result<(), String> {
+/// An `Ok` variant if the backup process completes successfully, or an `Err` variant with the `Failure`
+/// if an error occurs during the backup process or a device backup failed, with the exit code of the first failure.
+pub fn run(backup_args: &BackupArgs) -> Result<(), Failure> {
+    set_verbose_command(backup_args.verbose_command);
+    let max_level = log::max_level();
+    if backup_args.summary_only || backup_args.print_commands_only {
+        log::set_max_level(max_level.min(log::LevelFilter::Warn));
+    }
+
+    let mut run_summary = RunSummary::default();
+    let result = run_backups(backup_args, &mut run_summary);
+
+    set_print_commands_only(false);
+    if backup_args.dry_run && backup_args.output == OutputFormat::Text {
+        println!("{}", run_summary.dry_run_table());
+    } else if backup_args.summary_only {
+        println!("{}", run_summary.table());
+    }
+    let error = result.as_ref().err().map(|failure| &failure.message);
+    if backup_args.output == OutputFormat::Json {
+        println!("{}", run_summary.json(error));
+    }
+    log::set_max_level(max_level);
+    result?;
+    match run_summary.exit_code {
+        Some(exit_code) => Err(Failure {
+            message: format!("{} device backup(s) failed", run_summary.failures()),
+            exit_code,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Runs the backups of all configured destinations, adding the outcome of each device backup to `run_summary`.
+///
+/// With `--plan` a table of what each device backup would do is printed first, a dry run ends after it.
+/// With `--keep-going` the error of a destination is logged and the others are still backed up,
+/// an error listing the failed destinations is returned at the end. The error of a mirror is always handled so.
+/// At the end, the configured healthcheck is pinged, the email notification is sent and the webhook is posted,
+/// except on simulated runs.
+fn run_backups(backup_args: &BackupArgs, run_summary: &mut RunSummary) -> Result<(), Failure> {
     let config = backup_args_to_config(backup_args)?;
-    let lsblk = Lsblk::new()?;
 
+    // simulated runs are neither reported to the monitor nor notified about
+    let is_simulated = backup_args.dry_run || backup_args.print_commands_only;
+    let healthcheck_url = backup_args
+        .healthcheck_url
+        .clone()
+        .or(config.healthcheck_url.clone())
+        .filter(|_| !is_simulated);
+    if let Some(healthcheck_url) = &healthcheck_url {
+        healthcheck::ping(healthcheck_url, "/start");
+    }
+
+    let result = run_configured_backups(backup_args, &config, run_summary);
+    let error = result.as_ref().err().map(|failure| &failure.message);
+
+    if let Some(healthcheck_url) = &healthcheck_url {
+        match result.is_ok() && !run_summary.has_failures() {
+            true => healthcheck::ping(healthcheck_url, ""),
+            false => healthcheck::ping(healthcheck_url, "/fail"),
+        }
+    }
+    if let Some(email) = config
+        .notification
+        .as_ref()
+        .and_then(|notification| notification.email.as_ref())
+        .filter(|_| !is_simulated)
+    {
+        notification::send_email(email, run_summary, error);
+    }
+    if let Some(webhook_url) = config.webhook_url.as_ref().filter(|_| !is_simulated) {
+        webhook::post(
+            webhook_url,
+            config.webhook_token.as_deref(),
+            config.webhook_timeout,
+            run_summary,
+            error,
+        );
+    }
+    result
+}
+
+/// Runs the backups of all destinations configured in `config`, see `run_backups`.
+fn run_configured_backups(
+    backup_args: &BackupArgs,
+    config: &Config,
+    run_summary: &mut RunSummary,
+) -> Result<(), Failure> {
+    let lsblk = Lsblk::new(config)?;
+    signal::register_handler()?;
+    // `lsblk` only reads, it runs in any case to plan against the attached devices
+    set_print_commands_only(backup_args.print_commands_only);
+
+    for only_serial in &backup_args.only_serial {
+        if !config
+            .backups
+            .iter()
+            .flat_map(|backup_config| &backup_config.backup_devices)
+            .any(|backup_device| &backup_device.identifier() == only_serial)
+        {
+            warn!(
+                "--only-serial {} matches no configured device, nothing is backed up for it",
+                only_serial
+            );
+        }
+    }
+
+    let mut planned_backups = Vec::new();
+    let mut failed_destinations = Vec::new();
     for backup_config in &config.backups {
-        if let Some(backups) = Backups::new(backup_config, &lsblk, backup_args, &config)? {
-            backups.run()?;
+        if !backup_config.is_enabled() {
+            info!(
+                "Backup with {} is disabled, skipping it",
+                backup_config.destination_id()
+            );
+            continue;
+        }
+        if !backup_config
+            .backup_devices
+            .iter()
+            .any(|backup_device| backup_args.is_device_selected(&backup_device.identifier()))
+        {
+            info!(
+                "Backup with {} has no device selected by --only-serial or --exclude-serial, skipping it",
+                backup_config.destination_id()
+            );
+            continue;
+        }
+        for destination in backup_config.all_destination_ids() {
+            // all devices of the backup may be backed up to their own destinations
+            if !backup_config.backup_devices.iter().any(|backup_device| {
+                backup_device.is_backed_up_to(&destination, backup_config)
+                    && backup_args.is_device_selected(&backup_device.identifier())
+            }) {
+                continue;
+            }
+            // a failing mirror never stops the other destinations, other ones only with `--keep-going`
+            let is_mirror = backup_config.is_mirror(&destination);
+            let backups =
+                match Backups::new(&destination, backup_config, &lsblk, backup_args, config) {
+                    Ok(Some(backups)) => backups,
+                    Ok(None) if destination != backup_config.destination_id() => {
+                        warn!(
+                            "{} {} of backup with {} is not attached, skipping it",
+                            backup_config.destination_kind(&destination),
+                            destination,
+                            backup_config.destination_id()
+                        );
+                        continue;
+                    }
+                    Ok(None) => continue,
+                    Err(e) if backup_args.keep_going || is_mirror => {
+                        error!(
+                            "Error preparing backups to {}, continuing: {}",
+                            destination, e
+                        );
+                        failed_destinations.push(destination.to_string());
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+            match backup_args.plan {
+                // the plan of all destinations is printed before any of them is backed up
+                true => planned_backups.push((backups, is_mirror)),
+                false => run_destination(
+                    backups,
+                    is_mirror,
+                    backup_args,
+                    run_summary,
+                    &mut failed_destinations,
+                )?,
+            }
+        }
+    }
+
+    if backup_args.plan {
+        let entries: Vec<PlanEntry> = planned_backups
+            .iter()
+            .flat_map(|(backups, _)| backups.plan())
+            .collect();
+        println!("{}", plan::table(&entries));
+        if backup_args.dry_run {
+            return Ok(());
+        }
+        for (backups, is_mirror) in planned_backups {
+            run_destination(
+                backups,
+                is_mirror,
+                backup_args,
+                run_summary,
+                &mut failed_destinations,
+            )?;
         }
     }
 
-    Ok(())
+    if failed_destinations.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure {
+            message: format!(
+                "Backups to {} destination(s) failed: {}",
+                failed_destinations.len(),
+                failed_destinations.join(", ")
+            ),
+            exit_code: EXIT_BACKUP_FAILED,
+        })
+    }
+}
+
+/// Runs the prepared `backups` to a destination, adding the outcome of each device backup to `run_summary`.
+///
+/// The error of a mirror, or of any destination with `--keep-going`, is logged and the UUID of the
+/// destination is added to `failed_destinations`, so the next destinations are still backed up.
+/// Otherwise the error ends the run with the exit code of `BackupError::backup_exit_code`.
+fn run_destination(
+    backups: Backups,
+    is_mirror: bool,
+    backup_args: &BackupArgs,
+    run_summary: &mut RunSummary,
+    failed_destinations: &mut Vec<String>,
+) -> Result<(), Failure> {
+    let destination = DestinationId::Uuid(
+        backups
+            .dst_filesystem
+            .blockdevice
+            .uuid
+            .clone()
+            .unwrap_or_default(),
+    );
+    match backups.run(run_summary) {
+        Err(e) if backup_args.keep_going || is_mirror => {
+            error!(
+                "Error running backups to {}, continuing: {}",
+                destination, e
+            );
+            failed_destinations.push(destination.to_string());
+            Ok(())
+        }
+        result => result.map_err(Failure::backup),
+    }
+}
+
+/// Returns whether `identifier` is one of the `only_serials`, if any are given, and none of the `exclude_serials`.
+fn is_selected(identifier: &str, only_serials: &[String], exclude_serials: &[String]) -> bool {
+    (only_serials.is_empty() || only_serials.iter().any(|serial| serial == identifier))
+        && !exclude_serials.iter().any(|serial| serial == identifier)
 }
 
 /// Converts `BackupArgs` into a `Config` object.
@@ -113,41 +569,91 @@ pub fn run(backup_args: &BackupArgs) -> Result<(), String> {
 ///
 /// # Returns
 ///
-/// A `Result` containing the resulting `Config` object if the conversion is successful, or an error message as `String`
-/// if an error occurs during the conversion.
-fn backup_args_to_config(backup_args: &BackupArgs) -> Result<Config, String> {
+/// A `Result` containing the resulting `Config` object if the conversion is successful, or the `Failure`
+/// with the exit code of an invalid config if an error occurs during the conversion.
+fn backup_args_to_config(backup_args: &BackupArgs) -> Result<Config, Failure> {
     let config: Config = match &backup_args.file_config_args {
-        Some(file_config_args) => Config::new(&file_config_args.config_file_path),
+        Some(file_config_args) => match &file_config_args.config_dir {
+            Some(config_dir) => Config::from_dir(config_dir),
+            None => Config::new(&file_config_args.config_file_path),
+        },
         None => match &backup_args.single_backup_args {
             Some(single_backup_args) => {
                 let source_serial = single_backup_args.source_serial.clone().ok_or(
-                    "Source serial needs to be provided in single backup mode, like: `--source-serial x...x`",
+                    BackupError::InvalidConfig("Source serial needs to be provided in single backup mode, like: `--source-serial x...x`".to_string()),
                 )?;
                 let destination_uuid = single_backup_args.destination_uuid.clone().ok_or(
-                    "Destination UUID needs to be provided in single backup mode, like: `--destination-uuid x...x`",
+                    BackupError::InvalidConfig("Destination UUID needs to be provided in single backup mode, like: `--destination-uuid x...x`".to_string()),
                 )?;
 
                 let config = Config {
                     mountpath: Some(backup_args.mountpath.clone().unwrap_or("/mnt".to_string())),
                     backups: vec![BackupConfig {
                         backup_devices: vec![BackupDevice {
-                            serial: source_serial,
+                            serial: Some(source_serial),
+                            wwn: None,
+                            partuuid: None,
+                            partlabel: None,
+                            device_match: None,
                             name: single_backup_args.name.clone(),
                             copies: single_backup_args.copies,
+                            max_age_days: single_backup_args.max_age_days,
+                            method: single_backup_args.method,
+                            drop_cache: Some(single_backup_args.drop_cache),
+                            enabled: None,
+                            expected_size: single_backup_args.expected_size.clone(),
+                            destination_uuid: None,
                         }],
                         uuid: destination_uuid,
-                        destination_path: single_backup_args.destination_path.clone(),
+                        label: None,
+                        mirrors: Some(single_backup_args.mirror_uuid.clone()),
+                        destination_path: Some(
+                            backup_args
+                                .destination_path
+                                .clone()
+                                .unwrap_or("./".to_string()),
+                        ),
                         fsck_command: Some(single_backup_args.fsck_command.clone()),
                         skip_fsck: Some(single_backup_args.skip_fsck || single_backup_args.skip_mount),
                         skip_mount: Some(single_backup_args.skip_mount),
-                    }]
+                        mountpath: None,
+                        mount_options: single_backup_args.mount_options.clone(),
+                        mount_type: single_backup_args.mount_type.clone(),
+                        fsck_policy: single_backup_args.fsck_policy,
+                        durable: Some(!single_backup_args.no_durable),
+                        skip_space_check: None,
+                        retries: None,
+                        compression: single_backup_args.compression,
+                        sparse: Some(single_backup_args.sparse),
+                        verify: Some(single_backup_args.verify),
+                        skip_if_unchanged: Some(single_backup_args.skip_if_unchanged),
+                        block_size: single_backup_args.block_size.clone(),
+                        max_bandwidth: None,
+                        space_headroom: single_backup_args.space_headroom.clone(),
+                        dd_options: match single_backup_args.dd_option.is_empty() {
+                            true => None,
+                            false => Some(single_backup_args.dd_option.clone()),
+                        },
+                        name_template: single_backup_args.name_template.clone(),
+                        include_time: Some(single_backup_args.include_time),
+                        pre_mount: single_backup_args.pre_mount.clone(),
+                        post_unmount: single_backup_args.post_unmount.clone(),
+                        on_complete: single_backup_args.on_complete,
+                        enabled: None,
+                    }],
+                    ..Default::default()
                 };
                 Config::validate_config(Ok(config))
             },
             None => Config::new(&None),
         },
     }
-    .map_err(|e| format!("Failed to create Config struct object: {}", e))?;
+    .map_err(|e| Failure::from(e).context("Failed to create Config struct object"))?;
+
+    if let Some(destination_path) = &backup_args.destination_path {
+        validate_destination_path(destination_path, "`--destination-path`")
+            .map_err(BackupError::InvalidConfig)?;
+    }
 
     Ok(config)
 }
@@ -155,68 +661,148 @@ fn backup_args_to_config(backup_args: &BackupArgs) -> Result<Config, String> {
 #[cfg(test)]
 mod tests {
     use crate::run::backup_run::{FileConfigArgs, SingleBackupArgs};
+    use crate::run::error::EXIT_CONFIG;
 
     use super::*;
 
+    #[test]
+    fn test_is_selected() {
+        let serials = |serials: &[&str]| -> Vec<String> {
+            serials.iter().map(|serial| serial.to_string()).collect()
+        };
+        assert!(is_selected("a", &[], &[]));
+        assert!(is_selected("a", &serials(&["a", "b"]), &[]));
+        assert!(!is_selected("c", &serials(&["a", "b"]), &[]));
+        assert!(!is_selected("a", &[], &serials(&["a"])));
+        assert!(!is_selected("a", &serials(&["a"]), &serials(&["a"])));
+    }
+
+    #[test]
+    fn test_run_destination() {
+        let uuid = "dd-backup-test-run-destination";
+        let lsblk: Lsblk = serde_json::from_value(serde_json::json!({
+            "available_devices": [
+                {"name": "sdz", "model": "Disk", "serial": "SRC1", "uuid": null, "mountpoint": null, "size": 1000}
+            ],
+            "available_filesystems": [
+                {"name": "sdy1", "model": null, "serial": null, "uuid": uuid, "mountpoint": null, "size": 1000}
+            ]
+        }))
+        .unwrap();
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "backups": [{"uuid": uuid, "backup_devices": [{"serial": "SRC1"}], "skip_mount": true}]
+        }))
+        .unwrap();
+
+        // the destination isn't mounted, but skip_mount is set
+        let backup_args = BackupArgs {
+            jobs: 1,
+            ..Default::default()
+        };
+        let backups = Backups::new(
+            &config.backups[0].destination_id(),
+            &config.backups[0],
+            &lsblk,
+            &backup_args,
+            &config,
+        )
+        .unwrap()
+        .unwrap();
+        let mut failed_destinations = Vec::new();
+        let result = run_destination(
+            backups,
+            false,
+            &backup_args,
+            &mut RunSummary::default(),
+            &mut failed_destinations,
+        );
+        assert_eq!(
+            result,
+            Err(Failure {
+                message: "Filesystem /dev/sdy1 is not mounted, but skip_mount is set".to_string(),
+                exit_code: EXIT_BACKUP_FAILED,
+            })
+        );
+
+        let backup_args = BackupArgs {
+            keep_going: true,
+            jobs: 1,
+            ..Default::default()
+        };
+        let backups = Backups::new(
+            &config.backups[0].destination_id(),
+            &config.backups[0],
+            &lsblk,
+            &backup_args,
+            &config,
+        )
+        .unwrap()
+        .unwrap();
+        let result = run_destination(
+            backups,
+            false,
+            &backup_args,
+            &mut RunSummary::default(),
+            &mut failed_destinations,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(failed_destinations, vec![format!("UUID {}", uuid)]);
+    }
+
     #[test]
     fn test_run() {
         let valid_single_backup_args = SingleBackupArgs {
             destination_uuid: Some("some-uuid-which-does-not-exist".to_string()),
-            destination_path: None,
             source_serial: Some("some-source-serial-which-does-not-exist".to_string()),
-            copies: None,
-            name: None,
             fsck_command: "fsck -n".to_string(),
-            skip_fsck: false,
-            skip_mount: false,
+            ..Default::default()
         };
 
         let invalid_single_backup_args = SingleBackupArgs {
-            destination_uuid: None,
-            destination_path: None,
-            source_serial: None,
-            copies: None,
-            name: None,
             fsck_command: "fsck -n".to_string(),
-            skip_fsck: false,
-            skip_mount: false,
+            ..Default::default()
         };
         // Test when the command is `Run` and backup_run returns Ok(())
         let backup_args = BackupArgs {
-            dry_run: false,
-            file_config_args: None,
             single_backup_args: Some(valid_single_backup_args),
-            mountpath: None,
+            jobs: 1,
+            ..Default::default()
         };
         let result = run(&backup_args);
         assert_eq!(result, Ok(()));
 
         // Test when config is not found
         let backup_args = BackupArgs {
-            dry_run: false, /* initialize backup_args with appropriate values */
             file_config_args: Some(FileConfigArgs {
                 config_file_path: Some("/does/not/exist.json".to_string()),
+                config_dir: None,
             }),
             single_backup_args: Some(invalid_single_backup_args.clone()),
-            mountpath: None,
+            jobs: 1,
+            ..Default::default()
         };
         let result = run(&backup_args);
         assert_eq!(
             result,
-            Err("Failed to create Config struct object: No such file or directory (os error 2): /does/not/exist.json".to_string())
+            Err(Failure {
+                message: "Failed to create Config struct object: No such file or directory (os error 2): /does/not/exist.json".to_string(),
+                exit_code: EXIT_CONFIG,
+            })
         );
 
         // Test when using invalid single_backup_args
         let backup_args = BackupArgs {
-            dry_run: false, /* initialize backup_args with appropriate values */
-            file_config_args: None,
             single_backup_args: Some(invalid_single_backup_args),
-            mountpath: None,
+            jobs: 1,
+            ..Default::default()
         };
         let result = run(&backup_args);
         assert_eq!(
             result,
-            Err("Source serial needs to be provided in single backup mode, like: `--source-serial x...x`".to_string())
+            Err(Failure {
+                message: "Source serial needs to be provided in single backup mode, like: `--source-serial x...x`".to_string(),
+                exit_code: EXIT_CONFIG,
+            })
         );
     }
 }
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    Message, SmtpTransport, Transport,
+};
+
+use crate::run::config::{EmailNotification, SmtpTls};
+
+use super::summary::RunSummary;
+
+/// Sends an email with the outcome of every backup in `run_summary` and the `error` which ended the run, if any.
+///
+/// A failing email is only logged as a warning, since the notification must never fail the backup itself.
+pub fn send_email(email: &EmailNotification, run_summary: &RunSummary, error: Option<&String>) {
+    let (subject, body) = email_text(run_summary, error);
+    match try_send_email(email, subject, body) {
+        Ok(()) => info!("Sent email notification to {}", email.to.join(", ")),
+        Err(e) => warn!("Failed to send email notification: {}", e),
+    }
+}
+
+/// Builds the email and sends it to the SMTP server, see `send_email`.
+fn try_send_email(email: &EmailNotification, subject: String, body: String) -> Result<(), String> {
+    let parse_mailbox = |address: &String| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid email address '{}': {}", address, e))
+    };
+    let mut message_builder = Message::builder()
+        .from(parse_mailbox(&email.from)?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN);
+    for to in &email.to {
+        message_builder = message_builder.to(parse_mailbox(to)?);
+    }
+    let message = message_builder
+        .body(body)
+        .map_err(|e| format!("Failed to build email: {}", e))?;
+
+    let tls = email.tls.unwrap_or_default();
+    let transport_builder = match tls {
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(&email.smtp_host),
+        SmtpTls::Tls => SmtpTransport::relay(&email.smtp_host),
+        SmtpTls::None => Ok(SmtpTransport::builder_dangerous(&email.smtp_host)),
+    }
+    .map_err(|e| format!("Failed to connect to {}: {}", email.smtp_host, e))?
+    .port(email.smtp_port.unwrap_or(tls.default_port()))
+    .timeout(Some(Duration::from_secs(30)));
+    let transport = match (&email.username, &email.password) {
+        (Some(username), Some(password)) => transport_builder
+            .credentials(Credentials::new(username.clone(), password.clone()))
+            .build(),
+        _ => transport_builder.build(),
+    };
+
+    transport
+        .send(&message)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to send email via {}: {}", email.smtp_host, e))
+}
+
+/// Returns the subject and body of the email, telling whether the run succeeded,
+/// followed by the error which ended it, if any, and the table of backups.
+fn email_text(run_summary: &RunSummary, error: Option<&String>) -> (String, String) {
+    let failed = error.is_some() || run_summary.has_failures();
+    let subject = format!(
+        "dd-back-up {}: {} backups",
+        if failed { "failed" } else { "succeeded" },
+        run_summary.outcomes.len()
+    );
+    let mut body = String::new();
+    if let Some(error) = error {
+        body.push_str(&format!("Error: {}\n\n", error));
+    }
+    match run_summary.outcomes.is_empty() {
+        true => body.push_str("No backups were run.\n"),
+        false => body.push_str(&run_summary.table()),
+    }
+    (subject, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_text() {
+        let (subject, body) = email_text(&RunSummary::default(), None);
+        assert_eq!(subject, "dd-back-up succeeded: 0 backups");
+        assert_eq!(body, "No backups were run.\n");
+
+        let error = "Failed to mount".to_string();
+        let (subject, body) = email_text(&RunSummary::default(), Some(&error));
+        assert_eq!(subject, "dd-back-up failed: 0 backups");
+        assert_eq!(body, "Error: Failed to mount\n\nNo backups were run.\n");
+    }
+}
@@ -0,0 +1,175 @@
+use crate::run::{config::BackupMethod, utils::format_byte_size};
+
+use super::{backup::Backup, summary::format_table};
+
+/// What a device backup would do, shown with `--plan` before any backup is run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanEntry {
+    /// The path of the source device.
+    pub device_path: String,
+    /// The UUID of the destination filesystem.
+    pub destination_uuid: Option<String>,
+    /// The number of backups of the device present on the destination, if readable.
+    pub present_copies: Option<usize>,
+    /// The number of copies of the device to keep, as configured, `None` keeps all.
+    pub configured_copies: Option<usize>,
+    /// Whether the oldest backup would be deleted, if known.
+    pub deletes_oldest: Option<bool>,
+    /// The free space of the destination after the backup in bytes, if known.
+    /// Negative if the backup wouldn't fit.
+    pub free_space_after: Option<i128>,
+    /// The path of the backup to be written.
+    pub target: String,
+}
+
+impl PlanEntry {
+    /// Describes what `backup` would do, without changing anything, writing to `target`.
+    ///
+    /// Present backups and free space can only be read if the destination filesystem is
+    /// already mounted, otherwise they are unknown.
+    pub fn new(backup: &Backup, target: String) -> PlanEntry {
+        let is_mounted = backup.dst_filesystem.is_mounted();
+        let present_copies = is_mounted.then(|| {
+            backup.dst_filesystem.present_number_of_copies(
+                &backup.file_name_pattern(),
+                &backup.backup_dir_path(),
+                backup.backup_device.method,
+            )
+        });
+        let deletes_oldest = is_mounted.then(|| backup.needs_deletion());
+
+        PlanEntry {
+            device_path: backup.backup_device.device_path.clone(),
+            destination_uuid: backup.dst_filesystem.blockdevice.uuid.clone(),
+            present_copies,
+            configured_copies: backup.backup_device.copies,
+            deletes_oldest,
+            free_space_after: free_space_after(backup, deletes_oldest.unwrap_or(false)),
+            target,
+        }
+    }
+}
+
+/// Returns the free space after the backup, counting the space freed by deleting the oldest image.
+///
+/// The space taken by an `rsync` backup depends on the changed files, so it isn't known.
+fn free_space_after(backup: &Backup, deletes_oldest: bool) -> Option<i128> {
+    if backup.backup_device.method == BackupMethod::Rsync {
+        return None;
+    }
+    let available_space = backup.dst_filesystem.available_space().ok()??;
+
+    let freed_space = match deletes_oldest {
+        true => backup
+            .dst_filesystem
+            .oldest_backup(
+                &backup.file_name_pattern(),
+                &backup.backup_dir_path(),
+                backup.backup_device.method,
+            )
+            .ok()
+            .flatten()
+            .map(|oldest_file| {
+                backup
+                    .dst_filesystem
+                    .backup_size(&backup.backup_dir_path(), &oldest_file)
+            })
+            .unwrap_or(0),
+        false => 0,
+    };
+
+    Some(available_space as i128 + freed_space as i128 - backup.backup_device.total_size() as i128)
+}
+
+/// Returns the plan formatted as a table, one row per device backup.
+pub fn table(entries: &[PlanEntry]) -> String {
+    let header = [
+        "DEVICE",
+        "DESTINATION",
+        "COPIES",
+        "KEEP",
+        "DELETE OLDEST",
+        "COPIES AFTER",
+        "FREE AFTER",
+        "TARGET",
+    ]
+    .map(String::from)
+    .to_vec();
+    let unknown = || "-".to_string();
+    let rows: Vec<Vec<String>> = std::iter::once(header)
+        .chain(entries.iter().map(|entry| {
+            vec![
+                entry.device_path.clone(),
+                entry.destination_uuid.clone().unwrap_or_else(unknown),
+                entry
+                    .present_copies
+                    .map(|copies| copies.to_string())
+                    .unwrap_or_else(unknown),
+                entry
+                    .configured_copies
+                    .map(|copies| copies.to_string())
+                    .unwrap_or_else(|| "all".to_string()),
+                entry
+                    .deletes_oldest
+                    .map(|deletes_oldest| if deletes_oldest { "yes" } else { "no" }.to_string())
+                    .unwrap_or_else(unknown),
+                entry
+                    .present_copies
+                    .zip(entry.deletes_oldest)
+                    .map(|(copies, deletes_oldest)| {
+                        (copies + 1 - usize::from(deletes_oldest)).to_string()
+                    })
+                    .unwrap_or_else(unknown),
+                entry
+                    .free_space_after
+                    .map(|free_space| match free_space < 0 {
+                        true => format!("-{}", format_byte_size(free_space.unsigned_abs() as u64)),
+                        false => format_byte_size(free_space as u64),
+                    })
+                    .unwrap_or_else(unknown),
+                entry.target.clone(),
+            ]
+        }))
+        .collect();
+
+    format_table(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let entries = vec![
+            PlanEntry {
+                device_path: "/dev/sda".to_string(),
+                destination_uuid: Some("uuid1".to_string()),
+                present_copies: Some(2),
+                configured_copies: Some(2),
+                deletes_oldest: Some(true),
+                free_space_after: Some(-512),
+                target: "/mnt/2023-06-15_serial1.img".to_string(),
+            },
+            PlanEntry {
+                device_path: "/dev/sdb".to_string(),
+                destination_uuid: Some("uuid2".to_string()),
+                present_copies: None,
+                configured_copies: None,
+                deletes_oldest: None,
+                free_space_after: None,
+                target: "/mnt/2023-06-15_serial2.img".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            table(&entries),
+            [
+                "DEVICE    DESTINATION  COPIES  KEEP  DELETE OLDEST  COPIES AFTER  FREE AFTER  TARGET",
+                "/dev/sda  uuid1        2       2     yes            2             -512 B      /mnt/2023-06-15_serial1.img",
+                "/dev/sdb  uuid2        -       all   -              -             -           /mnt/2023-06-15_serial2.img",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::{self, IsTerminal, Write};
+
+use crate::run::utils::format_byte_size;
+
+/// The width of the progress bar in characters.
+const BAR_WIDTH: usize = 30;
+
+/// The percentage steps in which progress is logged, if stderr isn't a terminal.
+const LOG_PERCENT_STEP: u64 = 10;
+
+/// A progress update of `dd status=progress`, like `1048576 bytes (1.0 MB, 1.0 MiB) copied, 2 s, 524 kB/s`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DdProgress {
+    /// The bytes copied so far.
+    pub bytes: u64,
+    /// The seconds elapsed so far.
+    pub seconds: Option<f64>,
+    /// The transfer rate, as formatted by `dd`.
+    pub rate: Option<String>,
+}
+
+/// Parses a progress or final statistics line of `dd`, returns `None` for any other line.
+pub fn parse_dd_progress(line: &str) -> Option<DdProgress> {
+    let (copied, statistics) = line.trim().split_once(" copied, ")?;
+    let mut words = copied.split_whitespace();
+    let bytes = words.next()?.parse().ok()?;
+    if words.next() != Some("bytes") {
+        return None;
+    }
+    let mut statistics = statistics.split(", ");
+    let seconds = statistics
+        .next()
+        .and_then(|seconds| seconds.strip_suffix(" s"))
+        .and_then(|seconds| seconds.parse().ok());
+    let rate = statistics.next().map(String::from);
+    Some(DdProgress {
+        bytes,
+        seconds,
+        rate,
+    })
+}
+
+/// Renders the progress of a `dd` run, relative to the known size of the source device.
+///
+/// On a terminal a live progress bar is drawn on stderr, otherwise an info line is logged every 10 percent.
+pub struct Progress {
+    /// The name of the copied device, prefixing the output.
+    label: String,
+    /// The size of the source device in bytes.
+    total_size: u64,
+    /// The bytes already present before this run, when resuming a partial image.
+    offset: u64,
+    /// Whether a live progress bar is drawn.
+    is_terminal: bool,
+    /// The next percentage an info line is logged at.
+    next_logged_percent: u64,
+    /// The last progress update.
+    last: Option<DdProgress>,
+}
+
+impl Progress {
+    /// Creates the progress of copying `total_size` bytes, of which `offset` bytes were already copied.
+    pub fn new(label: &str, total_size: u64, offset: u64) -> Progress {
+        let next_logged_percent =
+            (percent(offset, total_size) / LOG_PERCENT_STEP + 1) * LOG_PERCENT_STEP;
+        Progress {
+            label: label.to_string(),
+            total_size,
+            offset,
+            is_terminal: io::stderr().is_terminal(),
+            next_logged_percent,
+            last: None,
+        }
+    }
+
+    /// Updates the progress from a stderr `line` of `dd`.
+    ///
+    /// # Returns
+    ///
+    /// Whether the line was a progress update, which is then rendered instead of printed.
+    pub fn update(&mut self, line: &str) -> bool {
+        let Some(progress) = parse_dd_progress(line) else {
+            return false;
+        };
+        let copied = self.offset + progress.bytes;
+        let percent = percent(copied, self.total_size);
+
+        if self.is_terminal {
+            eprint!(
+                "\r{}",
+                progress_bar(
+                    &self.label,
+                    percent,
+                    copied,
+                    self.total_size,
+                    progress.rate.as_deref()
+                )
+            );
+            let _ = io::stderr().flush();
+        } else if self.total_size > 0 && percent >= self.next_logged_percent {
+            info!(
+                "{}: {}% copied ({} of {})",
+                self.label,
+                percent,
+                format_byte_size(copied),
+                format_byte_size(self.total_size)
+            );
+            self.next_logged_percent = (percent / LOG_PERCENT_STEP + 1) * LOG_PERCENT_STEP;
+        }
+        self.last = Some(progress);
+        true
+    }
+
+    /// Ends the progress bar and logs the total bytes and elapsed time of the last update,
+    /// which is the final statistics line of a finished `dd` run.
+    ///
+    /// # Returns
+    ///
+    /// The bytes `dd` copied in this run, if it reported any progress.
+    pub fn finish(&mut self) -> Option<u64> {
+        let last = self.last.take()?;
+        if self.is_terminal {
+            eprintln!();
+        }
+        info!(
+            "{}: copied {} in {}{}",
+            self.label,
+            format_byte_size(last.bytes),
+            last.seconds
+                .map(|seconds| format!("{:.1} s", seconds))
+                .unwrap_or("-".to_string()),
+            last.rate
+                .map(|rate| format!(", {}", rate))
+                .unwrap_or_default()
+        );
+        Some(last.bytes)
+    }
+}
+
+/// Returns the percentage of `copied` bytes of `total_size`, capped at 100.
+fn percent(copied: u64, total_size: u64) -> u64 {
+    match total_size {
+        0 => 0,
+        _ => (copied.saturating_mul(100) / total_size).min(100),
+    }
+}
+
+/// Formats a progress bar line, like `/dev/sda [#######-------] 50% 1.0 GiB / 2.0 GiB 100 MB/s`.
+fn progress_bar(
+    label: &str,
+    percent: u64,
+    copied: u64,
+    total_size: u64,
+    rate: Option<&str>,
+) -> String {
+    let filled = BAR_WIDTH * percent as usize / 100;
+    format!(
+        "{} [{}{}] {:>3}% {} / {} {}",
+        label,
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        percent,
+        format_byte_size(copied),
+        format_byte_size(total_size),
+        rate.unwrap_or_default()
+    )
+    .trim_end()
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dd_progress() {
+        assert_eq!(
+            parse_dd_progress("1048576000 bytes (1.0 GB, 1000 MiB) copied, 5 s, 210 MB/s"),
+            Some(DdProgress {
+                bytes: 1_048_576_000,
+                seconds: Some(5.0),
+                rate: Some("210 MB/s".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_dd_progress("0 bytes copied, 3.4005e-05 s, 0.0 kB/s"),
+            Some(DdProgress {
+                bytes: 0,
+                seconds: Some(0.000034005),
+                rate: Some("0.0 kB/s".to_string()),
+            })
+        );
+        assert_eq!(parse_dd_progress("2048+0 records in"), None);
+        assert_eq!(
+            parse_dd_progress("dd: error reading '/dev/sda': Input/output error"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(0, 200), 0);
+        assert_eq!(percent(100, 200), 50);
+        assert_eq!(percent(300, 200), 100);
+        assert_eq!(percent(100, 0), 0);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(
+            progress_bar("/dev/sda", 50, 1024, 2048, Some("100 MB/s")),
+            "/dev/sda [###############---------------]  50% 1.0 KiB / 2.0 KiB 100 MB/s"
+        );
+        assert_eq!(
+            progress_bar("/dev/sda", 100, 2048, 2048, None),
+            "/dev/sda [##############################] 100% 2.0 KiB / 2.0 KiB"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use std::{env, fs, path::Path};
+
+use chrono::Local;
+use chrono_humanize::Humanize;
+
+use crate::run::error::BackupError;
+
+use super::{
+    backup::Backup,
+    command_output::{command_output, print_commands_only},
+};
+
+/// Runs a file level backup of the device with `rsync`.
+///
+/// The filesystem of the source device, the device itself or its only partition with one, is mounted
+/// read-only to a temporary mountpoint, unless it is mounted already, and its content is copied into
+/// a new dated directory on the destination. Unchanged files are hardlinked to the previous backup
+/// directory of the device with `--link-dest`, so only changed files take up space.
+///
+/// # Returns
+///
+/// * `Ok(())` if the backup process is successful.
+/// * `Err(BackupError)` if the backup process encounters an error, like a failing mount or `rsync`.
+pub fn run(backup: &Backup) -> Result<(), BackupError> {
+    let source_filesystem = backup.backup_device.filesystem.as_ref().ok_or_else(|| {
+        BackupError::Other(format!(
+            "Device {} has no single filesystem to copy with rsync, configure the partition to back up by its PARTUUID or label",
+            backup.backup_device.device_path
+        ))
+    })?;
+    let source_path = format!("/dev/{}", source_filesystem.name);
+    let present_mountpoint = source_filesystem.mountpoint.clone();
+    let source_mountpoint = present_mountpoint
+        .clone()
+        .unwrap_or_else(|| source_mountpoint(&source_filesystem.name));
+    let command_parts = rsync_command_parts(backup, &source_mountpoint);
+
+    if backup.backup_args.dry_run {
+        match present_mountpoint {
+            Some(_) => info!(
+                "[DRY RUN] backup would copy {} mounted at {} with command: {}",
+                source_path,
+                source_mountpoint,
+                command_parts.join(" "),
+            ),
+            None => info!(
+                "[DRY RUN] backup would mount {} read-only at {} and run with command: {}",
+                source_path,
+                source_mountpoint,
+                command_parts.join(" "),
+            ),
+        }
+        return Ok(());
+    }
+
+    if present_mountpoint.is_none() {
+        mount_source(&source_path, &source_mountpoint)?;
+    }
+
+    let time_before_rsync = Local::now();
+    let rsync_result = command_output(
+        command_parts.iter().map(|part| part.as_str()).collect(),
+        "run rsync command",
+        Some(true),
+    );
+    let unmount_result = match present_mountpoint {
+        Some(_) => Ok(()),
+        None => unmount_source(&source_mountpoint),
+    };
+
+    rsync_result?;
+    unmount_result?;
+    info!(
+        "Success running backup with rsync command {} for {}",
+        command_parts.join(" "),
+        (Local::now() - time_before_rsync).humanize(),
+    );
+    backup.sync_backup_dir()
+}
+
+/// Builds the rsync command, linking against the previous backup directory if there is one.
+///
+/// With `--overwrite` a present directory of today is updated to mirror the source, deleting removed files.
+fn rsync_command_parts(backup: &Backup, source_mountpoint: &str) -> Vec<String> {
+    let mut command_parts = vec!["rsync".to_string(), "-a".to_string()];
+    if backup.backup_args.overwrite {
+        command_parts.push("--delete".to_string());
+    }
+    if let Some(previous_backup_dir) = previous_backup_dir(backup) {
+        command_parts.push(format!("--link-dest={}", previous_backup_dir));
+    }
+    command_parts.push(format!("{}/", source_mountpoint));
+    command_parts.push(format!("{}/", backup.backup_file_path()));
+    command_parts
+}
+
+/// Returns the path of the newest present backup directory of the device, if any.
+///
+/// Backup directory names start with the date, so the newest one sorts last.
+/// An overwritten directory of today isn't a previous one.
+fn previous_backup_dir(backup: &Backup) -> Option<String> {
+    let backup_dir_path = backup.backup_dir_path();
+    let file_name = backup.file_name();
+    let mut present_backup_dirs: Vec<String> = backup
+        .dst_filesystem
+        .present_backup_files(
+            &backup.file_name_pattern(),
+            &backup_dir_path,
+            backup.backup_device.method,
+        )
+        .ok()?
+        .into_iter()
+        .filter(|dir_name| *dir_name != file_name)
+        .collect();
+    present_backup_dirs.sort();
+    present_backup_dirs
+        .pop()
+        .map(|dir_name| format!("{}/{}", backup_dir_path, dir_name))
+}
+
+/// Returns the temporary mountpoint of the source filesystem with the device `name`.
+fn source_mountpoint(name: &str) -> String {
+    env::temp_dir()
+        .join(format!("dd_backup_source_{}", name))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Mounts the source filesystem at `source_path` read-only, creating the temporary mountpoint.
+fn mount_source(source_path: &str, source_mountpoint: &str) -> Result<(), BackupError> {
+    if !print_commands_only() {
+        fs::create_dir_all(source_mountpoint).map_err(BackupError::io(format!(
+            "Failed to create source mountpoint {}",
+            source_mountpoint
+        )))?;
+    }
+    command_output(
+        vec!["mount", "-o", "ro", source_path, source_mountpoint],
+        &format!(
+            "mount source filesystem {} read-only at {}",
+            source_path, source_mountpoint
+        ),
+        Some(true),
+    )?;
+    Ok(())
+}
+
+/// Unmounts the source device and removes the temporary mountpoint.
+fn unmount_source(source_mountpoint: &str) -> Result<(), BackupError> {
+    command_output(
+        vec!["umount", source_mountpoint],
+        &format!("unmount source device at {}", source_mountpoint),
+        Some(true),
+    )?;
+    if !print_commands_only() && Path::new(source_mountpoint).is_dir() {
+        fs::remove_dir(source_mountpoint).map_err(BackupError::io(format!(
+            "Failed to remove source mountpoint {}",
+            source_mountpoint
+        )))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{process, sync::Mutex};
+
+use super::command_output::command_output;
+
+/// The state to clean up when the process is interrupted.
+#[derive(Debug, Default)]
+struct Cleanup {
+    /// The partial images of the currently running `dd` backups.
+    partial_files: Vec<String>,
+    /// The mountpoint of the destination filesystem, if it was mounted by this run.
+    mountpoint: Option<String>,
+}
+
+static CLEANUP: Mutex<Cleanup> = Mutex::new(Cleanup {
+    partial_files: Vec::new(),
+    mountpoint: None,
+});
+
+/// The exit code after an interruption, as a shell reports a process terminated by `SIGINT`.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Registers the handler cleaning up on `SIGINT` and `SIGTERM`.
+///
+/// The handler removes the partial images, syncs and unmounts the destination filesystem,
+/// in this order, and exits. Registering again is a no-op.
+///
+/// # Returns
+///
+/// - `Ok(())`: If the handler is registered.
+/// - `Err(String)`: If the handler can't be registered.
+pub fn register_handler() -> Result<(), String> {
+    match ctrlc::set_handler(|| {
+        warn!("Interrupted, cleaning up");
+        cleanup();
+        process::exit(INTERRUPTED_EXIT_CODE);
+    }) {
+        Ok(()) | Err(ctrlc::Error::MultipleHandlers) => Ok(()),
+        Err(e) => Err(format!("Failed to register signal handler: {}", e)),
+    }
+}
+
+/// Adds the partial image of a running backup, removed on interruption.
+pub fn track_partial_file(partial_file: String) {
+    if let Ok(mut cleanup) = CLEANUP.lock() {
+        cleanup.partial_files.push(partial_file);
+    }
+}
+
+/// Removes the partial image of a finished backup, which is then kept on interruption.
+pub fn untrack_partial_file(partial_file: &str) {
+    if let Ok(mut cleanup) = CLEANUP.lock() {
+        cleanup
+            .partial_files
+            .retain(|tracked| tracked != partial_file);
+    }
+}
+
+/// Sets the mountpoint of the destination filesystem, unmounted on interruption.
+pub fn set_mountpoint(mountpoint: Option<String>) {
+    if let Ok(mut cleanup) = CLEANUP.lock() {
+        cleanup.mountpoint = mountpoint;
+    }
+}
+
+/// Removes the partial images, then syncs and unmounts the destination filesystem.
+///
+/// The lock is held until the process exits, so the interrupted backup can't continue meanwhile.
+fn cleanup() {
+    let mut cleanup = match CLEANUP.lock() {
+        Ok(cleanup) => cleanup,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+
+    for partial_file in std::mem::take(&mut cleanup.partial_files) {
+        match command_output(
+            vec!["rm", "-f", &partial_file],
+            "remove partial backup file",
+            Some(true),
+        ) {
+            Ok(_) => info!("Removed partial backup file {}", partial_file),
+            Err(e) => error!("Failed to remove partial backup file: {}", e),
+        }
+    }
+
+    if let Some(mountpoint) = cleanup.mountpoint.take() {
+        let unmounted = command_output(vec!["sync"], "execute sync", Some(false)).and_then(|_| {
+            command_output(
+                vec!["umount", &mountpoint],
+                &format!("unmount filesystem at {}", mountpoint),
+                Some(true),
+            )
+        });
+        match unmounted {
+            Ok(_) => info!("Unmounted filesystem at {}", mountpoint),
+            Err(e) => error!("Failed to unmount filesystem: {}", e),
+        }
+    }
+}
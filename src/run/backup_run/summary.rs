@@ -0,0 +1,347 @@
+use std::fmt;
+
+use chrono::Duration;
+use chrono_humanize::{Accuracy, HumanTime, Tense};
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::run::utils::format_byte_size;
+
+/// The format of the result `run` prints to stdout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable log lines and tables.
+    #[default]
+    Text,
+    /// A single JSON document summarizing all device backups, printed at the end.
+    Json,
+}
+
+/// The outcome of a single device backup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupStatus {
+    /// The backup was written.
+    Success,
+    /// The backup was only simulated.
+    DryRun,
+    /// The backup wasn't attempted, e.g. because the destination failed its filesystem check.
+    Skipped,
+    /// The backup failed.
+    Failed,
+}
+
+impl fmt::Display for BackupStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            BackupStatus::Success => "success",
+            BackupStatus::DryRun => "dry-run",
+            BackupStatus::Skipped => "skipped",
+            BackupStatus::Failed => "failed",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// Describes the outcome of a single device backup for the summary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupOutcome {
+    /// The path of the source device.
+    pub device_path: String,
+    /// The serial number of the source device.
+    pub serial: Option<String>,
+    /// The UUID of the destination filesystem.
+    pub destination_uuid: Option<String>,
+    /// The path of the backup image.
+    pub image_path: String,
+    /// The size of the written backup in bytes, on a dry run the estimated size of the source device.
+    pub size: Option<u64>,
+    /// Whether the oldest copy was deleted to keep the configured number of copies,
+    /// on a dry run whether it would be.
+    pub deleted_oldest: bool,
+    /// The time the backup took.
+    pub duration: Option<Duration>,
+    /// The outcome of the backup.
+    pub status: BackupStatus,
+    /// The error or skip reason.
+    pub message: Option<String>,
+}
+
+/// Collects the outcomes of all device backups of a run.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// The outcomes in the order the backups were run.
+    pub outcomes: Vec<BackupOutcome>,
+    /// The exit code of the first failed device backup, see `BackupError::backup_exit_code`.
+    pub exit_code: Option<i32>,
+}
+
+impl RunSummary {
+    /// Adds the outcome of a device backup.
+    pub fn push(&mut self, outcome: BackupOutcome) {
+        self.outcomes.push(outcome);
+    }
+
+    /// Returns the number of failed device backups.
+    pub fn failures(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.status == BackupStatus::Failed)
+            .count()
+    }
+
+    /// Returns whether any device backup failed.
+    pub fn has_failures(&self) -> bool {
+        self.outcomes
+            .iter()
+            .any(|outcome| outcome.status == BackupStatus::Failed)
+    }
+
+    /// Returns the outcomes as a JSON document, with the `error` which ended the run, if any.
+    pub fn json(&self, error: Option<&String>) -> String {
+        let backups: Vec<serde_json::Value> = self
+            .outcomes
+            .iter()
+            .map(|outcome| {
+                json!({
+                    "serial": outcome.serial,
+                    "device_path": outcome.device_path,
+                    "destination_uuid": outcome.destination_uuid,
+                    "image_path": outcome.image_path,
+                    "size": outcome.size,
+                    "deleted_oldest": outcome.deleted_oldest,
+                    "duration_seconds": outcome.duration.map(|duration| duration.num_seconds()),
+                    "status": outcome.status.to_string(),
+                    "error": outcome.message,
+                })
+            })
+            .collect();
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "success": error.is_none() && !self.has_failures(),
+            "error": error,
+            "backups": backups,
+        })
+        .to_string()
+    }
+
+    /// Returns the outcomes formatted as a table, one row per device backup.
+    pub fn table(&self) -> String {
+        let header = [
+            "STATUS",
+            "DEVICE",
+            "SERIAL",
+            "DESTINATION",
+            "SIZE",
+            "DURATION",
+            "MESSAGE",
+        ]
+        .map(String::from)
+        .to_vec();
+        let rows: Vec<Vec<String>> = std::iter::once(header)
+            .chain(self.outcomes.iter().map(|outcome| {
+                vec![
+                    outcome.status.to_string(),
+                    outcome.device_path.clone(),
+                    outcome.serial.clone().unwrap_or("-".to_string()),
+                    outcome.destination_uuid.clone().unwrap_or("-".to_string()),
+                    outcome
+                        .size
+                        .map(format_byte_size)
+                        .unwrap_or("-".to_string()),
+                    outcome
+                        .duration
+                        .map(|duration| {
+                            HumanTime::from(duration).to_text_en(Accuracy::Precise, Tense::Present)
+                        })
+                        .unwrap_or("-".to_string()),
+                    outcome.message.clone().unwrap_or_default(),
+                ]
+            }))
+            .collect();
+
+        format_table(&rows)
+    }
+}
+
+impl RunSummary {
+    /// Returns the outcomes of a dry run formatted as a table, one row per device backup,
+    /// with the image that would be written, its estimated size and whether a retention deletion would trigger.
+    pub fn dry_run_table(&self) -> String {
+        let header = [
+            "STATUS",
+            "DEVICE",
+            "SERIAL",
+            "DESTINATION",
+            "TARGET",
+            "SIZE",
+            "DELETE OLDEST",
+            "MESSAGE",
+        ]
+        .map(String::from)
+        .to_vec();
+        let rows: Vec<Vec<String>> = std::iter::once(header)
+            .chain(self.outcomes.iter().map(|outcome| {
+                vec![
+                    outcome.status.to_string(),
+                    outcome.device_path.clone(),
+                    outcome.serial.clone().unwrap_or("-".to_string()),
+                    outcome.destination_uuid.clone().unwrap_or("-".to_string()),
+                    match outcome.status {
+                        BackupStatus::DryRun => outcome.image_path.clone(),
+                        _ => "-".to_string(),
+                    },
+                    outcome
+                        .size
+                        .map(format_byte_size)
+                        .unwrap_or("-".to_string()),
+                    match (outcome.status, outcome.deleted_oldest) {
+                        (BackupStatus::DryRun, true) => "yes",
+                        (BackupStatus::DryRun, false) => "no",
+                        _ => "-",
+                    }
+                    .to_string(),
+                    outcome.message.clone().unwrap_or_default(),
+                ]
+            }))
+            .collect();
+
+        format_table(&rows)
+    }
+}
+
+/// Formats rows of cells into left aligned columns.
+pub fn format_table(rows: &[Vec<String>]) -> String {
+    let column_count = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..column_count)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_summary() -> RunSummary {
+        let mut run_summary = RunSummary::default();
+        run_summary.push(BackupOutcome {
+            device_path: "/dev/sda".to_string(),
+            serial: Some("serial1".to_string()),
+            destination_uuid: Some("uuid1".to_string()),
+            image_path: "/mnt/2023-06-15_serial1.img".to_string(),
+            size: Some(1024),
+            deleted_oldest: true,
+            duration: Some(Duration::seconds(62)),
+            status: BackupStatus::Success,
+            message: None,
+        });
+        run_summary.push(BackupOutcome {
+            device_path: "/dev/sdb".to_string(),
+            serial: None,
+            destination_uuid: Some("uuid1".to_string()),
+            image_path: "/mnt/2023-06-15_model.img".to_string(),
+            size: None,
+            deleted_oldest: false,
+            duration: None,
+            status: BackupStatus::Failed,
+            message: Some("Not enough space".to_string()),
+        });
+        run_summary
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            run_summary().table(),
+            [
+                "STATUS   DEVICE    SERIAL   DESTINATION  SIZE     DURATION                MESSAGE",
+                "success  /dev/sda  serial1  uuid1        1.0 KiB  1 minute and 2 seconds",
+                "failed   /dev/sdb  -        uuid1        -        -                       Not enough space",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_dry_run_table() {
+        let mut run_summary = RunSummary::default();
+        run_summary.push(BackupOutcome {
+            device_path: "/dev/sda".to_string(),
+            serial: Some("serial1".to_string()),
+            destination_uuid: Some("uuid1".to_string()),
+            image_path: "/mnt/2023-06-15_serial1.img".to_string(),
+            size: Some(2048),
+            deleted_oldest: true,
+            duration: None,
+            status: BackupStatus::DryRun,
+            message: None,
+        });
+        run_summary.push(BackupOutcome {
+            device_path: "-".to_string(),
+            serial: Some("serial2".to_string()),
+            destination_uuid: Some("uuid1".to_string()),
+            image_path: String::new(),
+            size: None,
+            deleted_oldest: false,
+            duration: None,
+            status: BackupStatus::Skipped,
+            message: Some("Device not connected".to_string()),
+        });
+        assert_eq!(
+            run_summary.dry_run_table(),
+            [
+                "STATUS   DEVICE    SERIAL   DESTINATION  TARGET                       SIZE     DELETE OLDEST  MESSAGE",
+                "dry-run  /dev/sda  serial1  uuid1        /mnt/2023-06-15_serial1.img  2.0 KiB  yes",
+                "skipped  -         serial2  uuid1        -                            -        -              Device not connected",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let document: serde_json::Value = serde_json::from_str(&run_summary().json(None)).unwrap();
+        assert_eq!(document["success"], false);
+        assert_eq!(document["error"], serde_json::Value::Null);
+        assert_eq!(document["backups"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            document["backups"][0],
+            json!({
+                "serial": "serial1",
+                "device_path": "/dev/sda",
+                "destination_uuid": "uuid1",
+                "image_path": "/mnt/2023-06-15_serial1.img",
+                "size": 1024,
+                "deleted_oldest": true,
+                "duration_seconds": 62,
+                "status": "success",
+                "error": null,
+            })
+        );
+        assert_eq!(document["backups"][1]["error"], "Not enough space");
+
+        let error = "Failed to mount".to_string();
+        let document: serde_json::Value =
+            serde_json::from_str(&RunSummary::default().json(Some(&error))).unwrap();
+        assert_eq!(document["success"], false);
+        assert_eq!(document["error"], "Failed to mount");
+    }
+}
@@ -0,0 +1,89 @@
+use super::{command_output::command_output_with_input, summary::RunSummary};
+
+/// The maximum number of seconds the webhook request may take, if no `webhook_timeout` is configured.
+const DEFAULT_TIMEOUT: u64 = 10;
+
+/// Posts the outcome of every backup in `run_summary` and the `error` which ended the run, if any,
+/// as JSON to the webhook `url`, the same document as printed with `--output json`.
+///
+/// The request is sent with `curl`, which reads the payload and the optional bearer `token` as config
+/// from stdin, so the token is neither logged nor visible in the process list. A failing request is only
+/// logged as a warning, since the notification must never fail the backup itself.
+pub fn post(
+    url: &str,
+    token: Option<&str>,
+    timeout: Option<u64>,
+    run_summary: &RunSummary,
+    error: Option<&String>,
+) {
+    let timeout = timeout.unwrap_or(DEFAULT_TIMEOUT).to_string();
+    let curl_config = curl_config(&run_summary.json(error), token);
+    match command_output_with_input(
+        vec![
+            "curl",
+            "-fsS",
+            "-m",
+            &timeout,
+            "-o",
+            "/dev/null",
+            "-K",
+            "-",
+            url,
+        ],
+        "post webhook",
+        Some(false),
+        curl_config.as_bytes(),
+    ) {
+        Ok(_) => info!("Posted run summary to webhook {}", url),
+        Err(e) => warn!("Failed to post run summary to webhook {}: {}", url, e),
+    }
+}
+
+/// Returns the `curl` config posting the JSON `payload`, with the bearer `token` as authorization header.
+fn curl_config(payload: &str, token: Option<&str>) -> String {
+    let mut curl_config = format!(
+        "header = {}\n",
+        curl_config_quote("Content-Type: application/json")
+    );
+    if let Some(token) = token {
+        curl_config.push_str(&format!(
+            "header = {}\n",
+            curl_config_quote(&format!("Authorization: Bearer {}", token))
+        ));
+    }
+    curl_config.push_str(&format!("data-binary = {}\n", curl_config_quote(payload)));
+    curl_config
+}
+
+/// Quotes `value` for a `curl` config file, escaping backslashes, double quotes and line breaks.
+fn curl_config_quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curl_config() {
+        assert_eq!(
+            curl_config(r#"{"error":"a \"b\""}"#, Some("t0ken")),
+            concat!(
+                "header = \"Content-Type: application/json\"\n",
+                "header = \"Authorization: Bearer t0ken\"\n",
+                "data-binary = \"{\\\"error\\\":\\\"a \\\\\\\"b\\\\\\\"\\\"}\"\n",
+            )
+        );
+        assert_eq!(
+            curl_config("{}", None),
+            "header = \"Content-Type: application/json\"\ndata-binary = \"{}\"\n"
+        );
+    }
+}
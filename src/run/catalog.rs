@@ -0,0 +1,241 @@
+use clap::{Args, Subcommand};
+use rusqlite::{params, Connection};
+
+use super::config::Config;
+use super::error::Failure;
+use super::utils::format_byte_size;
+
+#[derive(Args, Debug)]
+pub struct CatalogArgs {
+    #[command(subcommand)]
+    pub command: CatalogCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CatalogCommands {
+    /// List the recorded backups, newest first
+    Query(CatalogQueryArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CatalogQueryArgs {
+    #[clap(long)]
+    /// The path to the catalog database, overwrites config value.
+    pub catalog_path: Option<String>,
+
+    #[clap(short, long)]
+    /// The path to the configuration file, used to read the catalog path.
+    pub config_file_path: Option<String>,
+
+    #[clap(long)]
+    /// Only list backups of the source device with this serial number.
+    pub serial: Option<String>,
+
+    #[clap(long)]
+    /// Only list backups on the destination filesystem with this UUID.
+    pub uuid: Option<String>,
+
+    #[clap(long)]
+    /// The maximum number of backups to list.
+    pub limit: Option<usize>,
+}
+
+/// A recorded backup in the catalog.
+#[derive(Debug, PartialEq)]
+pub struct CatalogEntry {
+    /// The time the backup was completed, in RFC 3339 format.
+    pub timestamp: String,
+    /// The serial number of the source device.
+    pub serial: Option<String>,
+    /// The model of the source device.
+    pub model: Option<String>,
+    /// The UUID of the destination filesystem.
+    pub destination_uuid: String,
+    /// The path of the backup image.
+    pub image_path: String,
+    /// The size of the backup image in bytes.
+    pub size: Option<u64>,
+    /// The checksum of the backup image, if one was computed.
+    pub checksum: Option<String>,
+    /// The outcome of the backup.
+    pub status: String,
+}
+
+/// A SQLite database recording the history of completed backups.
+pub struct Catalog {
+    connection: Connection,
+}
+
+impl Catalog {
+    /// Opens the catalog database at `path`, creating it and its table if needed.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Catalog)`: If the database is opened and initialized.
+    /// - `Err(String)`: If the database can't be opened or initialized.
+    pub fn open(path: &str) -> Result<Catalog, String> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("Failed to open catalog {}: {}", path, e))?;
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS backups (
+                    id INTEGER PRIMARY KEY,
+                    timestamp TEXT NOT NULL,
+                    serial TEXT,
+                    model TEXT,
+                    destination_uuid TEXT NOT NULL,
+                    image_path TEXT NOT NULL,
+                    size INTEGER,
+                    checksum TEXT,
+                    status TEXT NOT NULL
+                )",
+                [],
+            )
+            .map_err(|e| format!("Failed to initialize catalog {}: {}", path, e))?;
+        Ok(Catalog { connection })
+    }
+
+    /// Records a backup in the catalog.
+    pub fn insert(&self, entry: &CatalogEntry) -> Result<(), String> {
+        self.connection
+            .execute(
+                "INSERT INTO backups
+                    (timestamp, serial, model, destination_uuid, image_path, size, checksum, status)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    entry.timestamp,
+                    entry.serial,
+                    entry.model,
+                    entry.destination_uuid,
+                    entry.image_path,
+                    entry.size.map(|size| size as i64),
+                    entry.checksum,
+                    entry.status,
+                ],
+            )
+            .map_err(|e| format!("Failed to insert into catalog: {}", e))?;
+        Ok(())
+    }
+
+    /// Returns the recorded backups, newest first, optionally filtered by serial and destination UUID.
+    pub fn query(
+        &self,
+        serial: Option<&str>,
+        destination_uuid: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<CatalogEntry>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT timestamp, serial, model, destination_uuid, image_path, size, checksum, status
+                    FROM backups
+                    WHERE (?1 IS NULL OR serial = ?1) AND (?2 IS NULL OR destination_uuid = ?2)
+                    ORDER BY timestamp DESC, id DESC
+                    LIMIT ?3",
+            )
+            .map_err(|e| format!("Failed to prepare catalog query: {}", e))?;
+
+        let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+        let entries = statement
+            .query_map(params![serial, destination_uuid, limit], |row| {
+                Ok(CatalogEntry {
+                    timestamp: row.get(0)?,
+                    serial: row.get(1)?,
+                    model: row.get(2)?,
+                    destination_uuid: row.get(3)?,
+                    image_path: row.get(4)?,
+                    size: row.get::<_, Option<i64>>(5)?.map(|size| size as u64),
+                    checksum: row.get(6)?,
+                    status: row.get(7)?,
+                })
+            })
+            .map_err(|e| format!("Failed to query catalog: {}", e))?
+            .collect::<Result<Vec<CatalogEntry>, _>>()
+            .map_err(|e| format!("Failed to read catalog row: {}", e))?;
+        Ok(entries)
+    }
+}
+
+/// Runs the catalog subcommands.
+///
+/// The catalog path is taken from `--catalog-path`, or otherwise from the configuration file.
+///
+/// # Returns
+///
+/// An `Ok` variant if the subcommand completes successfully, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(catalog_args: &CatalogArgs) -> Result<(), Failure> {
+    match &catalog_args.command {
+        CatalogCommands::Query(query_args) => {
+            let catalog_path = match &query_args.catalog_path {
+                Some(catalog_path) => catalog_path.clone(),
+                None => Config::new(&query_args.config_file_path)?
+                    .catalog_path
+                    .ok_or("No catalog configured, set `catalog_path` or use `--catalog-path`")?,
+            };
+
+            let entries = Catalog::open(&catalog_path)?.query(
+                query_args.serial.as_deref(),
+                query_args.uuid.as_deref(),
+                query_args.limit,
+            )?;
+            for entry in entries {
+                println!(
+                    "{} {} {} {} {} {} {}",
+                    entry.timestamp,
+                    entry.status,
+                    entry.serial.unwrap_or("-".to_string()),
+                    entry.destination_uuid,
+                    entry.image_path,
+                    entry.size.map(format_byte_size).unwrap_or("-".to_string()),
+                    entry.checksum.unwrap_or("-".to_string()),
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: &str, serial: &str, destination_uuid: &str) -> CatalogEntry {
+        CatalogEntry {
+            timestamp: timestamp.to_string(),
+            serial: Some(serial.to_string()),
+            model: None,
+            destination_uuid: destination_uuid.to_string(),
+            image_path: format!("/mnt/{}_{}.img", timestamp, serial),
+            size: Some(1024),
+            checksum: None,
+            status: "success".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_query() {
+        let catalog = Catalog::open(":memory:").unwrap();
+        catalog
+            .insert(&entry("2023-06-14", "serial1", "uuid1"))
+            .unwrap();
+        catalog
+            .insert(&entry("2023-06-15", "serial1", "uuid2"))
+            .unwrap();
+        catalog
+            .insert(&entry("2023-06-16", "serial2", "uuid1"))
+            .unwrap();
+
+        let entries = catalog.query(None, None, None).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], entry("2023-06-16", "serial2", "uuid1"));
+
+        let entries = catalog.query(Some("serial1"), None, None).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let entries = catalog.query(Some("serial1"), Some("uuid1"), None).unwrap();
+        assert_eq!(entries, vec![entry("2023-06-14", "serial1", "uuid1")]);
+
+        let entries = catalog.query(None, None, Some(1)).unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -1,26 +1,282 @@
-use serde::{Deserialize, Serialize};
+use clap::ValueEnum;
+use lettre::message::Mailbox;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::HashSet,
-    fs::{self, File},
-    path::PathBuf,
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
 };
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+use crate::run::error::BackupError;
+use crate::run::utils::{
+    convert_to_byte_size, escapes_base_dir, is_dd_option, is_plain_mount_argument, parse_bandwidth,
+    unknown_placeholders, DATE_PLACEHOLDERS,
+};
+
+/// The name of a configuration file discovered in the current directory or its parents.
+pub const DISCOVERED_CONFIG_FILE_NAME: &str = ".dd-back-up.json";
+
+/// The env var holding the configuration as JSON, used if no config file path is given.
+pub const CONFIG_ENV_VAR: &str = "DD_BACKUP_CONFIG";
+
+/// The config file path reading the configuration as JSON from stdin.
+pub const STDIN_CONFIG_FILE_PATH: &str = "-";
+
+/// The names of the config file in the config home, in the order of preference.
+const DEFAULT_CONFIG_FILE_NAMES: [&str; 4] =
+    ["config.json", "config.yaml", "config.yml", "config.toml"];
+
+/// The file in a config dir providing the top-level fields, see `Config::from_dir`.
+const MAIN_CONFIG_FILE_NAME: &str = "main.json";
+
+/// The block size `dd` reads and writes with, if no `block_size` is configured.
+pub const DEFAULT_BLOCK_SIZE: &str = "4M";
+
+/// The template of backup file names, if no `name_template` is configured.
+pub const DEFAULT_NAME_TEMPLATE: &str = "{date}_{tag}_{name}_{model}_{serial}";
+
+/// The placeholders which can be used in a `name_template`.
+pub const NAME_TEMPLATE_PLACEHOLDERS: [&str; 7] = [
+    "{date}", "{time}", "{tag}", "{name}", "{model}", "{serial}", "{uuid}",
+];
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
 pub struct BackupDevice {
     /// The serial number of the device.
-    pub serial: String,
+    /// Exactly one of `serial`, `wwn`, `partuuid`, `partlabel` or `match` needs to be specified.
+    pub serial: Option<String>,
+    /// The world wide name of the device, for devices with a missing or duplicated serial number.
+    pub wwn: Option<String>,
+    /// The PARTUUID of a single partition to back up, instead of a whole device.
+    pub partuuid: Option<String>,
+    /// The label of a single partition to back up, instead of a whole device.
+    pub partlabel: Option<String>,
+    /// Attribute patterns matching possibly several devices, instead of a single serial number.
+    #[serde(rename = "match")]
+    pub device_match: Option<DeviceMatch>,
     /// An optional name for the device.
     pub name: Option<String>,
     /// The number of copies to be kept for this device.
     ///
     /// If set to `None`, only one copy will be kept.
     /// If set to a positive integer, the oldest copies will be deleted when the limit is reached.
-    /// If set to 0, Config::validate_config will return Err(String).
+    /// If set to 0, Config::validate_config will return Err(BackupError::InvalidConfig).
     pub copies: Option<usize>,
+    /// The maximum age of backups of this device in days.
+    ///
+    /// If set, backups older than that are deleted before a new backup, in addition to the `copies` limit.
+    /// If set to 0, Config::validate_config will return Err(BackupError::InvalidConfig).
+    pub max_age_days: Option<u64>,
+    /// The method used to back up the device, also accepted as `mode`.
+    /// If not provided, the device is imaged with `dd`.
+    #[serde(alias = "mode")]
+    pub method: Option<BackupMethod>,
+    /// Whether to evict the written image from the page cache after the backup.
+    /// If set to `true`, the image is flushed and dropped from the cache with `posix_fadvise`.
+    /// If set to `false` or not specified, the cache is left as is.
+    pub drop_cache: Option<bool>,
+    /// Whether the device is backed up.
+    /// If set to `false`, the device is skipped, but its configuration is still validated.
+    /// If set to `true` or not specified, the device is backed up.
+    pub enabled: Option<bool>,
+    /// The size the device is expected to have, like `500GB` or `1T`.
+    /// If set, a device whose size differs by more than 1% is skipped, or fails the run with `--strict`,
+    /// which catches a swapped drive with the same serial number.
+    /// If not provided, the device is backed up with whatever size it has.
+    pub expected_size: Option<String>,
+    /// The UUID of the destination filesystem the device is backed up to, instead of the one of its
+    /// backup and its mirrors, e.g. to keep a disk on a different drive than the others.
+    /// The other settings of the backup, like `mountpath` and `destination_path`, apply to it too.
+    /// If not provided, the device is backed up to the destination of its backup and its mirrors.
+    pub destination_uuid: Option<String>,
+}
+
+/// The method used to back up a device.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMethod {
+    /// Images the whole block device with `dd`.
+    #[default]
+    Dd,
+    /// Copies the files of the read-only mounted device with `rsync` into a dated directory.
+    /// Unchanged files are hardlinked to the previous backup directory.
+    Rsync,
+}
+
+/// What to do when the filesystem check of the destination fails.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FsckPolicy {
+    /// Skips the backups to the destination.
+    #[default]
+    Abort,
+    /// Logs a warning and backs up anyway, the fsck error is reported in the summary.
+    Warn,
+    /// Doesn't run the filesystem check at all, like `skip_fsck`.
+    Skip,
+}
+
+/// What to do with the drive of the destination filesystem, after its backups succeeded and it is unmounted.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnComplete {
+    /// Leaves the drive connected.
+    #[default]
+    None,
+    /// Ejects the drive with `eject`.
+    Eject,
+    /// Spins down and powers off the drive with `udisksctl power-off`, so it can be unplugged safely.
+    PowerOff,
+}
+
+/// The compressor `dd` images are piped through.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Compresses with `gzip`, into `.img.gz` files.
+    Gzip,
+    /// Compresses with `zstd`, into `.img.zst` files.
+    Zstd,
+    /// Compresses with `xz`, into `.img.xz` files.
+    Xz,
+}
+
+impl Compression {
+    /// Returns the name of the compressor binary.
+    pub fn binary(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+        }
+    }
+
+    /// Returns the extension appended to the `.img` extension of compressed images.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+            Compression::Xz => ".xz",
+        }
+    }
+
+    /// Detects the compression of an image from the extension of its `file_path`, `None` for raw images.
+    pub fn from_file_path(file_path: &str) -> Option<Compression> {
+        Compression::value_variants()
+            .iter()
+            .find(|compression| file_path.ends_with(compression.extension()))
+            .copied()
+    }
+}
+
+/// The space which has to remain free on a destination filesystem after a backup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpaceHeadroom {
+    /// An absolute size in bytes.
+    Bytes(u64),
+    /// A percentage of the size of the filesystem.
+    Percent(f64),
+}
+
+impl SpaceHeadroom {
+    /// Returns the headroom in bytes on a filesystem of `filesystem_size` bytes.
+    pub fn in_bytes(&self, filesystem_size: u64) -> u64 {
+        match self {
+            SpaceHeadroom::Bytes(bytes) => *bytes,
+            SpaceHeadroom::Percent(percent) => (filesystem_size as f64 * percent / 100.0) as u64,
+        }
+    }
+}
+
+/// Attribute patterns to match devices with, all given patterns need to match.
+/// How a destination filesystem is identified, each matched against its own column of `lsblk`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DestinationId {
+    /// The UUID of the filesystem, like the `uuid`, the `mirrors` and the `destination_uuid` of devices.
+    Uuid(String),
+    /// The label of the filesystem, configured as `label` instead of the `uuid`.
+    Label(String),
+}
+
+impl fmt::Display for DestinationId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DestinationId::Uuid(uuid) => write!(f, "UUID {}", uuid),
+            DestinationId::Label(label) => write!(f, "label {}", label),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+pub struct DeviceMatch {
+    /// The exact model name of the device.
+    pub model: Option<String>,
+    /// The prefix the serial number of the device starts with.
+    pub serial_prefix: Option<String>,
+}
+
+impl BackupDevice {
+    /// Returns a description of how the device is identified, for use in messages.
+    pub fn identifier(&self) -> String {
+        let identifier = [&self.serial, &self.wwn, &self.partuuid, &self.partlabel]
+            .into_iter()
+            .flatten()
+            .next();
+        match (identifier, &self.device_match) {
+            (Some(identifier), _) => identifier.clone(),
+            (None, Some(device_match)) => format!(
+                "match (model: {}, serial_prefix: {})",
+                device_match.model.as_deref().unwrap_or("*"),
+                device_match.serial_prefix.as_deref().unwrap_or("*")
+            ),
+            (None, None) => "<unidentified>".to_string(),
+        }
+    }
+
+    /// Returns whether the device is enabled, which it is unless `enabled` is set to `false`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Returns whether the device is backed up to the destination filesystem `destination`, which is
+    /// its own `destination_uuid`, or else one of the `destination_ids` of its `backup_config`.
+    pub fn is_backed_up_to(
+        &self,
+        destination: &DestinationId,
+        backup_config: &BackupConfig,
+    ) -> bool {
+        match &self.destination_uuid {
+            Some(destination_uuid) if *destination_uuid != backup_config.uuid => {
+                *destination == DestinationId::Uuid(destination_uuid.clone())
+            }
+            _ => backup_config.destination_ids().contains(destination),
+        }
+    }
+
+    /// Returns the configured `expected_size` in bytes, if any.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Option<u64>)`: The positive expected size in bytes, `None` if unset.
+    /// - `Err(BackupError::InvalidConfig)`: If the expected size isn't a valid size string or zero.
+    pub fn expected_size_in_bytes(&self) -> Result<Option<u64>, BackupError> {
+        let Some(expected_size) = self.expected_size.as_deref() else {
+            return Ok(None);
+        };
+        match convert_to_byte_size(expected_size) {
+            Ok(Some(bytes)) if bytes > 0 => Ok(Some(bytes)),
+            _ => Err(BackupError::InvalidConfig(format!(
+                "Invalid expected size '{}' for device '{}'. Must be a positive size like 500GB.",
+                expected_size,
+                self.identifier()
+            ))),
+        }
+    }
 }
 
 /// Represents the configuration for a single backup.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct BackupConfig {
     /// The list of devices to be backed up.
     ///
@@ -31,10 +287,21 @@ pub struct BackupConfig {
     pub backup_devices: Vec<BackupDevice>,
 
     /// The UUID of the destination backup filesystem or partition.
+    /// Empty if the `label` is given instead, see `destination_id`.
+    #[serde(default)]
     pub uuid: String,
 
+    /// The label of the destination backup filesystem, an alternative to the `uuid`
+    /// which stays the same when the filesystem is recreated.
+    pub label: Option<String>,
+
+    /// The UUIDs of further destination filesystems, each receiving the same backups.
+    /// A mirror which isn't attached is skipped with a warning.
+    pub mirrors: Option<Vec<String>>,
+
     /// The destination path where the backup will be stored.
     /// If not provided, the default path "./" will be used.
+    /// May contain the placeholders `{year}`, `{month}` and `{date}`, which are expanded on each run.
     pub destination_path: Option<String>,
 
     /// The command to execute the filesystem check (`fsck`).
@@ -46,14 +313,366 @@ pub struct BackupConfig {
     /// If set to `false` or not specified, the filesystem check will be performed.
     pub skip_fsck: Option<bool>,
 
+    /// What to do when the filesystem check fails, `abort`, `warn` or `skip`.
+    /// If not provided, the backups to this destination are aborted.
+    pub fsck_policy: Option<FsckPolicy>,
+
     /// Whether to skip the mount and unmount process
     /// If set to `true`, the mounting will be skipped.
     /// If set to `false` or not specified, mounting will be performed.
     pub skip_mount: Option<bool>,
+
+    /// The path on which this destination filesystem will be mounted.
+    /// If not provided, the top-level `mountpath` will be used.
+    pub mountpath: Option<String>,
+
+    /// The options the destination filesystem is mounted with, passed as `mount -o <options>`,
+    /// like `uid=1000,gid=1000` for exFAT or `rw,big_writes` for NTFS.
+    /// If not provided, the defaults of `mount` are used.
+    pub mount_options: Option<String>,
+
+    /// The type of the destination filesystem, passed as `mount -t <type>`, like `ntfs3` or `exfat`.
+    /// If not provided, `mount` detects the type.
+    pub mount_type: Option<String>,
+
+    /// Whether to fsync the image and the destination directory after writing a backup.
+    /// If set to `false`, a new image or its directory entry may not survive a power loss.
+    /// If set to `true` or not specified, the image and the destination directory will be synced.
+    pub durable: Option<bool>,
+
+    /// Whether to skip the check for enough free space before a backup.
+    /// If set to `true`, backups are written without checking, e.g. for compressing filesystems,
+    /// where the source size overstates the needed space.
+    /// If set to `false` or not specified, the free space is checked.
+    pub skip_space_check: Option<bool>,
+
+    /// The compressor `dd` images are piped through, `gzip`, `zstd` or `xz`.
+    /// Compressed images can't be resumed and the free space isn't checked, since their size is unknown upfront.
+    /// If not provided, raw images are written.
+    pub compression: Option<Compression>,
+
+    /// Whether to write `dd` images sparse with `conv=sparse`, so zero blocks take up no space
+    /// on destination filesystems supporting holes. Can't be combined with `compression`.
+    /// If set to `true`, a failing free space check only logs a warning, since the written size is smaller.
+    /// If set to `false` or not specified, every block is written.
+    pub sparse: Option<bool>,
+
+    /// Whether to verify `dd` images after writing, by comparing their SHA-256 to the one of the source device.
+    /// If set to `true`, the checksum is stored in a `<image>.sha256` file next to the image.
+    /// With `--parallel-checksum` a raw image is hashed while writing it instead, without reading it again.
+    /// If set to `false` or not specified, images aren't verified.
+    pub verify: Option<bool>,
+
+    /// Whether to skip the `dd` backup of a device whose SHA-256 equals the one recorded for its most recent backup.
+    /// If set to `true`, the source is read once more for the checksum before each backup, which only pays off
+    /// for rarely changing devices, and `verify` needs to be set to record the checksums.
+    /// If set to `false` or not specified, each backup is written.
+    pub skip_if_unchanged: Option<bool>,
+
+    /// The block size `dd` reads and writes with, like `4M`, `1MiB` or `64K`.
+    /// Larger blocks are much faster than the 512 byte default of `dd`, `--resume` continues in whole blocks.
+    /// If not provided, `4M` is used.
+    pub block_size: Option<String>,
+
+    /// The bandwidth `dd` images are written with at most, in bytes per second like `50M` or `20MB`,
+    /// by piping them through `pv -L`, so a backup doesn't saturate the bus during the day.
+    /// If not provided, `dd` runs at full speed.
+    pub max_bandwidth: Option<String>,
+
+    /// The space which has to remain free after a backup, as a size like `10G` or a percentage
+    /// of the filesystem size like `5%`. The free space check fails if a backup would take it up.
+    /// If not provided, the destination may be filled completely.
+    pub space_headroom: Option<String>,
+
+    /// Further operands appended to the `dd` command, like `conv=noerror,sync` or `iflag=direct`.
+    /// With `conv=noerror` read errors of a dying source don't fail the backup, but are logged as warning.
+    /// If not provided, `dd` runs with the block size and the operands of the other settings only.
+    pub dd_options: Option<Vec<String>>,
+
+    /// The template of backup file names, with the placeholders `{date}`, `{time}`, `{tag}`, `{name}`,
+    /// `{model}`, `{serial}` and `{uuid}`. Placeholders without value are dropped with an adjacent `_` or `-`.
+    /// The extension, like `.img`, is appended. Needs to contain `{date}`.
+    /// If not provided, `{date}_{tag}_{name}_{model}_{serial}` is used.
+    pub name_template: Option<String>,
+
+    /// Whether to include the time (HH-MM-SS) after the date in backup file names.
+    /// If set to `true`, several backups per day are possible, `{time}` is inserted after `{date}`
+    /// unless the `name_template` contains it already.
+    /// If set to `false` or not specified, only one backup per day and device is possible.
+    pub include_time: Option<bool>,
+
+    /// How often a failing `dd` is retried, e.g. after a hiccup of a USB enclosure.
+    /// Validation errors, like a full destination or an already present backup, aren't retried.
+    /// If not provided, `dd` isn't retried.
+    pub retries: Option<u32>,
+
+    /// A shell command run before the destination filesystem is checked and mounted,
+    /// e.g. to spin up the disk. If it fails, the backups to this destination fail without being run.
+    /// The env vars `DD_BACKUP_UUID` and `DD_BACKUP_DEVICE` describe the destination,
+    /// see `Backups::run_hook` for the placeholders. `{device_path}` is the destination
+    /// filesystem's device, not a source device. Also accepted as `pre_command`.
+    #[serde(alias = "pre_command")]
+    pub pre_mount: Option<String>,
+
+    /// A shell command run after the destination filesystem is unmounted,
+    /// e.g. to power down the disk. If it fails, the error is only logged.
+    /// The env vars `DD_BACKUP_UUID` and `DD_BACKUP_DEVICE` describe the destination,
+    /// see `Backups::run_hook` for the placeholders. Also accepted as `post_command`.
+    #[serde(alias = "post_command")]
+    pub post_unmount: Option<String>,
+
+    /// What to do with the drive of the destination filesystem after its backups succeeded,
+    /// `none`, `eject` or `power-off`. Runs after the `post_unmount` hook, skipped on dry runs and with `skip_mount`.
+    /// If not provided, the drive is left connected.
+    pub on_complete: Option<OnComplete>,
+
+    /// Whether the backups to this destination are run.
+    /// If set to `false`, the backup is skipped, but its configuration is still validated.
+    /// If set to `true` or not specified, the backup is run.
+    pub enabled: Option<bool>,
+}
+
+impl BackupConfig {
+    /// Returns how the destination filesystem is identified, by its `label` if one is configured,
+    /// otherwise by its `uuid`.
+    pub fn destination_id(&self) -> DestinationId {
+        match &self.label {
+            Some(label) => DestinationId::Label(label.clone()),
+            None => DestinationId::Uuid(self.uuid.clone()),
+        }
+    }
+
+    /// Returns the `destination_id` of the destination filesystem, followed by the UUIDs of its mirrors.
+    pub fn destination_ids(&self) -> Vec<DestinationId> {
+        std::iter::once(self.destination_id())
+            .chain(
+                self.mirrors
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .map(DestinationId::Uuid),
+            )
+            .collect()
+    }
+
+    /// Returns the `destination_ids`, followed by the distinct `destination_uuid`s of devices
+    /// which aren't one of them, so each destination filesystem is mounted once.
+    pub fn all_destination_ids(&self) -> Vec<DestinationId> {
+        let mut destinations = self.destination_ids();
+        for destination_uuid in self
+            .backup_devices
+            .iter()
+            .filter_map(|backup_device| backup_device.destination_uuid.as_ref())
+        {
+            let destination = DestinationId::Uuid(destination_uuid.clone());
+            if !destinations.contains(&destination) {
+                destinations.push(destination);
+            }
+        }
+        destinations
+    }
+
+    /// Validates that the destination filesystem is configured by its `uuid` or its `label`,
+    /// which are matched against the UUIDs and labels of the available filesystems.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: If exactly one of `uuid` and `label` is configured.
+    /// - `Err(BackupError::InvalidConfig)`: If both or neither of them are configured.
+    fn validate_destination_id(&self) -> Result<(), BackupError> {
+        match (self.uuid.is_empty(), &self.label) {
+            (true, Some(label)) if !label.is_empty() => Ok(()),
+            (false, None) => Ok(()),
+            (false, Some(label)) => Err(BackupError::InvalidConfig(format!(
+                "Backup with {} also has the label '{}', only one of `uuid` and `label` may be given",
+                self.destination_id(), label
+            ))),
+            (true, _) => Err(BackupError::InvalidConfig(
+                "Backup without a `uuid` or `label` of its destination filesystem".to_string(),
+            )),
+        }
+    }
+
+    /// Returns whether the destination filesystem `destination` is one of the `mirrors`.
+    pub fn is_mirror(&self, destination: &DestinationId) -> bool {
+        *destination != self.destination_id()
+            && self
+                .mirrors
+                .iter()
+                .flatten()
+                .any(|mirror| *destination == DestinationId::Uuid(mirror.clone()))
+    }
+
+    /// Returns how the destination filesystem `destination` is configured, for use in messages.
+    pub fn destination_kind(&self, destination: &DestinationId) -> &'static str {
+        if *destination == self.destination_id() {
+            "Destination"
+        } else if self.is_mirror(destination) {
+            "Mirror"
+        } else {
+            "Device destination"
+        }
+    }
+
+    /// Returns whether the backup is enabled, which it is unless `enabled` is set to `false`.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Returns the template of backup file names, the `name_template` or `DEFAULT_NAME_TEMPLATE`,
+    /// with `{time}` inserted after `{date}` if `include_time` is set.
+    pub fn file_name_template(&self) -> String {
+        let name_template = self
+            .name_template
+            .clone()
+            .unwrap_or(DEFAULT_NAME_TEMPLATE.to_string());
+        match self.include_time == Some(true) && !name_template.contains("{time}") {
+            true => name_template.replacen("{date}", "{date}_{time}", 1),
+            false => name_template,
+        }
+    }
+
+    /// Returns the configured `block_size` in bytes, `DEFAULT_BLOCK_SIZE` if unset.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(u64)`: The positive block size in bytes.
+    /// - `Err(BackupError::InvalidConfig)`: If the block size isn't a valid size string or zero.
+    pub fn block_size_in_bytes(&self) -> Result<u64, BackupError> {
+        let block_size = self.block_size.as_deref().unwrap_or(DEFAULT_BLOCK_SIZE);
+        match convert_to_byte_size(block_size) {
+            Ok(Some(bytes)) if bytes > 0 => Ok(bytes),
+            Ok(_) => Err(BackupError::InvalidConfig(format!(
+                "Invalid block size '{}' of backup with {}. Must be a positive size like 4M.",
+                block_size,
+                self.destination_id()
+            ))),
+            Err(e) => Err(BackupError::InvalidConfig(format!(
+                "Invalid block size '{}' of backup with {}: {}",
+                block_size,
+                self.destination_id(),
+                e
+            ))),
+        }
+    }
+
+    /// Returns the configured `max_bandwidth` in bytes per second, `None` if unset.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Option<u64>)`: The positive bandwidth in bytes per second, if configured.
+    /// - `Err(BackupError::InvalidConfig)`: If the bandwidth isn't a valid size string or zero.
+    pub fn max_bandwidth_in_bytes(&self) -> Result<Option<u64>, BackupError> {
+        self.max_bandwidth
+            .as_deref()
+            .map(parse_bandwidth)
+            .transpose()
+            .map_err(|e| {
+                BackupError::InvalidConfig(format!(
+                    "{} of backup with {}",
+                    e,
+                    self.destination_id()
+                ))
+            })
+    }
+
+    /// Returns the configured `space_headroom`, no headroom if unset.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(SpaceHeadroom)`: The headroom as size in bytes or percentage below 100.
+    /// - `Err(BackupError::InvalidConfig)`: If the headroom is neither a valid size string nor percentage.
+    pub fn space_headroom(&self) -> Result<SpaceHeadroom, BackupError> {
+        let Some(space_headroom) = self.space_headroom.as_deref() else {
+            return Ok(SpaceHeadroom::Bytes(0));
+        };
+        let invalid = || {
+            BackupError::InvalidConfig(format!(
+                "Invalid space headroom '{}' of backup with {}. Must be a size like 10G or a percentage like 5%.",
+                space_headroom, self.destination_id()
+            ))
+        };
+        match space_headroom.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(percent) if (0.0..100.0).contains(&percent) => {
+                    Ok(SpaceHeadroom::Percent(percent))
+                }
+                _ => Err(invalid()),
+            },
+            None => match convert_to_byte_size(space_headroom) {
+                Ok(Some(bytes)) => Ok(SpaceHeadroom::Bytes(bytes)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+/// The notifications sent at the end of a run.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+pub struct Notification {
+    /// An email with the outcome of every backup.
+    /// If not provided, no email is sent.
+    pub email: Option<EmailNotification>,
+}
+
+/// The SMTP settings of the email sent at the end of a run.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+pub struct EmailNotification {
+    /// The host name of the SMTP server.
+    pub smtp_host: String,
+    /// The port of the SMTP server.
+    /// If not provided, the default port of the `tls` mode is used, 587, 465 or 25.
+    pub smtp_port: Option<u16>,
+    /// How the connection to the SMTP server is encrypted.
+    /// If not provided, STARTTLS is required.
+    pub tls: Option<SmtpTls>,
+    /// The sender address, like `dd-back-up <backup@example.com>`.
+    pub from: String,
+    /// The recipient addresses.
+    pub to: Vec<String>,
+    /// The user name to log in to the SMTP server.
+    /// If not provided, no login is done.
+    pub username: Option<String>,
+    /// The password to log in to the SMTP server, used with `username`.
+    pub password: Option<String>,
+}
+
+/// How the connection to the SMTP server is encrypted.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrades the plain connection with STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// Connects with TLS right away, usually on port 465.
+    Tls,
+    /// Doesn't encrypt the connection, only meant for a relay on localhost, usually on port 25.
+    None,
+}
+
+impl SmtpTls {
+    /// Returns the port SMTP servers usually listen on with this encryption.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::Starttls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+/// A file of a config dir besides `main.json`, contributing backups, see `Config::from_dir`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFragment {
+    /// The backup configurations, appended to the ones of the other files.
+    backups: Vec<BackupConfig>,
+    /// The mount path of the fragment's backups which don't set their own.
+    mountpath: Option<String>,
 }
 
 /// Represents the configuration containing multiple backup configurations.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     /// The list of backup configurations.
     /// Each configuration specifies the destination backup filesystem or partition
@@ -62,6 +681,38 @@ pub struct Config {
     /// The path on which the destination filesystem will be mounted.
     /// If not provided, the default mount path will be used.
     pub mountpath: Option<String>,
+    /// The only paths destination filesystems may be mounted on, guarding against shadowing
+    /// an existing mount like `/home` with a typo in `mountpath`.
+    /// If not provided, any mount path is allowed.
+    pub allowed_mountpaths: Option<Vec<String>>,
+    /// The path of the SQLite catalog recording every completed backup.
+    /// If not provided, no catalog is written.
+    pub catalog_path: Option<String>,
+    /// The path of a JSON-lines file, to which the outcome of every backup is appended.
+    /// If not provided, no history is written.
+    pub history_file: Option<String>,
+    /// The URL of a healthchecks.io-style monitor, pinged at `<url>/start` when a run starts,
+    /// at `<url>` when it succeeds and at `<url>/fail` when it fails.
+    /// If not provided, no monitor is pinged.
+    pub healthcheck_url: Option<String>,
+    /// The URL the outcome of every backup is posted to as JSON at the end of a run.
+    /// If not provided, no webhook is posted.
+    pub webhook_url: Option<String>,
+    /// The maximum number of seconds the webhook request may take.
+    /// If not provided, 10 seconds are used.
+    pub webhook_timeout: Option<u64>,
+    /// A token sent as `Authorization: Bearer <token>` header with the webhook request.
+    /// If not provided, no authorization header is sent.
+    pub webhook_token: Option<String>,
+    /// The path of the `lsblk` binary, the `LSBLK` env var takes precedence.
+    /// If not provided, `lsblk` is looked up in `PATH`.
+    pub lsblk_path: Option<String>,
+    /// Additional columns requested from `lsblk` with `-o`, like `TRAN` or `VENDOR`.
+    /// Their values are kept with the block device information, which is logged at debug level.
+    pub lsblk_columns: Option<Vec<String>>,
+    /// The notifications sent at the end of a run, skipped on dry runs.
+    /// If not provided, no notifications are sent.
+    pub notification: Option<Notification>,
 }
 
 impl Config {
@@ -70,37 +721,230 @@ impl Config {
     /// # Returns
     ///
     /// - `Ok(Config)`: If the configuration file is successfully read and parsed.
-    /// - `Err(String)`: If there is an error reading or parsing the configuration file.
-    pub fn new(config_file_path: &Option<String>) -> Result<Config, String> {
+    /// - `Err(BackupError)`: If there is an error reading or parsing the configuration file.
+    pub fn new(config_file_path: &Option<String>) -> Result<Config, BackupError> {
         let config = Self::validate_config(Self::read_config_file(config_file_path))?;
         debug!("{:?}", config);
         Ok(config)
     }
 
+    /// Creates a new `Config` instance by merging the `*.json` files in `config_dir`.
+    ///
+    /// The top-level fields are read from `main.json`, if present. The `backups` of all files are
+    /// concatenated, in the order of the file names after the ones of `main.json`. Other files may
+    /// only contain `backups` and a `mountpath`, which applies to their backups without one.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Config)`: If the files are successfully read, parsed and the merged configuration is valid.
+    /// - `Err(BackupError::InvalidConfig)`: If a destination UUID is configured in several files,
+    ///   naming both, or the merged configuration isn't valid.
+    /// - `Err(BackupError)`: If there is an error reading or parsing a file.
+    pub fn from_dir(config_dir: &str) -> Result<Config, BackupError> {
+        let config = Self::validate_config(Self::read_config_dir(Path::new(config_dir)))?;
+        debug!("{:?}", config);
+        Ok(config)
+    }
+
+    /// Reads and merges the `*.json` files in `config_dir`, see `from_dir`.
+    fn read_config_dir(config_dir: &Path) -> Result<Config, BackupError> {
+        let mut config_file_paths = fs::read_dir(config_dir)
+            .map_err(BackupError::io(format!(
+                "Failed to read config dir {}",
+                config_dir.display()
+            )))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "json"))
+            .collect::<Vec<PathBuf>>();
+        config_file_paths.sort();
+        if config_file_paths.is_empty() {
+            return Err(BackupError::InvalidConfig(format!(
+                "No *.json config files found in {}",
+                config_dir.display()
+            )));
+        }
+
+        let main_config_file_path = config_dir.join(MAIN_CONFIG_FILE_NAME);
+        let mut config: Config = match config_file_paths.contains(&main_config_file_path) {
+            true => Self::read_config_file_at(&main_config_file_path)?,
+            false => Config::default(),
+        };
+        for backup in &config.backups {
+            backup.validate_destination_id()?;
+        }
+
+        // the file of each destination filesystem, to name both files of a duplicate
+        let mut destination_files: HashMap<DestinationId, &PathBuf> = HashMap::new();
+        for backup in &config.backups {
+            for destination in backup.all_destination_ids() {
+                destination_files.insert(destination, &main_config_file_path);
+            }
+        }
+        for config_file_path in config_file_paths
+            .iter()
+            .filter(|path| **path != main_config_file_path)
+        {
+            let fragment: ConfigFragment = Self::read_config_file_at(config_file_path)?;
+            for mut backup in fragment.backups {
+                backup.validate_destination_id()?;
+                for destination in backup.all_destination_ids() {
+                    if let Some(destination_file) =
+                        destination_files.insert(destination.clone(), config_file_path)
+                    {
+                        return Err(BackupError::InvalidConfig(format!(
+                            "Duplicate {} in {}, already configured in {}",
+                            destination,
+                            config_file_path.display(),
+                            destination_file.display()
+                        )));
+                    }
+                }
+                backup.mountpath = backup.mountpath.or(fragment.mountpath.clone());
+                config.backups.push(backup);
+            }
+        }
+        info!(
+            "Merged {} config files from {}",
+            config_file_paths.len(),
+            config_dir.display()
+        );
+        Ok(config)
+    }
+
     /// Reads the configuration file and returns a `HashMap` of destination devices to `BackUpConfig`.
     ///
+    /// The file is parsed as JSON, YAML or TOML depending on its extension, see `parse_config`.
+    /// The file is looked up with `resolve_config_file_path`.
+    ///
+    /// Instead of a file, the configuration is read as JSON from stdin if `config_file_path` is `-`,
+    /// or from the `DD_BACKUP_CONFIG` env var if no `config_file_path` is given, see `read_config`.
+    ///
     /// # Returns
     ///
     /// - `Ok(HashMap<String, BackUpConfig>)`: If the configuration file is successfully read and parsed.
-    /// - `Err(String)`: If there is an error reading or parsing the configuration file.
-    fn read_config_file(config_file_path: &Option<String>) -> Result<Config, String> {
-        let config_file_path = match config_file_path {
-            Some(path_string) => Ok(PathBuf::from(path_string)),
-            None => Self::default_config_file_path(),
-        }?;
+    /// - `Err(BackupError)`: If there is an error reading or parsing the configuration file.
+    pub fn read_config_file(config_file_path: &Option<String>) -> Result<Config, BackupError> {
+        let config =
+            Self::read_config(config_file_path, env::var(CONFIG_ENV_VAR).ok(), io::stdin())?;
+        for backup in &config.backups {
+            backup.validate_destination_id()?;
+        }
+        Ok(config)
+    }
 
-        match File::open(&config_file_path) {
-            Ok(config_file) => {
-                let parsed_config: Result<Config, _> = serde_json::from_reader(config_file);
+    /// Reads the configuration from the first source present: the file at `config_file_path`,
+    /// or `stdin` if it is `-`, then the JSON of the `env_config`, then the discovered or default config file.
+    fn read_config(
+        config_file_path: &Option<String>,
+        env_config: Option<String>,
+        mut stdin: impl Read,
+    ) -> Result<Config, BackupError> {
+        let env_config = env_config.filter(|env_config| !env_config.trim().is_empty());
+        match (config_file_path.as_deref(), env_config) {
+            (Some(STDIN_CONFIG_FILE_PATH), _) => {
+                info!("Reading config from stdin");
+                let mut content = String::new();
+                stdin
+                    .read_to_string(&mut content)
+                    .map_err(BackupError::io("Failed to read config from stdin"))?;
+                Self::parse_config(&content, Path::new(STDIN_CONFIG_FILE_PATH))
+            }
+            (None, Some(env_config)) => {
+                info!("Using config from env var {}", CONFIG_ENV_VAR);
+                Self::parse_config(&env_config, Path::new(CONFIG_ENV_VAR))
+            }
+            _ => Self::read_config_file_at(&Self::resolve_config_file_path(config_file_path)?),
+        }
+    }
+
+    /// Reads and parses the configuration file at `config_file_path`, see `parse_config`.
+    fn read_config_file_at<T: DeserializeOwned>(config_file_path: &Path) -> Result<T, BackupError> {
+        match fs::read_to_string(config_file_path) {
+            Ok(content) => Self::parse_config(&content, config_file_path),
+            Err(source) => Err(BackupError::ConfigRead {
+                path: config_file_path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Returns the path of the configuration file to read, the explicit `config_file_path` if given.
+    ///
+    /// Without an explicit path, a `.dd-back-up.json` in the current directory or its parents is
+    /// used, before falling back to the default config file in the home directory.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(PathBuf)`: The path of the configuration file, which may not exist.
+    /// - `Err(BackupError)`: If the config home directory can't be read or created.
+    pub fn resolve_config_file_path(
+        config_file_path: &Option<String>,
+    ) -> Result<PathBuf, BackupError> {
+        match config_file_path {
+            Some(path_string) => Ok(PathBuf::from(path_string)),
+            None => match env::current_dir().ok().and_then(|current_dir| {
+                Self::discover_config_file(&current_dir, dirs::home_dir().as_deref())
+            }) {
+                Some(discovered_config_file_path) => {
+                    info!(
+                        "Using discovered config file {}",
+                        discovered_config_file_path.display()
+                    );
+                    Ok(discovered_config_file_path)
+                }
+                None => Self::default_config_file_path(),
+            },
+        }
+    }
 
-                parsed_config.map_err(|e| format!("Cannot parse config file -> {}", e))
+    /// Parses the `content` of the configuration file at `config_file_path`.
+    ///
+    /// The format is chosen by the extension: `.json` (or none), `.yaml`/`.yml` or `.toml`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(T)`: If the content is successfully parsed.
+    /// - `Err(BackupError::ConfigParse)`: If the extension is unknown or the content can't be parsed.
+    ///   Errors of JSON and YAML name the line and column, and show the offending line, see `located_parse_error`.
+    fn parse_config<T: DeserializeOwned>(
+        content: &str,
+        config_file_path: &Path,
+    ) -> Result<T, BackupError> {
+        let extension = config_file_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            None | Some("json") => serde_json::from_str(content).map_err(|e| {
+                located_parse_error(
+                    &e.to_string(),
+                    content,
+                    config_file_path,
+                    e.line(),
+                    e.column(),
+                )
+            }),
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(content).map_err(|e| match e.location() {
+                    Some(location) => located_parse_error(
+                        &e.to_string(),
+                        content,
+                        config_file_path,
+                        location.line(),
+                        location.column(),
+                    ),
+                    None => e.to_string(),
+                })
+            }
+            Some("toml") => toml::from_str(content).map_err(|e| e.to_string()),
+            Some(extension) => {
+                return Err(BackupError::InvalidConfig(format!(
+                "Unknown config file extension '.{}' of {}, expected .json, .yaml, .yml or .toml",
+                extension,
+                config_file_path.display()
+            )))
             }
-            Err(e) => Err(format!(
-                "{}: {}",
-                e,
-                config_file_path.as_path().to_str().unwrap(),
-            )),
         }
+        .map_err(BackupError::ConfigParse)
     }
 
     /// Validates the configuration to ensure unique UUIDs and serial numbers.
@@ -112,56 +956,301 @@ impl Config {
     /// # Returns
     ///
     /// - `Ok(Config)`: If the configuration is valid.
-    /// - `Err(String)`: If the configuration is not valid, with a descriptive error message.
-    pub fn validate_config(config: Result<Config, String>) -> Result<Config, String> {
+    /// - `Err(BackupError::InvalidConfig)`: If the configuration is not valid, with a descriptive error message.
+    pub fn validate_config(config: Result<Config, BackupError>) -> Result<Config, BackupError> {
         let config = config?;
+        Self::validate_values(&config).map_err(BackupError::InvalidConfig)?;
+        info!("Config is successfully validated");
+        Ok(config)
+    }
 
-        // Check for unique UUIDs
-        let uuids: HashSet<&String> = config.backups.iter().map(|backup| &backup.uuid).collect();
-        if uuids.len() != config.backups.len() {
-            return Err("Duplicate UUID found in backups".to_string());
+    /// Checks the values of the configuration, see `validate_config`.
+    fn validate_values(config: &Config) -> Result<(), String> {
+        Self::validate_top_level_values(config)?;
+        for backup in &config.backups {
+            Self::validate_backup_values(backup)?;
         }
+        Ok(())
+    }
 
-        for backup in &config.backups {
-            // Check for unique serial numbers within each backup
-            let serials: HashSet<&String> = backup
+    /// Returns the errors `validate_config` finds, the first one of the top-level fields
+    /// and of each backup, instead of only the first one of the whole configuration.
+    pub fn validation_errors(config: &Config) -> Vec<String> {
+        std::iter::once(Self::validate_top_level_values(config))
+            .chain(config.backups.iter().map(Self::validate_backup_values))
+            .filter_map(Result::err)
+            .collect()
+    }
+
+    /// Checks the top-level values of the configuration and the UUIDs across backups, see `validate_config`.
+    fn validate_top_level_values(config: &Config) -> Result<(), String> {
+        // Check for parsable email addresses, so a notification doesn't fail only at the end of a run
+        if let Some(email) = config
+            .notification
+            .as_ref()
+            .and_then(|notification| notification.email.as_ref())
+        {
+            if email.to.is_empty() {
+                return Err("Email notification needs at least one `to` address".to_string());
+            }
+            for address in std::iter::once(&email.from).chain(&email.to) {
+                address
+                    .parse::<Mailbox>()
+                    .map_err(|e| format!("Invalid email address '{}': {}", address, e))?;
+            }
+        }
+
+        // Check for plain column names, since they are passed to `lsblk -o`
+        for column in config.lsblk_columns.iter().flatten() {
+            if column.is_empty()
+                || !column
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ':')
+            {
+                return Err(format!("Invalid lsblk column '{}'", column));
+            }
+        }
+
+        // Check for unique UUIDs and labels, including mirrors and the destinations of devices,
+        // so a destination filesystem is only mounted with the settings of one backup
+        let destinations: Vec<DestinationId> = config
+            .backups
+            .iter()
+            .flat_map(|backup| backup.all_destination_ids())
+            .collect();
+        if destinations.iter().collect::<HashSet<_>>().len() != destinations.len() {
+            return Err("Duplicate UUID or label found in backups".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks the values of a single `backup`, see `validate_config`.
+    fn validate_backup_values(backup: &BackupConfig) -> Result<(), String> {
+        if let Some(destination_path) = &backup.destination_path {
+            validate_destination_path(
+                destination_path,
+                &format!("backup with {}", backup.destination_id()),
+            )?;
+        }
+
+        if let Some(name_template) = &backup.name_template {
+            // Check for known placeholders in the name template
+            let unknown_placeholders =
+                unknown_placeholders(name_template, &NAME_TEMPLATE_PLACEHOLDERS);
+            if !unknown_placeholders.is_empty() {
+                return Err(format!(
+                    "Unknown placeholders {} in name template of backup with {}. Known are {}",
+                    unknown_placeholders.join(", "),
+                    backup.destination_id(),
+                    NAME_TEMPLATE_PLACEHOLDERS.join(", ")
+                ));
+            }
+
+            // Check that the names of different days differ and stay in the backup dir
+            if !name_template.contains("{date}") || name_template.contains('/') {
+                return Err(format!(
+                    "Invalid name template '{}' of backup with {}. Must contain {{date}} and no '/'.",
+                    name_template, backup.destination_id()
+                ));
+            }
+        }
+
+        // Check that the block size is a positive size
+        backup.block_size_in_bytes()?;
+
+        // Check that the bandwidth is a positive size
+        backup.max_bandwidth_in_bytes()?;
+
+        // Check that the space headroom is a size or percentage
+        backup.space_headroom()?;
+
+        // Check that sparse images are written directly, since holes can't be seeked in a pipe
+        if backup.sparse == Some(true) && backup.compression.is_some() {
+            return Err(format!(
+                "Backup with {} can't combine `sparse` and `compression`",
+                backup.destination_id()
+            ));
+        }
+
+        // Check for plain mount options and type, since they are passed to `mount`
+        for (kind, value) in [
+            ("mount options", &backup.mount_options),
+            ("mount type", &backup.mount_type),
+        ] {
+            if let Some(value) = value {
+                if !is_plain_mount_argument(value) {
+                    return Err(format!(
+                        "Invalid {} '{}' of backup with {}",
+                        kind,
+                        value,
+                        backup.destination_id()
+                    ));
+                }
+            }
+        }
+
+        // Check that dd options are plain operands, which don't replace the source or image
+        for dd_option in backup.dd_options.iter().flatten() {
+            if !is_dd_option(dd_option) {
+                return Err(format!(
+                    "Invalid dd option '{}' of backup with {}. Must look like key=value.",
+                    dd_option,
+                    backup.destination_id()
+                ));
+            }
+            if dd_option.starts_with("if=") || dd_option.starts_with("of=") {
+                return Err(format!(
+                    "Invalid dd option '{}' of backup with {}. The input and output file are set by dd_backup.",
+                    dd_option, backup.destination_id()
+                ));
+            }
+        }
+
+        // Check for unique serial numbers, WWNs, PARTUUIDs and labels within each backup
+        let identifiers_of = |identifier: fn(&BackupDevice) -> &Option<String>| {
+            backup
                 .backup_devices
                 .iter()
-                .map(|device| &device.serial)
-                .collect();
-            if serials.len() != backup.backup_devices.len() {
+                .filter_map(|device| identifier(device).as_ref())
+                .collect::<Vec<&String>>()
+        };
+        for (kind, identifiers) in [
+            ("serial number", identifiers_of(|device| &device.serial)),
+            ("WWN", identifiers_of(|device| &device.wwn)),
+            ("PARTUUID", identifiers_of(|device| &device.partuuid)),
+            (
+                "partition label",
+                identifiers_of(|device| &device.partlabel),
+            ),
+        ] {
+            if identifiers.iter().collect::<HashSet<_>>().len() != identifiers.len() {
+                return Err(format!(
+                    "Duplicate {} found in backup with {}",
+                    kind,
+                    backup.destination_id()
+                ));
+            }
+        }
+
+        for device in &backup.backup_devices {
+            // Check that a device is identified by exactly one of serial, wwn, partuuid, partlabel or match
+            let identifiers = [
+                device.serial.is_some(),
+                device.wwn.is_some(),
+                device.partuuid.is_some(),
+                device.partlabel.is_some(),
+                device.device_match.is_some(),
+            ];
+            if identifiers.iter().filter(|&&is_set| is_set).count() != 1 {
                 return Err(format!(
-                    "Duplicate serial number found in backup with UUID '{}'",
-                    backup.uuid
+                    "Device in backup with {} needs exactly one of `serial`, `wwn`, `partuuid`, `partlabel` or `match`.",
+                    backup.destination_id()
                 ));
             }
+            if let Some(device_match) = &device.device_match {
+                if device_match.model.is_none() && device_match.serial_prefix.is_none() {
+                    return Err(format!(
+                        "Empty match for device in backup with {}. Needs `model` or `serial_prefix`.",
+                        backup.destination_id()
+                    ));
+                }
+            }
 
             // Check if the number of copies is specified and greater than 0
-            for device in &backup.backup_devices {
-                if let Some(copies) = device.copies {
-                    if copies == 0 {
-                        return Err(format!(
-                        "Invalid number of copies for device with serial '{}'. Must be greater than 0.",
-                        device.serial
+            if let Some(copies) = device.copies {
+                if copies == 0 {
+                    return Err(format!(
+                        "Invalid number of copies for device '{}'. Must be greater than 0.",
+                        device.identifier()
                     ));
-                    }
                 }
             }
+
+            // Check if the maximum age is specified and greater than 0
+            if device.max_age_days == Some(0) {
+                return Err(format!(
+                    "Invalid maximum age for device '{}'. Must be greater than 0 days.",
+                    device.identifier()
+                ));
+            }
+
+            // Check that the expected size is a positive size
+            device.expected_size_in_bytes()?;
         }
-        info!("Config is successfully validated");
-        Ok(config)
+        Ok(())
+    }
+
+    /// Searches `start_dir` and its parents for a `.dd-back-up.json`, like git looks for `.git`.
+    ///
+    /// The search stops after `stop_dir` (usually `$HOME`) if `start_dir` is below it,
+    /// otherwise at the filesystem root.
+    ///
+    /// # Returns
+    ///
+    /// - `Some(PathBuf)`: The path of the nearest discovered configuration file.
+    /// - `None`: If there is no such file up to the stop dir.
+    pub fn discover_config_file(start_dir: &Path, stop_dir: Option<&Path>) -> Option<PathBuf> {
+        for dir in start_dir.ancestors() {
+            let config_file_path = dir.join(DISCOVERED_CONFIG_FILE_NAME);
+            if config_file_path.is_file() {
+                return Some(config_file_path);
+            }
+            if Some(dir) == stop_dir {
+                break;
+            }
+        }
+        None
     }
 
     /// Returns the default path to the configuration file.
     ///
+    /// In the config home, `config.json` is preferred over `config.yaml`, `config.yml` and `config.toml`.
+    /// Falls back to the legacy `~/.dd-back-up/config.json` of older versions, see `migrated_config_file_path`.
+    ///
     /// # Returns
     ///
     /// - `Ok(PathBuf)`: The path to the configuration file if it exists.
-    /// - `Err(String)`: If there is an error getting the configuration file path or the path doesn't exist.
-    pub fn default_config_file_path() -> Result<PathBuf, String> {
-        Ok(Self::config_home_path()
-            .map_err(|e| format!("Failed reading or creating data directory -> {}", e))?
-            .join("config.json"))
+    /// - `Err(BackupError)`: If there is an error getting the configuration file path or the path doesn't exist.
+    pub fn default_config_file_path() -> Result<PathBuf, BackupError> {
+        let config_file_path = Self::config_file_in(
+            &Self::config_home_path()
+                .map_err(|e| format!("Failed reading or creating data directory -> {}", e))?,
+        );
+        let legacy_config_file_path = dirs::home_dir()
+            .ok_or("Failed to find Home dir")?
+            .join(".dd-back-up")
+            .join("config.json");
+        Ok(Self::migrated_config_file_path(
+            config_file_path,
+            legacy_config_file_path,
+        ))
+    }
+
+    /// Returns the first present of the default config file names in `dir`, or `config.json` if none is.
+    fn config_file_in(dir: &Path) -> PathBuf {
+        DEFAULT_CONFIG_FILE_NAMES
+            .iter()
+            .map(|file_name| dir.join(file_name))
+            .find(|config_file_path| config_file_path.is_file())
+            .unwrap_or(dir.join(DEFAULT_CONFIG_FILE_NAMES[0]))
+    }
+
+    /// Returns the legacy config file path if only it exists, logging a deprecation notice,
+    /// otherwise the current `config_file_path`.
+    fn migrated_config_file_path(
+        config_file_path: PathBuf,
+        legacy_config_file_path: PathBuf,
+    ) -> PathBuf {
+        if !config_file_path.exists() && legacy_config_file_path.is_file() {
+            warn!(
+                "Reading config from deprecated location {}, please move it to {}",
+                legacy_config_file_path.display(),
+                config_file_path.display()
+            );
+            legacy_config_file_path
+        } else {
+            config_file_path
+        }
     }
 
     /// Returns the path to the home directory where the configuration file is located.
@@ -170,8 +1259,8 @@ impl Config {
     /// # Returns
     ///
     /// - `Ok(PathBuf)`: The path to the home directory.
-    /// - `Err(String)`: If there is an error getting the home directory path or creating the data directory.
-    pub fn config_home_path() -> Result<PathBuf, String> {
+    /// - `Err(BackupError)`: If there is an error getting the home directory path or creating the data directory.
+    pub fn config_home_path() -> Result<PathBuf, BackupError> {
         let data_dir = dirs::home_dir()
             .ok_or("Failed to find Home dir")?
             .join(".config")
@@ -193,33 +1282,130 @@ impl Config {
     /// # Returns
     ///
     /// - `Ok(())`: If the data directory is successfully created or already exists.
-    /// - `Err(String)`: If there is an error creating the data directory.
-    fn create_data_directory(data_dir: &PathBuf) -> Result<(), String> {
-        fs::create_dir(data_dir).map_err(|e| {
-            format!(
-                "Failed to create data directory at {}, Error -> {}",
-                data_dir.to_string_lossy(),
-                e
-            )
-        })
+    /// - `Err(BackupError::Io)`: If there is an error creating the data directory.
+    fn create_data_directory(data_dir: &PathBuf) -> Result<(), BackupError> {
+        fs::create_dir(data_dir).map_err(BackupError::io(format!(
+            "Failed to create data directory at {}",
+            data_dir.to_string_lossy()
+        )))
+    }
+}
+
+/// Formats the `message` of an error parsing the `content` of the config file at `config_file_path`,
+/// located at the 1-based `line` and `column`, like a compiler error.
+///
+/// The location is moved from the `message` to the front, followed by the offending line
+/// and a caret below the column, e.g.:
+///
+/// ```text
+/// config.json:4:3: trailing comma
+///    4 |   },
+///      |   ^
+/// ```
+fn located_parse_error(
+    message: &str,
+    content: &str,
+    config_file_path: &Path,
+    line: usize,
+    column: usize,
+) -> String {
+    let location_suffix = format!(" at line {} column {}", line, column);
+    let message = message.replacen(&location_suffix, "", 1);
+    let mut located_message = format!(
+        "{}:{}:{}: {}",
+        config_file_path.display(),
+        line,
+        column,
+        message
+    );
+    if let Some(offending_line) = content.lines().nth(line.saturating_sub(1)) {
+        let gutter = format!("{:>4} | ", line);
+        located_message.push_str(&format!(
+            "\n{}{}\n{:>width$}{}^",
+            gutter,
+            offending_line,
+            "| ",
+            " ".repeat(column.saturating_sub(1)),
+            width = gutter.len()
+        ));
     }
+    located_message
+}
+
+/// Checks that the `destination_path` of `owner`, like a backup, stays within the mounted filesystem
+/// and only contains known placeholders.
+///
+/// # Returns
+///
+/// - `Ok(())`: If the destination path is valid.
+/// - `Err(String)`: The error message naming the `owner`, if it isn't.
+pub fn validate_destination_path(destination_path: &str, owner: &str) -> Result<(), String> {
+    if escapes_base_dir(destination_path) {
+        return Err(format!(
+            "Destination path '{}' of {} leaves the mounted filesystem",
+            destination_path, owner
+        ));
+    }
+
+    let unknown_placeholders = unknown_placeholders(destination_path, &DATE_PLACEHOLDERS);
+    if !unknown_placeholders.is_empty() {
+        return Err(format!(
+            "Unknown placeholders {} in destination path of {}. Known are {}",
+            unknown_placeholders.join(", "),
+            owner,
+            DATE_PLACEHOLDERS.join(", ")
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_validate_config_destination_path_traversal() {
+        let config = |destination_path: &str| Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                destination_path: Some(destination_path.to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config("./backups/../other"))).is_ok());
+        assert!(Config::validate_config(Ok(config("../backups"))).is_err());
+        assert!(Config::validate_config(Ok(config("./backups/../../other"))).is_err());
+        assert!(Config::validate_config(Ok(config("/../backups"))).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_destination_path_placeholders() {
+        let config = |destination_path: &str| Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                destination_path: Some(destination_path.to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config("./{year}/{month}"))).is_ok());
+        assert!(Config::validate_config(Ok(config("./{day}"))).is_err());
+    }
+
     #[test]
     fn test_validate_config_success() {
         let device1 = BackupDevice {
-            serial: "device1".to_string(),
+            serial: Some("device1".to_string()),
             copies: Some(1),
             name: None,
+            ..Default::default()
         };
         let device2 = BackupDevice {
-            serial: "device2".to_string(),
+            serial: Some("device2".to_string()),
             copies: Some(1),
             name: None,
+            ..Default::default()
         };
         let backup1 = BackupConfig {
             uuid: "backup1".to_string(),
@@ -228,6 +1414,10 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let backup2 = BackupConfig {
             uuid: "backup2".to_string(),
@@ -236,20 +1426,38 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let config = Config {
             backups: vec![backup1, backup2],
             mountpath: Some("/mnt".to_string()),
+            ..Default::default()
         };
         assert!(Config::validate_config(Ok(config)).is_ok());
     }
 
+    #[test]
+    fn test_validate_config_lsblk_columns() {
+        let config = |column: &str| Config {
+            lsblk_columns: Some(vec![column.to_string()]),
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config("TRAN"))).is_ok());
+        assert!(Config::validate_config(Ok(config("MAJ:MIN"))).is_ok());
+        assert!(Config::validate_config(Ok(config("TRAN,-h"))).is_err());
+        assert!(Config::validate_config(Ok(config(""))).is_err());
+    }
+
     #[test]
     fn test_validate_config_duplicate_uuids() {
         let device = BackupDevice {
-            serial: "device".to_string(),
+            serial: Some("device".to_string()),
             copies: Some(1),
             name: None,
+            ..Default::default()
         };
         let backup1 = BackupConfig {
             uuid: "backup".to_string(),
@@ -258,6 +1466,10 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let backup2 = BackupConfig {
             uuid: "backup".to_string(),
@@ -266,10 +1478,105 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let config = Config {
             backups: vec![backup1, backup2],
             mountpath: Some("/mnt".to_string()),
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_duplicate_mirror_uuids() {
+        let backup = |uuid: &str, mirror: &str| BackupConfig {
+            uuid: uuid.to_string(),
+            mirrors: Some(vec![mirror.to_string()]),
+            ..Default::default()
+        };
+        let config = Config {
+            backups: vec![backup("backup1", "mirror1"), backup("backup2", "mirror2")],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_ok());
+
+        let config = Config {
+            backups: vec![backup("backup1", "mirror"), backup("backup2", "mirror")],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_err());
+
+        let config = Config {
+            backups: vec![backup("backup1", "backup2"), backup("backup2", "mirror")],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_err());
+    }
+
+    #[test]
+    fn test_device_destination_uuids() {
+        let device = |serial: &str, destination_uuid: Option<&str>| BackupDevice {
+            serial: Some(serial.to_string()),
+            destination_uuid: destination_uuid.map(String::from),
+            ..Default::default()
+        };
+        let backup = BackupConfig {
+            uuid: "backup".to_string(),
+            mirrors: Some(vec!["mirror".to_string()]),
+            backup_devices: vec![
+                device("device1", None),
+                device("device2", Some("other")),
+                device("device3", Some("other")),
+                device("device4", Some("backup")),
+                device("device5", Some("mirror")),
+            ],
+            ..Default::default()
+        };
+
+        let uuid = |uuid: &str| DestinationId::Uuid(uuid.to_string());
+        assert_eq!(
+            backup.all_destination_ids(),
+            vec![uuid("backup"), uuid("mirror"), uuid("other")]
+        );
+        assert_eq!(backup.destination_kind(&uuid("mirror")), "Mirror");
+        assert_eq!(
+            backup.destination_kind(&uuid("other")),
+            "Device destination"
+        );
+        assert!(backup.is_mirror(&uuid("mirror")));
+        assert!(!backup.is_mirror(&backup.destination_id()));
+        assert!(!backup.is_mirror(&uuid("other")));
+        let backed_up_to = |destination_uuid: &str| {
+            backup
+                .backup_devices
+                .iter()
+                .filter(|backup_device| {
+                    backup_device.is_backed_up_to(&uuid(destination_uuid), &backup)
+                })
+                .map(|backup_device| backup_device.identifier())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(backed_up_to("backup"), vec!["device1", "device4"]);
+        assert_eq!(
+            backed_up_to("mirror"),
+            vec!["device1", "device4", "device5"]
+        );
+        assert_eq!(backed_up_to("other"), vec!["device2", "device3"]);
+
+        // a device destination may not be configured in another backup
+        let config = Config {
+            backups: vec![
+                backup,
+                BackupConfig {
+                    uuid: "other".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
         };
         assert!(Config::validate_config(Ok(config)).is_err());
     }
@@ -277,9 +1584,10 @@ mod tests {
     #[test]
     fn test_validate_config_duplicate_serials() {
         let device = BackupDevice {
-            serial: "device".to_string(),
+            serial: Some("device".to_string()),
             copies: Some(1),
             name: None,
+            ..Default::default()
         };
         let backup = BackupConfig {
             uuid: "backup".to_string(),
@@ -288,20 +1596,82 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let config = Config {
             backups: vec![backup],
             mountpath: Some("/mnt".to_string()),
+            ..Default::default()
         };
         assert!(Config::validate_config(Ok(config)).is_err());
     }
 
+    #[test]
+    fn test_validate_config_device_identification() {
+        let matched_device = BackupDevice {
+            device_match: Some(DeviceMatch {
+                model: Some("model".to_string()),
+                serial_prefix: None,
+            }),
+            ..Default::default()
+        };
+        let wwn_device = BackupDevice {
+            wwn: Some("0x5000000000000001".to_string()),
+            ..Default::default()
+        };
+        let config = Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                backup_devices: vec![
+                    matched_device.clone(),
+                    matched_device.clone(),
+                    wwn_device.clone(),
+                ],
+                ..Default::default()
+            }],
+            mountpath: None,
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_ok());
+
+        for invalid_device in [
+            BackupDevice::default(),
+            BackupDevice {
+                serial: Some("device".to_string()),
+                ..matched_device
+            },
+            BackupDevice {
+                serial: Some("device".to_string()),
+                ..wwn_device
+            },
+            BackupDevice {
+                device_match: Some(DeviceMatch::default()),
+                ..Default::default()
+            },
+        ] {
+            let config = Config {
+                backups: vec![BackupConfig {
+                    uuid: "backup".to_string(),
+                    backup_devices: vec![invalid_device],
+                    ..Default::default()
+                }],
+                mountpath: None,
+                ..Default::default()
+            };
+            assert!(Config::validate_config(Ok(config)).is_err());
+        }
+    }
+
     #[test]
     fn test_validate_config_zero_copies() {
         let device = BackupDevice {
-            serial: "device".to_string(),
+            serial: Some("device".to_string()),
             copies: Some(0),
             name: None,
+            ..Default::default()
         };
         let backup = BackupConfig {
             uuid: "backup".to_string(),
@@ -310,11 +1680,491 @@ mod tests {
             fsck_command: None,
             skip_fsck: None,
             skip_mount: None,
+            mountpath: None,
+            mount_options: None,
+            mount_type: None,
+            ..Default::default()
         };
         let config = Config {
             backups: vec![backup],
             mountpath: Some("/mnt".to_string()),
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config)).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_zero_max_age_days() {
+        let config = |max_age_days: u64| Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                backup_devices: vec![BackupDevice {
+                    serial: Some("device".to_string()),
+                    max_age_days: Some(max_age_days),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config(0))).is_err());
+        assert!(Config::validate_config(Ok(config(30))).is_ok());
+    }
+
+    #[test]
+    fn test_validate_config_mount_options() {
+        let config = |mount_options: &str, mount_type: &str| Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                mount_options: Some(mount_options.to_string()),
+                mount_type: Some(mount_type.to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config("uid=1000,gid=1000", "exfat"))).is_ok());
+        assert!(Config::validate_config(Ok(config("rw;reboot", "exfat"))).is_err());
+        assert!(Config::validate_config(Ok(config("rw", "ntfs3 -o x"))).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_sparse() {
+        let config = |compression: Option<Compression>| Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                sparse: Some(true),
+                compression,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(Config::validate_config(Ok(config(None))).is_ok());
+        assert!(Config::validate_config(Ok(config(Some(Compression::Zstd)))).is_err());
+    }
+
+    #[test]
+    fn test_validate_config_disabled() {
+        let config = Config {
+            backups: vec![BackupConfig {
+                uuid: "backup".to_string(),
+                backup_devices: vec![BackupDevice {
+                    serial: Some("device".to_string()),
+                    copies: Some(0),
+                    enabled: Some(false),
+                    ..Default::default()
+                }],
+                enabled: Some(false),
+                ..Default::default()
+            }],
+            ..Default::default()
         };
+        assert!(!config.backups[0].is_enabled());
+        assert!(!config.backups[0].backup_devices[0].is_enabled());
+        // disabled entries are still validated, so they can be enabled safely
         assert!(Config::validate_config(Ok(config)).is_err());
+        assert!(BackupConfig::default().is_enabled());
+        assert!(BackupDevice::default().is_enabled());
+    }
+
+    #[test]
+    fn test_discover_config_file() {
+        let home_dir = env::temp_dir().join(format!(
+            "dd_backup_test_discover_config_file_{}",
+            std::process::id()
+        ));
+        let project_dir = home_dir.join("project");
+        let nested_dir = project_dir.join("nested").join("deeper");
+        fs::create_dir_all(&nested_dir).unwrap();
+
+        // nothing to discover up to the stop dir
+        assert_eq!(
+            Config::discover_config_file(&nested_dir, Some(&home_dir)),
+            None
+        );
+
+        // the stop dir itself is searched
+        fs::write(home_dir.join(DISCOVERED_CONFIG_FILE_NAME), "{}").unwrap();
+        assert_eq!(
+            Config::discover_config_file(&nested_dir, Some(&home_dir)),
+            Some(home_dir.join(DISCOVERED_CONFIG_FILE_NAME))
+        );
+        // but not above it
+        assert_eq!(
+            Config::discover_config_file(&nested_dir, Some(&project_dir)),
+            None
+        );
+
+        // the nearest file wins
+        fs::write(project_dir.join(DISCOVERED_CONFIG_FILE_NAME), "{}").unwrap();
+        assert_eq!(
+            Config::discover_config_file(&nested_dir, Some(&home_dir)),
+            Some(project_dir.join(DISCOVERED_CONFIG_FILE_NAME))
+        );
+
+        fs::remove_dir_all(&home_dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_from_dir() {
+        let config_dir = env::temp_dir().join(format!(
+            "dd_backup_test_config_from_dir_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&config_dir).unwrap();
+        let backup = |uuid: &str| {
+            format!(
+                r#"{{"uuid": "{}", "backup_devices": [{{"serial": "{}"}}]}}"#,
+                uuid, uuid
+            )
+        };
+        fs::write(
+            config_dir.join(MAIN_CONFIG_FILE_NAME),
+            format!(
+                r#"{{"mountpath": "/mnt", "catalog_path": "/catalog.db", "backups": [{}]}}"#,
+                backup("main")
+            ),
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("b.json"),
+            format!(r#"{{"backups": [{}]}}"#, backup("b")),
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("a.json"),
+            format!(r#"{{"mountpath": "/media", "backups": [{}]}}"#, backup("a")),
+        )
+        .unwrap();
+        fs::write(config_dir.join("notes.txt"), "not a config").unwrap();
+
+        let config = Config::from_dir(config_dir.to_str().unwrap()).unwrap();
+        assert_eq!(
+            config
+                .backups
+                .iter()
+                .map(|backup| (backup.uuid.as_str(), backup.mountpath.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("main", None), ("a", Some("/media")), ("b", None)]
+        );
+        assert_eq!(config.mountpath.as_deref(), Some("/mnt"));
+        assert_eq!(config.catalog_path.as_deref(), Some("/catalog.db"));
+
+        // a duplicate names both files
+        fs::write(
+            config_dir.join("c.json"),
+            format!(r#"{{"backups": [{}]}}"#, backup("a")),
+        )
+        .unwrap();
+        let error = Config::from_dir(config_dir.to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("c.json") && error.contains("a.json"));
+
+        // only main.json may set other top-level fields
+        fs::write(
+            config_dir.join("c.json"),
+            r#"{"catalog_path": "/other.db", "backups": []}"#,
+        )
+        .unwrap();
+        assert!(Config::from_dir(config_dir.to_str().unwrap()).is_err());
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+
+    #[test]
+    fn test_fsck_policy_deserialization() {
+        let backup: BackupConfig = serde_json::from_str(
+            r#"{"backup_devices": [], "uuid": "backup", "fsck_policy": "warn"}"#,
+        )
+        .unwrap();
+        assert_eq!(backup.fsck_policy, Some(FsckPolicy::Warn));
+        assert!(serde_json::from_str::<BackupConfig>(
+            r#"{"backup_devices": [], "uuid": "backup", "fsck_policy": "ignore"}"#
+        )
+        .is_err());
+        assert_eq!(FsckPolicy::default(), FsckPolicy::Abort);
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let json = r#"{"mountpath": "/mnt", "backups": [{"uuid": "backup", "backup_devices": [{"serial": "device", "copies": 2}]}]}"#;
+        let yaml = "
+mountpath: /mnt
+backups:
+  - uuid: backup
+    backup_devices:
+      - serial: device
+        copies: 2
+";
+        let toml = r#"
+mountpath = "/mnt"
+
+[[backups]]
+uuid = "backup"
+
+[[backups.backup_devices]]
+serial = "device"
+copies = 2
+"#;
+        let expected = Config::parse_config::<Config>(json, Path::new("config.json")).unwrap();
+        assert_eq!(expected.backups[0].backup_devices[0].copies, Some(2));
+        assert_eq!(
+            Config::parse_config::<Config>(json, Path::new("config")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            Config::parse_config::<Config>(yaml, Path::new("config.yaml")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            Config::parse_config::<Config>(yaml, Path::new("config.YML")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            Config::parse_config::<Config>(toml, Path::new("config.toml")).unwrap(),
+            expected
+        );
+        assert!(matches!(
+            Config::parse_config::<Config>(yaml, Path::new("config.json")),
+            Err(BackupError::ConfigParse(_))
+        ));
+        assert_eq!(
+            Config::parse_config::<Config>(
+                "{\"backups\": [\n  {\"uuid\": \"backup\", \"backup_devices\": []},\n]}",
+                Path::new("config.json")
+            )
+            .unwrap_err()
+            .to_string(),
+            "Cannot parse config file -> config.json:3:1: trailing comma\n   3 | ]}\n     | ^"
+        );
+        assert!(
+            Config::parse_config::<Config>(json, Path::new("config.ini"))
+                .unwrap_err()
+                .to_string()
+                .starts_with("Unknown config file extension '.ini'")
+        );
+    }
+
+    #[test]
+    fn test_read_config() {
+        let config_file_path = env::temp_dir().join(format!(
+            "dd_backup_test_read_config_{}.json",
+            std::process::id()
+        ));
+        fs::write(
+            &config_file_path,
+            r#"{"mountpath": "/file", "backups": []}"#,
+        )
+        .unwrap();
+        let file_config_path = Some(config_file_path.to_str().unwrap().to_string());
+        let env_config = Some(r#"{"mountpath": "/env", "backups": []}"#.to_string());
+        let stdin = r#"{"mountpath": "/stdin", "backups": []}"#.as_bytes();
+
+        let mountpath = |config: Result<Config, BackupError>| config.unwrap().mountpath.unwrap();
+        assert_eq!(
+            mountpath(Config::read_config(
+                &file_config_path,
+                env_config.clone(),
+                stdin
+            )),
+            "/file"
+        );
+        assert_eq!(
+            mountpath(Config::read_config(
+                &Some("-".to_string()),
+                env_config.clone(),
+                stdin
+            )),
+            "/stdin"
+        );
+        assert_eq!(
+            mountpath(Config::read_config(&None, env_config, stdin)),
+            "/env"
+        );
+        assert!(matches!(
+            Config::read_config(&None, Some("mountpath: /env".to_string()), stdin),
+            Err(BackupError::ConfigParse(_))
+        ));
+
+        fs::remove_file(config_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_destination_id() {
+        let backup = |uuid: &str, label: Option<&str>| BackupConfig {
+            uuid: uuid.to_string(),
+            label: label.map(String::from),
+            mirrors: Some(vec!["mirror".to_string()]),
+            ..Default::default()
+        };
+
+        let labeled_backup = backup("", Some("backup"));
+        assert!(labeled_backup.validate_destination_id().is_ok());
+        assert_eq!(
+            labeled_backup.destination_ids(),
+            vec![
+                DestinationId::Label("backup".to_string()),
+                DestinationId::Uuid("mirror".to_string())
+            ]
+        );
+        assert_eq!(labeled_backup.destination_id().to_string(), "label backup");
+        assert!(backup("uuid1", None).validate_destination_id().is_ok());
+        assert_eq!(
+            backup("uuid1", None).destination_id().to_string(),
+            "UUID uuid1"
+        );
+        assert!(matches!(
+            backup("uuid1", Some("backup")).validate_destination_id(),
+            Err(BackupError::InvalidConfig(_))
+        ));
+        assert!(backup("", None).validate_destination_id().is_err());
+        assert!(backup("", Some("")).validate_destination_id().is_err());
+    }
+
+    #[test]
+    fn test_config_file_in() {
+        let dir = env::temp_dir().join(format!(
+            "dd_backup_test_config_file_in_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(Config::config_file_in(&dir), dir.join("config.json"));
+        fs::write(dir.join("config.toml"), "").unwrap();
+        assert_eq!(Config::config_file_in(&dir), dir.join("config.toml"));
+        fs::write(dir.join("config.yaml"), "").unwrap();
+        assert_eq!(Config::config_file_in(&dir), dir.join("config.yaml"));
+        fs::write(dir.join("config.json"), "").unwrap();
+        assert_eq!(Config::config_file_in(&dir), dir.join("config.json"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_name_template() {
+        let backup = |name_template: Option<&str>, include_time: Option<bool>| BackupConfig {
+            uuid: "uuid1".to_string(),
+            name_template: name_template.map(|name_template| name_template.to_string()),
+            include_time,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            backup(None, None).file_name_template(),
+            DEFAULT_NAME_TEMPLATE
+        );
+        assert_eq!(
+            backup(None, Some(true)).file_name_template(),
+            "{date}_{time}_{tag}_{name}_{model}_{serial}"
+        );
+        assert_eq!(
+            backup(Some("{serial}-{date}"), Some(true)).file_name_template(),
+            "{serial}-{date}_{time}"
+        );
+        assert_eq!(
+            backup(Some("{date}T{time}_{serial}"), Some(true)).file_name_template(),
+            "{date}T{time}_{serial}"
+        );
+    }
+
+    #[test]
+    fn test_block_size_in_bytes() {
+        let backup = |block_size: Option<&str>| BackupConfig {
+            uuid: "uuid1".to_string(),
+            block_size: block_size.map(|block_size| block_size.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            backup(None).block_size_in_bytes().unwrap(),
+            (4 * 1024 * 1024)
+        );
+        assert_eq!(
+            backup(Some("1MiB")).block_size_in_bytes().unwrap(),
+            (1024 * 1024)
+        );
+        assert_eq!(
+            backup(Some("1MB")).block_size_in_bytes().unwrap(),
+            (1000 * 1000)
+        );
+        assert!(backup(Some("0")).block_size_in_bytes().is_err());
+        assert!(backup(Some("4X")).block_size_in_bytes().is_err());
+        assert!(backup(Some("-4M")).block_size_in_bytes().is_err());
+    }
+
+    #[test]
+    fn test_space_headroom() {
+        let backup = |space_headroom: Option<&str>| BackupConfig {
+            uuid: "uuid1".to_string(),
+            space_headroom: space_headroom.map(|space_headroom| space_headroom.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            backup(None).space_headroom().unwrap(),
+            SpaceHeadroom::Bytes(0)
+        );
+        assert_eq!(
+            backup(Some("1G")).space_headroom().unwrap(),
+            SpaceHeadroom::Bytes(1024 * 1024 * 1024)
+        );
+        assert_eq!(
+            backup(Some("5%")).space_headroom().unwrap(),
+            SpaceHeadroom::Percent(5.0)
+        );
+        assert_eq!(SpaceHeadroom::Percent(5.0).in_bytes(2000), 100);
+        assert_eq!(SpaceHeadroom::Bytes(10).in_bytes(2000), 10);
+        assert!(backup(Some("100%")).space_headroom().is_err());
+        assert!(backup(Some("-1%")).space_headroom().is_err());
+        assert!(backup(Some("10X")).space_headroom().is_err());
+    }
+
+    #[test]
+    fn test_compression_from_file_path() {
+        assert_eq!(
+            Compression::from_file_path("/mnt/2023-06-15_serial.img.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_file_path("/mnt/2023-06-15_serial.img.gz"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_file_path("/mnt/2023-06-15_serial.img.xz"),
+            Some(Compression::Xz)
+        );
+        assert_eq!(
+            Compression::from_file_path("/mnt/2023-06-15_serial.img"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_migrated_config_file_path() {
+        let home_dir = env::temp_dir().join(format!(
+            "dd_backup_test_migrated_config_file_path_{}",
+            std::process::id()
+        ));
+        let config_file_path = home_dir.join(".config/dd_backup/config.json");
+        let legacy_config_file_path = home_dir.join(".dd-back-up/config.json");
+        fs::create_dir_all(config_file_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(legacy_config_file_path.parent().unwrap()).unwrap();
+        let migrated = || {
+            Config::migrated_config_file_path(
+                config_file_path.clone(),
+                legacy_config_file_path.clone(),
+            )
+        };
+
+        // neither exists, the error should name the current path
+        assert_eq!(migrated(), config_file_path);
+
+        fs::write(&legacy_config_file_path, "{}").unwrap();
+        assert_eq!(migrated(), legacy_config_file_path);
+
+        fs::write(&config_file_path, "{}").unwrap();
+        assert_eq!(migrated(), config_file_path);
+
+        fs::remove_dir_all(&home_dir).unwrap();
     }
 }
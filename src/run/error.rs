@@ -0,0 +1,347 @@
+use std::{io, path::PathBuf, process::ExitStatus, time::Duration};
+
+use thiserror::Error;
+
+use super::{backup_run::command_output::exit_status_description, utils::format_byte_size};
+
+/// The exit code of a failure without a kind of its own.
+pub const EXIT_FAILURE: i32 = 1;
+/// The exit code of an unreadable or invalid configuration.
+pub const EXIT_CONFIG: i32 = 2;
+/// The exit code of a configured device which isn't found.
+pub const EXIT_DEVICE_NOT_FOUND: i32 = 3;
+/// The exit code of a destination without enough space for a backup.
+pub const EXIT_INSUFFICIENT_SPACE: i32 = 4;
+/// The exit code of a failed device backup, like a failing `dd`, of a failed destination, like a failing mount,
+/// or of failed destinations with `--keep-going`.
+pub const EXIT_BACKUP_FAILED: i32 = 5;
+
+/// The errors of reading the configuration, resolving devices and running backups.
+///
+/// The messages are meant for the user, failures without a kind of their own are kept as `Other`.
+#[derive(Debug, Error)]
+pub enum BackupError {
+    /// The configuration file can't be read.
+    #[error("{source}: {}", .path.display())]
+    ConfigRead { path: PathBuf, source: io::Error },
+
+    /// The configuration file can't be parsed.
+    #[error("Cannot parse config file -> {0}")]
+    ConfigParse(String),
+
+    /// The configuration is parsed, but not valid.
+    #[error("{0}")]
+    InvalidConfig(String),
+
+    /// No available device has the configured serial number.
+    #[error("Device not found: {0}")]
+    DeviceNotFound(String),
+
+    /// Several available devices have the configured serial number.
+    #[error("Device has not a unique serial: {0}")]
+    NotUniqueSerial(String),
+
+    /// Several available devices have the configured WWN.
+    #[error("Device has not a unique WWN: {0}")]
+    NotUniqueWwn(String),
+
+    /// Several available partitions have the configured PARTUUID.
+    #[error("Partition has not a unique PARTUUID: {0}")]
+    NotUniquePartuuid(String),
+
+    /// Several available partitions have the configured label.
+    #[error("Partition has not a unique label: {0}")]
+    NotUniqueLabel(String),
+
+    /// Several available filesystems have the configured UUID.
+    #[error("Not a unique UUID: {uuid}, shared by {}", .device_paths.join(", "))]
+    NotUniqueUuid {
+        uuid: String,
+        device_paths: Vec<String>,
+    },
+
+    /// Several available filesystems have the configured label of a destination.
+    #[error("Not a unique label: {label}, shared by {}", .device_paths.join(", "))]
+    NotUniqueDestinationLabel {
+        label: String,
+        device_paths: Vec<String>,
+    },
+
+    /// The destination filesystem is too full for the backup of a device.
+    #[error(
+        "Not enough space on destination filesystem {filesystem} to backup device {device}: {} needed, {} available + {} freed by deleting old backups - {} reserved by running backups - {} headroom, {} short",
+        format_byte_size(*.needed),
+        format_byte_size(*.available),
+        format_byte_size(*.freed),
+        format_byte_size(*.reserved),
+        format_byte_size(*.headroom),
+        format_byte_size((.needed + .reserved + .headroom).saturating_sub(.available + .freed))
+    )]
+    InsufficientSpace {
+        filesystem: String,
+        device: String,
+        available: u64,
+        freed: u64,
+        reserved: u64,
+        headroom: u64,
+        needed: u64,
+    },
+
+    /// The backup of today is already present, so the device is skipped.
+    #[error(
+        "Backup file for today is already present {0}. Skipping it, pass --overwrite to replace it"
+    )]
+    BackupPresent(String),
+
+    /// The source device has the checksum of its most recent backup, so the device is skipped.
+    #[error("Source {device} unchanged since backup {backup}, skipping it")]
+    SourceUnchanged { device: String, backup: String },
+
+    /// A command exited unsuccessfully.
+    #[error("Error running {command} ({}): {stderr}", exit_status_description(.status))]
+    CommandFailed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+
+    /// A command was killed for exceeding its timeout.
+    #[error("Timed out after {timeout:?} running {command}")]
+    CommandTimedOut { command: String, timeout: Duration },
+
+    /// The size of a source device differs from its configured `expected_size`.
+    #[error(
+        "Device {device} has a size of {}, but {} is expected",
+        format_byte_size(*.size),
+        format_byte_size(*.expected)
+    )]
+    SizeMismatch {
+        device: String,
+        size: u64,
+        expected: u64,
+    },
+
+    /// Mounting is skipped for a destination filesystem which isn't mounted.
+    #[error("Filesystem {0} is not mounted, but skip_mount is set")]
+    NotMounted(String),
+
+    /// Mounting a destination filesystem failed.
+    #[error("Error mounting filesystem {device_path} on {mountpath}: {source}")]
+    MountFailed {
+        device_path: String,
+        mountpath: String,
+        source: Box<BackupError>,
+    },
+
+    /// Unmounting a destination filesystem failed.
+    #[error("Error unmounting filesystem {device_path} at {mountpath}: {source}")]
+    UnmountFailed {
+        device_path: String,
+        mountpath: String,
+        source: Box<BackupError>,
+    },
+
+    /// Another process holds the lock of a destination filesystem, which it backs up.
+    #[error("Backup already running for UUID {uuid}, locked with {lock_file_path}")]
+    AlreadyRunning {
+        uuid: String,
+        lock_file_path: String,
+    },
+
+    /// A file or process couldn't be accessed, `context` tells which.
+    #[error("{context}: {source}")]
+    Io { context: String, source: io::Error },
+
+    /// Any other failure, described by its message.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl BackupError {
+    /// Returns the exit code of the kind of the error, `EXIT_FAILURE` for errors without a kind of their own.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BackupError::ConfigRead { .. }
+            | BackupError::ConfigParse(_)
+            | BackupError::InvalidConfig(_) => EXIT_CONFIG,
+            BackupError::DeviceNotFound(_) => EXIT_DEVICE_NOT_FOUND,
+            BackupError::InsufficientSpace { .. } => EXIT_INSUFFICIENT_SPACE,
+            _ => EXIT_FAILURE,
+        }
+    }
+
+    /// Returns the exit code of a device backup failing with the error, `EXIT_BACKUP_FAILED`
+    /// for errors without a kind of their own.
+    pub fn backup_exit_code(&self) -> i32 {
+        match self.exit_code() {
+            EXIT_FAILURE => EXIT_BACKUP_FAILED,
+            exit_code => exit_code,
+        }
+    }
+
+    /// Returns a closure wrapping an `io::Error` into `BackupError::Io` with the `context`, for `map_err`.
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> BackupError {
+        let context = context.into();
+        move |source| BackupError::Io { context, source }
+    }
+}
+
+impl From<String> for BackupError {
+    fn from(message: String) -> Self {
+        BackupError::Other(message)
+    }
+}
+
+impl From<&str> for BackupError {
+    fn from(message: &str) -> Self {
+        BackupError::Other(message.to_string())
+    }
+}
+
+impl From<BackupError> for String {
+    fn from(error: BackupError) -> Self {
+        error.to_string()
+    }
+}
+
+/// The error ending a command, with the exit code the process exits with.
+#[derive(Debug, PartialEq, Error)]
+#[error("{message}")]
+pub struct Failure {
+    /// The message logged before exiting.
+    pub message: String,
+    /// The exit code, one of the `EXIT_*` constants.
+    pub exit_code: i32,
+}
+
+impl Failure {
+    /// Wraps the `error` which stopped the backups to a destination, like a failing mount,
+    /// with the exit code of `BackupError::backup_exit_code`.
+    pub fn backup(error: BackupError) -> Failure {
+        Failure {
+            exit_code: error.backup_exit_code(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Prefixes the message with `context`, keeping the exit code.
+    pub fn context(self, context: &str) -> Failure {
+        Failure {
+            message: format!("{}: {}", context, self.message),
+            exit_code: self.exit_code,
+        }
+    }
+}
+
+impl From<BackupError> for Failure {
+    fn from(error: BackupError) -> Self {
+        Failure {
+            exit_code: error.exit_code(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure {
+            message,
+            exit_code: EXIT_FAILURE,
+        }
+    }
+}
+
+impl From<&str> for Failure {
+    fn from(message: &str) -> Self {
+        Failure::from(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            BackupError::CommandFailed {
+                command: "umount /mnt".to_string(),
+                status: ExitStatus::from_raw(32 << 8),
+                stderr: "target is busy".to_string(),
+            }
+            .to_string(),
+            "Error running umount /mnt (exit code 32): target is busy"
+        );
+        assert_eq!(
+            BackupError::MountFailed {
+                device_path: "/dev/sdc1".to_string(),
+                mountpath: "/mnt".to_string(),
+                source: Box::new(BackupError::CommandTimedOut {
+                    command: "mount /dev/sdc1 /mnt".to_string(),
+                    timeout: Duration::from_secs(5),
+                }),
+            }
+            .to_string(),
+            "Error mounting filesystem /dev/sdc1 on /mnt: Timed out after 5s running mount /dev/sdc1 /mnt"
+        );
+        assert_eq!(
+            BackupError::InsufficientSpace {
+                filesystem: "/dev/sdc1".to_string(),
+                device: "/dev/sda".to_string(),
+                available: 1024,
+                freed: 512,
+                reserved: 0,
+                headroom: 256,
+                needed: 2048,
+            }
+            .to_string(),
+            "Not enough space on destination filesystem /dev/sdc1 to backup device /dev/sda: 2.0 KiB needed, 1.0 KiB available + 512 B freed by deleting old backups - 0 B reserved by running backups - 256 B headroom, 768 B short"
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let invalid_config = BackupError::InvalidConfig("copies must be positive".to_string());
+        assert_eq!(invalid_config.exit_code(), EXIT_CONFIG);
+        assert_eq!(invalid_config.backup_exit_code(), EXIT_CONFIG);
+        let device_not_found = BackupError::DeviceNotFound("SRC1".to_string());
+        assert_eq!(device_not_found.exit_code(), EXIT_DEVICE_NOT_FOUND);
+        let dd_failed = BackupError::CommandFailed {
+            command: "dd if=/dev/sda".to_string(),
+            status: ExitStatus::from_raw(1 << 8),
+            stderr: "Input/output error".to_string(),
+        };
+        assert_eq!(dd_failed.exit_code(), EXIT_FAILURE);
+        assert_eq!(dd_failed.backup_exit_code(), EXIT_BACKUP_FAILED);
+
+        let failure = Failure::from(device_not_found).context("Failed to restore image");
+        assert_eq!(failure.exit_code, EXIT_DEVICE_NOT_FOUND);
+        assert_eq!(
+            failure.to_string(),
+            "Failed to restore image: Device not found: SRC1"
+        );
+        assert_eq!(Failure::from("lsblk failed").exit_code, EXIT_FAILURE);
+
+        let mount_failed = BackupError::MountFailed {
+            device_path: "/dev/sdc1".to_string(),
+            mountpath: "/mnt".to_string(),
+            source: Box::new(BackupError::CommandFailed {
+                command: "mount /dev/sdc1 /mnt".to_string(),
+                status: ExitStatus::from_raw(32 << 8),
+                stderr: "wrong fs type".to_string(),
+            }),
+        };
+        assert_eq!(Failure::backup(mount_failed).exit_code, EXIT_BACKUP_FAILED);
+        let rsync_failed = BackupError::CommandFailed {
+            command: "rsync -a /tmp/dd_backup_source_sda1/ /mnt/2023-06-15_model_serial"
+                .to_string(),
+            status: ExitStatus::from_raw(23 << 8),
+            stderr: "some files could not be transferred".to_string(),
+        };
+        assert_eq!(Failure::backup(rsync_failed).exit_code, EXIT_BACKUP_FAILED);
+        let invalid_config = BackupError::InvalidConfig("block_size must be positive".to_string());
+        assert_eq!(Failure::backup(invalid_config).exit_code, EXIT_CONFIG);
+    }
+}
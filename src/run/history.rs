@@ -0,0 +1,91 @@
+use std::{fs::OpenOptions, io::Write};
+
+use serde::{Deserialize, Serialize};
+
+/// One line of the history file, describing the outcome of a single device backup.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// The version of `dd_backup` which wrote the record.
+    pub version: String,
+    /// The time the backup finished, in RFC 3339 format.
+    pub timestamp: String,
+    /// The serial number of the source device.
+    pub serial: Option<String>,
+    /// The model of the source device.
+    pub model: Option<String>,
+    /// The path of the source device.
+    pub device_path: String,
+    /// The UUID of the destination filesystem.
+    pub destination_uuid: Option<String>,
+    /// The path of the backup image.
+    pub image_path: String,
+    /// The size of the backup image in bytes, if it was written.
+    pub size: Option<u64>,
+    /// The outcome of the backup, `success`, `skipped` for an unchanged source or `failure`.
+    pub status: String,
+    /// The error message, if the backup failed, or the reason it was skipped.
+    pub error: Option<String>,
+}
+
+impl HistoryRecord {
+    /// Appends the record as a single JSON line to the history file at `path`.
+    ///
+    /// The line is written with one `write` call on a file opened in append mode,
+    /// so concurrent writers don't interleave within a line.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: If the record was appended.
+    /// - `Err(String)`: If the record couldn't be serialized or the file couldn't be written.
+    pub fn append(&self, path: &str) -> Result<(), String> {
+        let mut line = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize history record: {}", e))?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open history file {}: {}", path, e))?;
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to write history file {}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_append() {
+        let path =
+            std::env::temp_dir().join(format!("dd_backup_history_{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let record = HistoryRecord {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: "2023-06-15T00:00:00+00:00".to_string(),
+            serial: Some("serial".to_string()),
+            model: None,
+            device_path: "/dev/sda".to_string(),
+            destination_uuid: Some("uuid".to_string()),
+            image_path: "/mnt/2023-06-15_serial.img".to_string(),
+            size: None,
+            status: "failure".to_string(),
+            error: Some("error".to_string()),
+        };
+
+        record.append(path).unwrap();
+        record.append(path).unwrap();
+
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<HistoryRecord>(lines[1]).unwrap(),
+            record
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use chrono::NaiveDate;
+use clap::Args;
+
+use super::backup_run::{
+    backup::{backup_dir_path, file_name_pattern, DdSettings},
+    device::Device,
+    filesystem::Filesystem,
+    lsblk::Lsblk,
+};
+use super::config::{Config, DestinationId};
+use super::error::{BackupError, Failure};
+use super::prune::is_dated_within;
+use super::utils::{format_byte_size, parse_date};
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    #[clap(short, long)]
+    /// The path to the configuration file.
+    pub config_file_path: Option<String>,
+
+    #[clap(long, value_parser = parse_date)]
+    /// Only lists backups dated after this date (YYYY-MM-DD).
+    pub after: Option<NaiveDate>,
+
+    #[clap(long, value_parser = parse_date)]
+    /// Only lists backups dated before this date (YYYY-MM-DD).
+    pub before: Option<NaiveDate>,
+}
+
+/// Lists the configured backups with the destinations and devices they resolve to.
+///
+/// For each destination and mirror it prints whether its filesystem is present and where it is
+/// mounted, for each device the connected block devices it matches, their size and mount state,
+/// followed by their backups on the destinations which are mounted already, dated within the
+/// `--after` and `--before` window. Nothing is mounted or written.
+///
+/// # Returns
+///
+/// An `Ok` variant if the backups are listed, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(list_args: &ListArgs) -> Result<(), Failure> {
+    let config = Config::new(&list_args.config_file_path)?;
+    let lsblk = Lsblk::new(&config)?;
+
+    for backup_config in &config.backups {
+        let dd_settings = DdSettings::new(backup_config)?;
+        let mut mounted_filesystems = Vec::new();
+        for destination in backup_config.all_destination_ids() {
+            let filesystem = Filesystem::new(
+                &destination,
+                backup_config,
+                &lsblk.available_filesystems,
+                backup_config.mountpath.clone().or(config.mountpath.clone()),
+            )?;
+            println!(
+                "{}",
+                destination_line(
+                    backup_config.destination_kind(&destination),
+                    &destination,
+                    backup_config.is_enabled(),
+                    filesystem.as_ref().map(|filesystem| {
+                        (
+                            filesystem.device_path.as_str(),
+                            filesystem.blockdevice.mountpoint.as_deref(),
+                        )
+                    }),
+                )
+            );
+            if let Some(filesystem) = filesystem.filter(|filesystem| filesystem.is_mounted()) {
+                mounted_filesystems.push((destination, filesystem));
+            }
+        }
+
+        for backup_device in &backup_config.backup_devices {
+            let mut resolved = Vec::new();
+            let mut backup_paths = Vec::new();
+            for blockdevice in Device::resolve_blockdevices(backup_device, &lsblk.available_devices)
+            {
+                let device_path = format!("/dev/{}", blockdevice.name);
+                let mounted = Device::is_device_mounted(&device_path)?;
+                resolved.push((device_path, blockdevice.size, mounted));
+                for (_, filesystem) in mounted_filesystems.iter().filter(|(destination, _)| {
+                    backup_device.is_backed_up_to(destination, backup_config)
+                }) {
+                    let device = Device::from_blockdevice(
+                        backup_device,
+                        blockdevice,
+                        &lsblk.available_filesystems,
+                        backup_config
+                            .destination_path
+                            .clone()
+                            .unwrap_or("/.".to_string()),
+                    );
+                    backup_paths.extend(backup_paths_within(
+                        filesystem,
+                        &dd_settings,
+                        &device,
+                        list_args,
+                    )?);
+                }
+            }
+            println!(
+                "{}",
+                device_line(
+                    &match &backup_device.destination_uuid {
+                        Some(destination_uuid) => {
+                            format!("{} (to {})", backup_device.identifier(), destination_uuid)
+                        }
+                        None => backup_device.identifier(),
+                    },
+                    backup_device.is_enabled(),
+                    &resolved
+                )
+            );
+            for backup_path in backup_paths {
+                println!("    {}", backup_path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the paths of the backups of `device` on the mounted `filesystem`, oldest first,
+/// dated within the `--after` and `--before` window of the `list_args`.
+fn backup_paths_within(
+    filesystem: &Filesystem,
+    dd_settings: &DdSettings,
+    device: &Device,
+    list_args: &ListArgs,
+) -> Result<Vec<String>, BackupError> {
+    let backup_dir_path = backup_dir_path(filesystem, device);
+    if !Path::new(&backup_dir_path).is_dir() {
+        return Ok(Vec::new());
+    }
+    let file_name_pattern = file_name_pattern(dd_settings, device, None);
+    Ok(filesystem
+        .present_backups_oldest_first(&file_name_pattern, &backup_dir_path, device.method)?
+        .into_iter()
+        .filter(|backup| {
+            is_dated_within(
+                backup,
+                &file_name_pattern,
+                list_args.after,
+                list_args.before,
+            )
+        })
+        .map(|backup| format!("{}/{}", backup_dir_path, backup))
+        .collect())
+}
+
+/// Formats the state of a destination filesystem, `present` holds its device path and mountpoint.
+fn destination_line(
+    kind: &str,
+    destination: &DestinationId,
+    enabled: bool,
+    present: Option<(&str, Option<&str>)>,
+) -> String {
+    let state = match present {
+        Some((device_path, Some(mountpoint))) => {
+            format!("present at {}, mounted at {}", device_path, mountpoint)
+        }
+        Some((device_path, None)) => format!("present at {}, not mounted", device_path),
+        None => "not present".to_string(),
+    };
+    format!(
+        "{} {}{}: {}",
+        kind,
+        destination,
+        disabled_note(enabled),
+        state
+    )
+}
+
+/// Formats the state of a configured device, `resolved` holds the path, size and mount state
+/// of each connected block device it matches.
+fn device_line(identifier: &str, enabled: bool, resolved: &[(String, u64, bool)]) -> String {
+    let state = match resolved.is_empty() {
+        true => "not connected".to_string(),
+        false => resolved
+            .iter()
+            .map(|(device_path, size, mounted)| {
+                format!(
+                    "{} ({}), {}",
+                    device_path,
+                    format_byte_size(*size),
+                    if *mounted { "mounted" } else { "not mounted" }
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("; "),
+    };
+    format!(
+        "  Device {}{}: {}",
+        identifier,
+        disabled_note(enabled),
+        state
+    )
+}
+
+/// Returns the note appended to disabled destinations and devices.
+fn disabled_note(enabled: bool) -> &'static str {
+    match enabled {
+        true => "",
+        false => " (disabled)",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination_line() {
+        let uuid = |uuid: &str| DestinationId::Uuid(uuid.to_string());
+        assert_eq!(
+            destination_line(
+                "Destination",
+                &uuid("uuid1"),
+                true,
+                Some(("/dev/sdc1", Some("/mnt")))
+            ),
+            "Destination UUID uuid1: present at /dev/sdc1, mounted at /mnt"
+        );
+        assert_eq!(
+            destination_line("Mirror", &uuid("uuid2"), true, Some(("/dev/sdd1", None))),
+            "Mirror UUID uuid2: present at /dev/sdd1, not mounted"
+        );
+        assert_eq!(
+            destination_line("Destination", &uuid("uuid3"), false, None),
+            "Destination UUID uuid3 (disabled): not present"
+        );
+        assert_eq!(
+            destination_line(
+                "Destination",
+                &DestinationId::Label("backup".to_string()),
+                true,
+                None
+            ),
+            "Destination label backup: not present"
+        );
+    }
+
+    #[test]
+    fn test_device_line() {
+        assert_eq!(
+            device_line("serial1", true, &[]),
+            "  Device serial1: not connected"
+        );
+        assert_eq!(
+            device_line(
+                "serial1",
+                false,
+                &[("/dev/sda".to_string(), 2 * 1024 * 1024 * 1024, false)]
+            ),
+            "  Device serial1 (disabled): /dev/sda (2.0 GiB), not mounted"
+        );
+        assert_eq!(
+            device_line(
+                "match (model: Disk, serial_prefix: *)",
+                true,
+                &[
+                    ("/dev/sda".to_string(), 1024, true),
+                    ("/dev/sdb".to_string(), 512, false)
+                ]
+            ),
+            "  Device match (model: Disk, serial_prefix: *): /dev/sda (1.0 KiB), mounted; /dev/sdb (512 B), not mounted"
+        );
+    }
+}
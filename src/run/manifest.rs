@@ -0,0 +1,164 @@
+use std::{fs, os::unix::fs::MetadataExt};
+
+use serde::{Deserialize, Serialize};
+
+use super::utils::format_byte_size;
+
+/// The extension of the file next to an image, holding its manifest.
+pub const MANIFEST_FILE_EXTENSION: &str = ".json";
+
+/// The metadata of a backup image, written as JSON next to it, so it can be identified later.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of `dd_backup` which wrote the image.
+    pub version: String,
+    /// The serial number of the source device.
+    pub serial: Option<String>,
+    /// The model of the source device.
+    pub model: Option<String>,
+    /// The size of the source device in bytes, which is the size of the restored image.
+    pub total_size: u64,
+    /// The block size `dd` was run with, in bytes.
+    pub block_size: u64,
+    /// The `dd` command, or compression pipeline, which wrote the image.
+    pub command: String,
+    /// The time `dd` started, in RFC 3339 format.
+    pub started_at: String,
+    /// The time `dd` finished, in RFC 3339 format.
+    pub finished_at: String,
+    /// The name of the host which ran the backup.
+    pub hostname: Option<String>,
+    /// The `--tag` of the run which wrote the image, if it was tagged.
+    pub tag: Option<String>,
+    /// The owner the image was chowned to, the user running the backup.
+    pub owner: Option<Owner>,
+    /// The owner and permission bits of the source device node, re-applied by `restore --preserve-owner`.
+    pub source_owner: Option<Owner>,
+    /// The SHA-256 checksum of the image content, if it was verified.
+    pub checksum: Option<String>,
+}
+
+/// The owner of a file or device node, with its permission bits if they are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Owner {
+    /// The user ID.
+    pub uid: u32,
+    /// The group ID.
+    pub gid: u32,
+    /// The permission bits, like `0o660`, stored as a decimal number.
+    pub mode: Option<u32>,
+}
+
+impl Owner {
+    /// Returns the owner and permission bits of the file or device node at `path`, if it is readable.
+    pub fn of_path(path: &str) -> Option<Owner> {
+        fs::metadata(path).ok().map(|metadata| Owner {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: Some(metadata.mode() & 0o7777),
+        })
+    }
+}
+
+impl Manifest {
+    /// Writes the manifest as pretty-printed JSON next to the image at `image_path`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(String)`: The path of the written manifest.
+    /// - `Err(String)`: If the manifest couldn't be serialized or written.
+    pub fn write(&self, image_path: &str) -> Result<String, String> {
+        let manifest_file_path = manifest_file_path(image_path);
+        let mut content = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        content.push('\n');
+        fs::write(&manifest_file_path, content).map_err(|e| {
+            format!(
+                "Failed to write manifest file {}: {}",
+                manifest_file_path, e
+            )
+        })?;
+        Ok(manifest_file_path)
+    }
+
+    /// Reads the manifest of the image at `image_path`, `None` if it has none or it isn't readable.
+    pub fn read(image_path: &str) -> Option<Manifest> {
+        let manifest_file_path = manifest_file_path(image_path);
+        let content = fs::read_to_string(&manifest_file_path).ok()?;
+        serde_json::from_str(&content)
+            .map_err(|e| warn!("Ignoring invalid manifest {}: {}", manifest_file_path, e))
+            .ok()
+    }
+
+    /// Describes the source device of the image, like `Samsung_SSD S1 (1.8 TiB)`.
+    pub fn source(&self) -> String {
+        format!(
+            "{} {} ({})",
+            self.model.as_deref().unwrap_or("-"),
+            self.serial.as_deref().unwrap_or("-"),
+            format_byte_size(self.total_size)
+        )
+    }
+}
+
+/// Returns the path of the manifest of the image at `image_path`.
+pub fn manifest_file_path(image_path: &str) -> String {
+    format!("{}{}", image_path, MANIFEST_FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let image_path = std::env::temp_dir().join(format!(
+            "dd_backup_manifest_{}_Disk_SRC1.img",
+            std::process::id()
+        ));
+        let image_path = image_path.to_str().unwrap();
+        let manifest = Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            serial: Some("SRC1".to_string()),
+            model: Some("Disk".to_string()),
+            total_size: 2 * 1024 * 1024,
+            block_size: 4 * 1024 * 1024,
+            command: format!("dd if=/dev/sda of={}.partial bs=4194304", image_path),
+            started_at: "2026-10-15T03:00:00+00:00".to_string(),
+            finished_at: "2026-10-15T03:10:00+00:00".to_string(),
+            hostname: Some("host".to_string()),
+            tag: Some("before-upgrade".to_string()),
+            owner: Some(Owner {
+                uid: 1000,
+                gid: 1000,
+                mode: None,
+            }),
+            source_owner: Some(Owner {
+                uid: 0,
+                gid: 6,
+                mode: Some(0o660),
+            }),
+            checksum: None,
+        };
+
+        assert!(Manifest::read(image_path).is_none());
+        assert_eq!(
+            manifest.write(image_path).unwrap(),
+            format!("{}.json", image_path)
+        );
+        let read_manifest = Manifest::read(image_path);
+        fs::remove_file(manifest_file_path(image_path)).unwrap();
+        assert_eq!(read_manifest, Some(manifest));
+        let read_manifest = read_manifest.unwrap();
+        assert_eq!(read_manifest.source(), "Disk SRC1 (2.0 MiB)");
+
+        // manifests written before the tag and owners were recorded have none
+        let mut json = serde_json::to_value(&read_manifest).unwrap();
+        for field in ["tag", "owner", "source_owner"] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+        let untagged_manifest: Manifest = serde_json::from_value(json).unwrap();
+        assert_eq!(untagged_manifest.tag, None);
+        assert_eq!(untagged_manifest.source_owner, None);
+    }
+}
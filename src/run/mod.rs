@@ -1,39 +1,124 @@
 pub mod backup_run;
+mod catalog;
 mod config;
+mod error;
+mod history;
+mod list;
+mod manifest;
+mod prune;
+mod restore;
+mod status;
+mod systemd;
 pub mod utils;
+mod validate_config;
 
-use clap::{Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
+
+use crate::logger::LogFormat;
+
+pub use self::error::Failure;
 
 use self::backup_run::{run as backup_run, BackupArgs};
+use self::catalog::{run as catalog_run, CatalogArgs};
+use self::list::{run as list_run, ListArgs};
+use self::prune::{run as prune_run, PruneArgs};
+use self::restore::{run as restore_run, RestoreArgs};
+use self::status::{run as status_run, StatusArgs};
+use self::systemd::{run as install_systemd_run, InstallSystemdArgs};
+use self::validate_config::{run as validate_config_run, ValidateConfigArgs};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
-struct Cli {
+pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(long, global = true)]
+    /// Disables colored log output, also disabled by the `NO_COLOR` env var or if stderr isn't a terminal.
+    pub no_color: bool,
+
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    /// The format of the log output, `json` writes one JSON object per line.
+    pub log_format: LogFormat,
+
+    #[arg(long, global = true)]
+    /// A file the log output is appended to without colors, in addition to stderr.
+    pub log_file: Option<String>,
+
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    /// Logs more, `-v` at debug and `-vv` at trace level, the `RUST_LOG` env var takes precedence.
+    pub verbose: u8,
+
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    /// Logs less, `-q` only warnings and errors, `-qq` only errors, the `RUST_LOG` env var takes precedence.
+    pub quiet: u8,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Perform the backups
-    Run(BackupArgs),
+    Run(Box<BackupArgs>),
+    /// List the configured backups and the devices they resolve to, without running them
+    List(ListArgs),
+    /// Report the free space of each destination, its present backups and whether the next run fits
+    Status(StatusArgs),
+    /// Delete the backups beyond the configured copies and maximum age, without backing up
+    Prune(PruneArgs),
+    /// Inspect the catalog of completed backups
+    Catalog(CatalogArgs),
+    /// Write a backup image back onto a device
+    Restore(RestoreArgs),
+    /// Write a systemd service and timer running the backups on a schedule
+    InstallSystemd(InstallSystemdArgs),
+    /// Check the configuration for errors and likely mistakes, without backing up
+    ValidateConfig(ValidateConfigArgs),
+}
+
+/// Parses the command line arguments.
+pub fn parse_cli() -> Cli {
+    Cli::parse()
 }
 
 /// Runs the backup process.
 ///
-/// This function is responsible for parsing the command line arguments and executing the backup process.
+/// This function is responsible for executing the command given by the parsed command line arguments.
 ///
 /// # Errors
 ///
-/// Returns an error if the backup process fails to run.
-pub fn run() -> Result<(), String> {
-    let cli = Cli::parse();
-
+/// Returns an error if the backup process fails to run, or right away on other operating systems than Linux,
+/// since the devices are found with `lsblk` and `/proc/mounts`. The error carries the exit code of its kind.
+pub fn run(cli: &Cli) -> Result<(), Failure> {
+    if !cfg!(target_os = "linux") {
+        return Err(format!(
+            "dd-back-up currently requires Linux, not {}",
+            std::env::consts::OS
+        )
+        .into());
+    }
     trace!("CLI command matching {:?}", &cli.command);
     match &cli.command {
         Commands::Run(backup_args) => {
-            backup_run(backup_args).map_err(|e| format!("Failed to run backups: {}", e))
+            backup_run(backup_args).map_err(|e| e.context("Failed to run backups"))
+        }
+        Commands::List(list_args) => {
+            list_run(list_args).map_err(|e| e.context("Failed to list backups"))
+        }
+        Commands::Status(status_args) => {
+            status_run(status_args).map_err(|e| e.context("Failed to report status"))
+        }
+        Commands::Prune(prune_args) => {
+            prune_run(prune_args).map_err(|e| e.context("Failed to prune backups"))
+        }
+        Commands::Catalog(catalog_args) => {
+            catalog_run(catalog_args).map_err(|e| e.context("Failed to run catalog command"))
+        }
+        Commands::Restore(restore_args) => {
+            restore_run(restore_args).map_err(|e| e.context("Failed to restore image"))
         }
+        Commands::InstallSystemd(install_systemd_args) => install_systemd_run(install_systemd_args)
+            .map_err(|e| e.context("Failed to install systemd units")),
+        Commands::ValidateConfig(validate_config_args) => validate_config_run(validate_config_args)
+            .map_err(|e| e.context("Failed to validate config")),
     }
 }
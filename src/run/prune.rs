@@ -0,0 +1,309 @@
+use std::{path::Path, time::Duration};
+
+use chrono::NaiveDate;
+use clap::Args;
+
+use super::backup_run::{
+    backup::{backup_dir_path, file_name_pattern, DdSettings},
+    device::Device,
+    filesystem::{backup_date, Filesystem},
+    lock::DestinationLock,
+    lsblk::Lsblk,
+};
+use super::config::{BackupConfig, Config, DestinationId};
+use super::error::{BackupError, Failure};
+use super::utils::{is_within_dates, parse_date};
+
+#[derive(Args, Debug)]
+pub struct PruneArgs {
+    #[clap(short, long)]
+    /// The path to the configuration file.
+    pub config_file_path: Option<String>,
+
+    #[clap(short = 'n', long)]
+    /// Lists the backups which would be deleted, without deleting them.
+    pub dry_run: bool,
+
+    #[clap(long, value_parser = parse_date)]
+    /// Only deletes backups dated after this date (YYYY-MM-DD).
+    pub after: Option<NaiveDate>,
+
+    #[clap(long, value_parser = parse_date)]
+    /// Only deletes backups dated before this date (YYYY-MM-DD).
+    pub before: Option<NaiveDate>,
+}
+
+/// Applies the retention of `copies` and `max_age_days` to the present backups, without backing up.
+///
+/// For each destination and mirror, which is mounted for it if it isn't mounted and unmounted afterward,
+/// the expired backups of each configured device are deleted, then the oldest ones until at most `copies`
+/// are left. Each deletion is printed. Only backups of connected devices can be matched, since their names
+/// contain the model and serial number. Tagged backups form series of their own and are kept.
+/// Like a backup run, a prune locks each destination, so it fails if a backup to it is running.
+/// With `--after` and `--before` only the backups the retention selects dated within the window are deleted.
+///
+/// # Returns
+///
+/// An `Ok` variant if the backups are pruned, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(prune_args: &PruneArgs) -> Result<(), Failure> {
+    let config = Config::new(&prune_args.config_file_path)?;
+    let lsblk = Lsblk::new(&config)?;
+
+    let mut pruned = 0;
+    for backup_config in config
+        .backups
+        .iter()
+        .filter(|backup_config| backup_config.is_enabled())
+    {
+        let dd_settings = DdSettings::new(backup_config)?;
+        for destination in backup_config.all_destination_ids() {
+            let filesystem = Filesystem::new(
+                &destination,
+                backup_config,
+                &lsblk.available_filesystems,
+                backup_config.mountpath.clone().or(config.mountpath.clone()),
+            )?;
+            let Some(mut filesystem) = filesystem else {
+                warn!("Filesystem with {} not connected, skipping it", destination);
+                continue;
+            };
+            filesystem.allowed_mountpaths = config.allowed_mountpaths.clone();
+            // locked by the UUID of the filesystem, like `run` does, also if it is configured by its label
+            let _lock = DestinationLock::acquire(
+                filesystem.blockdevice.uuid.as_deref().unwrap_or_default(),
+            )?;
+
+            let mounted_temporarily = !filesystem.is_mounted();
+            if mounted_temporarily {
+                if backup_config.skip_mount == Some(true) {
+                    warn!(
+                        "{}, skipping it",
+                        BackupError::NotMounted(filesystem.device_path.clone())
+                    );
+                    continue;
+                }
+                filesystem.mount()?;
+            }
+            let result = prune_destination(
+                &filesystem,
+                &dd_settings,
+                &destination,
+                backup_config,
+                &lsblk,
+                prune_args,
+            );
+            let unmount_result = match mounted_temporarily {
+                true => filesystem.unmount(),
+                false => Ok(()),
+            };
+            pruned += result?;
+            unmount_result?;
+        }
+    }
+
+    match prune_args.dry_run {
+        true => println!("[DRY RUN] Would delete {} backups", pruned),
+        false => println!("Deleted {} backups", pruned),
+    }
+    Ok(())
+}
+
+/// Prunes the backups of the enabled devices of `backup_config` backed up to the mounted `filesystem`,
+/// the `destination`.
+///
+/// # Returns
+///
+/// - `Ok(usize)`: The number of deleted backups, or of the ones which would be deleted on a dry run.
+/// - `Err(BackupError)`: If the backups couldn't be read or deleted.
+fn prune_destination(
+    filesystem: &Filesystem,
+    dd_settings: &DdSettings,
+    destination: &DestinationId,
+    backup_config: &BackupConfig,
+    lsblk: &Lsblk,
+    prune_args: &PruneArgs,
+) -> Result<usize, BackupError> {
+    let mut pruned = 0;
+    for backup_device in backup_config
+        .backup_devices
+        .iter()
+        .filter(|backup_device| backup_device.is_enabled())
+        .filter(|backup_device| backup_device.is_backed_up_to(destination, backup_config))
+        .filter(|backup_device| {
+            backup_device.copies.is_some() || backup_device.max_age_days.is_some()
+        })
+    {
+        // devices which aren't connected are warned about while resolving them
+        for blockdevice in Device::resolve_blockdevices(backup_device, &lsblk.available_devices) {
+            let device = Device::from_blockdevice(
+                backup_device,
+                blockdevice,
+                &lsblk.available_filesystems,
+                backup_config
+                    .destination_path
+                    .clone()
+                    .unwrap_or("/.".to_string()),
+            );
+            pruned += prune_device(filesystem, dd_settings, &device, prune_args)?;
+        }
+    }
+    Ok(pruned)
+}
+
+/// Deletes the expired backups of `device`, then the oldest ones until at most `copies` are present,
+/// of those only the ones dated within the `--after` and `--before` window.
+fn prune_device(
+    filesystem: &Filesystem,
+    dd_settings: &DdSettings,
+    device: &Device,
+    prune_args: &PruneArgs,
+) -> Result<usize, BackupError> {
+    let backup_dir_path = backup_dir_path(filesystem, device);
+    if !Path::new(&backup_dir_path).is_dir() {
+        return Ok(0);
+    }
+    let file_name_pattern = file_name_pattern(dd_settings, device, None);
+    let expired_backups = match device.max_age_days {
+        Some(max_age_days) => filesystem.expired_backups(
+            &file_name_pattern,
+            &backup_dir_path,
+            device.method,
+            Duration::from_secs(max_age_days * 24 * 60 * 60),
+        )?,
+        None => Vec::new(),
+    };
+    let present_backups = filesystem.present_backups_oldest_first(
+        &file_name_pattern,
+        &backup_dir_path,
+        device.method,
+    )?;
+    let prunable_backups: Vec<String> =
+        backups_to_prune(&present_backups, &expired_backups, device.copies)
+            .into_iter()
+            .filter(|prunable_backup| {
+                is_dated_within(
+                    prunable_backup,
+                    &file_name_pattern,
+                    prune_args.after,
+                    prune_args.before,
+                )
+            })
+            .collect();
+
+    for prunable_backup in &prunable_backups {
+        let reason = match expired_backups.contains(prunable_backup) {
+            true => format!(
+                "older than {} days",
+                device.max_age_days.unwrap_or_default()
+            ),
+            false => format!("more than {} copies", device.copies.unwrap_or_default()),
+        };
+        match prune_args.dry_run {
+            true => println!(
+                "[DRY RUN] Would delete {}/{}, {}",
+                backup_dir_path, prunable_backup, reason
+            ),
+            false => {
+                filesystem.delete_backup(&backup_dir_path, prunable_backup, device.method)?;
+                println!(
+                    "Deleted {}/{}, {}",
+                    backup_dir_path, prunable_backup, reason
+                );
+            }
+        }
+    }
+    Ok(prunable_backups.len())
+}
+
+/// Returns whether the backup `file_name` of the series with `file_name_pattern` is dated
+/// strictly after `after` and before `before`. Without both, every backup is.
+pub(crate) fn is_dated_within(
+    file_name: &str,
+    file_name_pattern: &str,
+    after: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+) -> bool {
+    match (after, before) {
+        (None, None) => true,
+        _ => backup_date(file_name, file_name_pattern)
+            .is_some_and(|date| is_within_dates(date, after, before)),
+    }
+}
+
+/// Returns the backups a prune deletes, the `expired_backups` followed by the oldest of the remaining
+/// `present_backups`, sorted oldest first, beyond the number of `copies`.
+fn backups_to_prune(
+    present_backups: &[String],
+    expired_backups: &[String],
+    copies: Option<usize>,
+) -> Vec<String> {
+    let remaining_backups: Vec<&String> = present_backups
+        .iter()
+        .filter(|present_backup| !expired_backups.contains(present_backup))
+        .collect();
+    let excess = copies
+        .map(|copies| remaining_backups.len().saturating_sub(copies))
+        .unwrap_or(0);
+    expired_backups
+        .iter()
+        .chain(remaining_backups.into_iter().take(excess))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_to_prune() {
+        let present_backups = ["2026-10-01.img", "2026-10-08.img", "2026-10-15.img"]
+            .map(String::from)
+            .to_vec();
+        let expired_backups = vec!["2026-10-01.img".to_string()];
+
+        assert_eq!(
+            backups_to_prune(&present_backups, &[], Some(1)),
+            vec!["2026-10-01.img", "2026-10-08.img"]
+        );
+        assert_eq!(
+            backups_to_prune(&present_backups, &expired_backups, Some(1)),
+            vec!["2026-10-01.img", "2026-10-08.img"]
+        );
+        assert_eq!(
+            backups_to_prune(&present_backups, &expired_backups, None),
+            vec!["2026-10-01.img"]
+        );
+        assert!(backups_to_prune(&present_backups, &[], Some(3)).is_empty());
+    }
+
+    #[test]
+    fn test_is_dated_within() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 10, day);
+        let pattern = "{date}_Disk_SRC1.img";
+        assert!(is_dated_within(
+            "2026-10-08_Disk_SRC1.img",
+            pattern,
+            None,
+            None
+        ));
+        assert!(is_dated_within(
+            "2026-10-08_Disk_SRC1.img",
+            pattern,
+            date(1),
+            date(15)
+        ));
+        assert!(!is_dated_within(
+            "2026-10-15_Disk_SRC1.img",
+            pattern,
+            date(1),
+            date(15)
+        ));
+        assert!(!is_dated_within(
+            "2026-10-01_Disk_SRC1.img",
+            pattern,
+            date(1),
+            None
+        ));
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+
+use clap::Args;
+
+use super::backup_run::{command_output::command_output, device::Device, lsblk::Lsblk};
+use super::config::{Compression, Config};
+use super::error::Failure;
+use super::manifest::{Manifest, Owner};
+use super::utils::{find_executable, format_byte_size, shell_quote};
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    #[clap(long)]
+    /// The path of the backup image to restore, compressed images are detected by their extension.
+    pub image: String,
+
+    #[clap(long)]
+    /// The serial number, or WWN, of the device to overwrite with the image.
+    pub destination_serial: String,
+
+    #[clap(long)]
+    /// Flag to confirm overwriting all data on the destination device.
+    pub confirm: bool,
+
+    #[clap(short, long)]
+    /// The path to the configuration file, used to read the `lsblk` settings.
+    pub config_file_path: Option<String>,
+
+    #[clap(long)]
+    /// Re-applies the owner, group and permissions of the source device recorded in the manifest to the device.
+    pub preserve_owner: bool,
+}
+
+/// Restores a backup image onto the device with the given serial number, using `dd`.
+///
+/// The device is refused if it, or one of its partitions, is mounted, or if it is smaller than the image.
+/// The size of a compressed image's content is read from its manifest, if it has one.
+/// Since all data on the device is overwritten, `--confirm` is required.
+/// With `--preserve-owner` the device node gets the owner, group and permissions of the source device
+/// recorded in the manifest afterward, instead of keeping the ones of the restore target.
+///
+/// # Returns
+///
+/// An `Ok` variant if the image is restored successfully, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(restore_args: &RestoreArgs) -> Result<(), Failure> {
+    let config = match &restore_args.config_file_path {
+        Some(_) => Config::new(&restore_args.config_file_path)?,
+        None => Config::default(),
+    };
+
+    let image_size = fs::metadata(&restore_args.image)
+        .map_err(|e| format!("Failed to read image {}: {}", restore_args.image, e))?
+        .len();
+    let compression = Compression::from_file_path(&restore_args.image);
+    let manifest = Manifest::read(&restore_args.image);
+    if let Some(manifest) = &manifest {
+        info!(
+            "Image {} is a backup of {}, written by {} at {}",
+            restore_args.image,
+            manifest.source(),
+            manifest.hostname.as_deref().unwrap_or("-"),
+            manifest.finished_at
+        );
+    }
+    if let Some(compression) = compression {
+        find_executable(compression.binary()).ok_or(format!(
+            "Decompressor {} not found in PATH, it is needed to restore {}",
+            compression.binary(),
+            restore_args.image
+        ))?;
+    }
+
+    let lsblk = Lsblk::new(&config)?;
+    let blockdevice =
+        Device::validate_serial(&restore_args.destination_serial, &lsblk.available_devices)?;
+    let device_path = format!("/dev/{}", blockdevice.name);
+
+    if Device::is_device_mounted(&device_path)? {
+        return Err(format!(
+            "Device {} is mounted, unmount it and its partitions before restoring",
+            device_path
+        )
+        .into());
+    }
+    // without a manifest, the size of a compressed image's content is unknown without decompressing it
+    let content_size = match (&manifest, compression) {
+        (Some(manifest), _) => Some(manifest.total_size),
+        (None, None) => Some(image_size),
+        (None, Some(_)) => None,
+    };
+    if let Some(content_size) = content_size.filter(|&size| size > blockdevice.size) {
+        return Err(format!(
+            "Image {} ({}) is larger than device {} ({})",
+            restore_args.image,
+            format_byte_size(content_size),
+            device_path,
+            format_byte_size(blockdevice.size)
+        )
+        .into());
+    }
+
+    let command_parts = restore_command(&restore_args.image, &device_path, compression);
+    let owner_commands = match restore_args.preserve_owner {
+        true => {
+            let source_owner = manifest
+                .as_ref()
+                .and_then(|manifest| manifest.source_owner)
+                .ok_or(format!(
+                    "Image {} has no manifest recording the owner of its source device, which --preserve-owner needs",
+                    restore_args.image
+                ))?;
+            owner_commands(&device_path, source_owner)
+        }
+        false => Vec::new(),
+    };
+    if !restore_args.confirm {
+        return Err(format!(
+            "Restoring overwrites all data on device {} (model: {}, serial: {}), add `--confirm` to run: {}",
+            device_path,
+            blockdevice.model.as_deref().unwrap_or("-"),
+            restore_args.destination_serial,
+            command_parts.join(" ")
+        )
+        .into());
+    }
+
+    info!(
+        "Restoring image {} onto device {}",
+        restore_args.image, device_path
+    );
+    command_output(
+        command_parts.iter().map(|part| part.as_str()).collect(),
+        &format!("restore image onto {}", device_path),
+        Some(true),
+    )?;
+    for owner_command in &owner_commands {
+        command_output(
+            owner_command.iter().map(|part| part.as_str()).collect(),
+            &format!("restore the owner of {}", device_path),
+            Some(true),
+        )?;
+    }
+    info!(
+        "Restored image {} onto device {}",
+        restore_args.image, device_path
+    );
+    Ok(())
+}
+
+/// Returns the commands applying the `owner`, and its permission bits if recorded, to `device_path`.
+fn owner_commands(device_path: &str, owner: Owner) -> Vec<Vec<String>> {
+    let mut owner_commands = vec![vec![
+        "chown".to_string(),
+        format!("{}:{}", owner.uid, owner.gid),
+        device_path.to_string(),
+    ]];
+    if let Some(mode) = owner.mode {
+        owner_commands.push(vec![
+            "chmod".to_string(),
+            format!("{:o}", mode),
+            device_path.to_string(),
+        ]);
+    }
+    owner_commands
+}
+
+/// Returns the command writing the `image` onto the device at `device_path`.
+///
+/// A compressed image is piped through its decompressor into `dd`, run by `bash` with `pipefail`.
+fn restore_command(
+    image: &str,
+    device_path: &str,
+    compression: Option<Compression>,
+) -> Vec<String> {
+    match compression {
+        Some(compression) => vec![
+            "bash".to_string(),
+            "-o".to_string(),
+            "pipefail".to_string(),
+            "-c".to_string(),
+            format!(
+                "{} -dc {} | dd of={} status=progress",
+                compression.binary(),
+                shell_quote(image),
+                shell_quote(device_path)
+            ),
+        ],
+        None => vec![
+            "dd".to_string(),
+            format!("if={}", image),
+            format!("of={}", device_path),
+            "status=progress".to_string(),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_command() {
+        assert_eq!(
+            restore_command("/mnt/2023-06-15_serial.img", "/dev/sdb", None),
+            vec![
+                "dd",
+                "if=/mnt/2023-06-15_serial.img",
+                "of=/dev/sdb",
+                "status=progress"
+            ]
+        );
+        assert_eq!(
+            restore_command("/mnt/My Disk.img.zst", "/dev/sdb", Some(Compression::Zstd)),
+            vec![
+                "bash",
+                "-o",
+                "pipefail",
+                "-c",
+                "zstd -dc '/mnt/My Disk.img.zst' | dd of=/dev/sdb status=progress"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_owner_commands() {
+        let owner = Owner {
+            uid: 0,
+            gid: 6,
+            mode: Some(0o660),
+        };
+        assert_eq!(
+            owner_commands("/dev/sdb", owner),
+            vec![
+                vec!["chown", "0:6", "/dev/sdb"],
+                vec!["chmod", "660", "/dev/sdb"]
+            ]
+        );
+        assert_eq!(
+            owner_commands(
+                "/dev/sdb",
+                Owner {
+                    mode: None,
+                    ..owner
+                }
+            ),
+            vec![vec!["chown", "0:6", "/dev/sdb"]]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Local};
+use clap::Args;
+
+use super::backup_run::{
+    backup::{file_name_pattern, join_backup_dir_path, DdSettings},
+    device::Device,
+    filesystem::{is_backup_of_series, Filesystem},
+    lock::DestinationLock,
+    lsblk::Lsblk,
+};
+use super::config::{BackupMethod, Config, DestinationId};
+use super::error::Failure;
+use super::manifest::Manifest;
+use super::utils::{expand_date_placeholders, format_byte_size};
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    #[clap(short, long)]
+    /// The path to the configuration file.
+    pub config_file_path: Option<String>,
+}
+
+/// A backup image or `rsync` directory present in the backup dir of a destination.
+#[derive(Debug, PartialEq)]
+struct PresentBackup {
+    /// The file or directory name.
+    name: String,
+    /// The size of an image, `None` for directories.
+    size: Option<u64>,
+    /// The time the backup was last modified, if readable.
+    modified: Option<DateTime<Local>>,
+    /// The source device of an image, read from its manifest.
+    source: Option<String>,
+}
+
+/// Reports the free space of each destination, its present backups and whether the next run fits.
+///
+/// For each destination and mirror it prints the available space and the summed size of the connected
+/// source devices, followed by their backups in its backup dir. A destination which isn't mounted is
+/// locked and mounted read-only for it and unmounted afterward, one which isn't connected gets a warning row.
+/// Only backups of connected devices can be matched, since their names contain the model and serial number.
+///
+/// # Returns
+///
+/// An `Ok` variant if the status is reported, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(status_args: &StatusArgs) -> Result<(), Failure> {
+    let config = Config::new(&status_args.config_file_path)?;
+    let lsblk = Lsblk::new(&config)?;
+
+    for backup_config in &config.backups {
+        let dd_settings = DdSettings::new(backup_config)?;
+        for destination in backup_config.all_destination_ids() {
+            let kind = backup_config.destination_kind(&destination);
+            let devices: Vec<Device> = backup_config
+                .backup_devices
+                .iter()
+                .filter(|backup_device| backup_device.is_enabled())
+                .filter(|backup_device| backup_device.is_backed_up_to(&destination, backup_config))
+                .flat_map(|backup_device| {
+                    Device::resolve_blockdevices(backup_device, &lsblk.available_devices)
+                        .into_iter()
+                        .map(|blockdevice| {
+                            Device::from_blockdevice(
+                                backup_device,
+                                blockdevice,
+                                &lsblk.available_filesystems,
+                                backup_config
+                                    .destination_path
+                                    .clone()
+                                    .unwrap_or("/.".to_string()),
+                            )
+                        })
+                })
+                .collect();
+            // the space the next run needs at most, compressed and incremental backups need less
+            let needed_space: u64 = devices.iter().map(Device::total_size).sum();
+            let filesystem = Filesystem::new(
+                &destination,
+                backup_config,
+                &lsblk.available_filesystems,
+                backup_config.mountpath.clone().or(config.mountpath.clone()),
+            )?;
+            let Some(filesystem) = filesystem else {
+                println!("{} {}: WARNING not connected", kind, destination);
+                continue;
+            };
+
+            let device_path = filesystem.device_path.clone();
+            match destination_status(filesystem, &dd_settings, &devices, &config) {
+                Ok((available_space, present_backups)) => {
+                    println!(
+                        "{}",
+                        destination_line(
+                            kind,
+                            &destination,
+                            &device_path,
+                            available_space,
+                            needed_space
+                        )
+                    );
+                    for present_backup in &present_backups {
+                        println!("{}", backup_line(present_backup));
+                    }
+                }
+                Err(e) => {
+                    println!(
+                        "{} {}: WARNING present at {}, but not readable: {}",
+                        kind, destination, device_path, e
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the available space of the destination `filesystem` and the present backups of the `devices`,
+/// locking it by its UUID and mounting it read-only for it, if it isn't mounted.
+fn destination_status(
+    mut filesystem: Filesystem,
+    dd_settings: &DdSettings,
+    devices: &[Device],
+    config: &Config,
+) -> Result<(u64, Vec<PresentBackup>), String> {
+    let mounted_temporarily = !filesystem.is_mounted();
+    // a running backup keeps its destination mounted, so only a temporary mount needs the lock
+    let _lock = match mounted_temporarily {
+        true => Some(DestinationLock::acquire(
+            filesystem.blockdevice.uuid.as_deref().unwrap_or_default(),
+        )?),
+        false => None,
+    };
+    if mounted_temporarily {
+        filesystem.allowed_mountpaths = config.allowed_mountpaths.clone();
+        filesystem.mount_options = Some(match &filesystem.mount_options {
+            Some(mount_options) => format!("{},ro", mount_options),
+            None => "ro".to_string(),
+        });
+        filesystem.mount()?;
+    }
+
+    let status = filesystem
+        .available_space()
+        .map_err(String::from)
+        .and_then(|available_space| {
+            let mountpoint = filesystem
+                .blockdevice
+                .mountpoint
+                .as_ref()
+                .unwrap_or(&filesystem.mountpath);
+            // all devices of a backup share its destination path
+            let backup_dir_path = join_backup_dir_path(
+                mountpoint,
+                &expand_date_placeholders(
+                    devices
+                        .first()
+                        .map(|device| device.destination_path.as_str())
+                        .unwrap_or("/."),
+                ),
+            );
+            let series = devices
+                .iter()
+                .map(|device| (file_name_pattern(dd_settings, device, None), device.method))
+                .collect::<Vec<(String, BackupMethod)>>();
+            Ok((
+                available_space.ok_or("Available space not readable")?,
+                present_backups(Path::new(&backup_dir_path), &series)?,
+            ))
+        });
+
+    if mounted_temporarily {
+        filesystem.unmount()?;
+    }
+    status
+}
+
+/// Returns the backups in `backup_dir` of the `series`, the file name patterns and methods of the devices,
+/// sorted by name, which starts with their date.
+///
+/// Like for the retention, a backup is an image or `rsync` directory whose name matches a pattern exactly,
+/// so partial images, checksum and manifest files and corrupt images aren't. A missing dir has none.
+fn present_backups(
+    backup_dir: &Path,
+    series: &[(String, BackupMethod)],
+) -> Result<Vec<PresentBackup>, String> {
+    if !backup_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut present_backups = fs::read_dir(backup_dir)
+        .map_err(|e| format!("Failed to read backup dir {}: {}", backup_dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            if !series.iter().any(|(file_name_pattern, method)| {
+                is_dir == (*method == BackupMethod::Rsync)
+                    && is_backup_of_series(&name, file_name_pattern)
+            }) {
+                return None;
+            }
+            let metadata = entry.metadata().ok();
+            Some(PresentBackup {
+                size: metadata
+                    .as_ref()
+                    .filter(|metadata| metadata.is_file())
+                    .map(|metadata| metadata.len()),
+                modified: metadata
+                    .and_then(|metadata| metadata.modified().ok())
+                    .map(DateTime::<Local>::from),
+                source: Manifest::read(&entry.path().to_string_lossy())
+                    .map(|manifest| manifest.source()),
+                name,
+            })
+        })
+        .collect::<Vec<PresentBackup>>();
+    present_backups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(present_backups)
+}
+
+/// Formats the space of a destination filesystem and whether the `needed_space` of the next run fits.
+fn destination_line(
+    kind: &str,
+    destination: &DestinationId,
+    device_path: &str,
+    available_space: u64,
+    needed_space: u64,
+) -> String {
+    format!(
+        "{} {}: {}, {} available, next run needs up to {}, {}",
+        kind,
+        destination,
+        device_path,
+        format_byte_size(available_space),
+        format_byte_size(needed_space),
+        if needed_space <= available_space {
+            "fits"
+        } else {
+            "WARNING may not fit"
+        }
+    )
+}
+
+/// Formats a present backup with its size, modification time and source device, if known.
+fn backup_line(present_backup: &PresentBackup) -> String {
+    format!(
+        "  {} ({}, {}{})",
+        present_backup.name,
+        present_backup
+            .size
+            .map(format_byte_size)
+            .unwrap_or("directory".to_string()),
+        present_backup
+            .modified
+            .map(|modified| modified.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or("-".to_string()),
+        present_backup
+            .source
+            .as_ref()
+            .map(|source| format!(", from {}", source))
+            .unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination_line() {
+        assert_eq!(
+            destination_line(
+                "Destination",
+                &DestinationId::Uuid("uuid1".to_string()),
+                "/dev/sdc1",
+                2048,
+                1024
+            ),
+            "Destination UUID uuid1: /dev/sdc1, 2.0 KiB available, next run needs up to 1.0 KiB, fits"
+        );
+        assert_eq!(
+            destination_line(
+                "Destination",
+                &DestinationId::Label("backup".to_string()),
+                "/dev/sdd1",
+                1024,
+                2048
+            ),
+            "Destination label backup: /dev/sdd1, 1.0 KiB available, next run needs up to 2.0 KiB, WARNING may not fit"
+        );
+    }
+
+    #[test]
+    fn test_present_backups() {
+        let backup_dir = std::env::temp_dir().join(format!(
+            "dd_backup_test_present_backups_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(backup_dir.join("2026-10-02_Disk_SRC1")).unwrap();
+        for file_name in [
+            "2026-10-01_Disk_SRC1.img",
+            "2026-10-01_Disk_SRC1.img.sha256",
+            "2026-10-01_Disk_SRC1.img.json",
+            "2026-10-03_Disk_SRC1.img.partial",
+            "2026-10-04_Disk_SRC1.img.corrupt",
+            "2026-10-05_Disk_SRC10.img",
+            "2026-10-06_Disk_SRC1",
+            "notes.txt",
+        ] {
+            fs::write(backup_dir.join(file_name), "image").unwrap();
+        }
+
+        let series = [
+            ("{date}_Disk_SRC1.img".to_string(), BackupMethod::Dd),
+            ("{date}_Disk_SRC1".to_string(), BackupMethod::Rsync),
+        ];
+        let backups = present_backups(&backup_dir, &series).unwrap();
+        assert_eq!(
+            backups
+                .iter()
+                .map(|present_backup| (present_backup.name.as_str(), present_backup.size))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2026-10-01_Disk_SRC1.img", Some(5)),
+                ("2026-10-02_Disk_SRC1", None)
+            ]
+        );
+        assert!(backup_line(&backups[0]).starts_with("  2026-10-01_Disk_SRC1.img (5 B, "));
+        assert!(present_backups(&backup_dir.join("missing"), &series)
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(&backup_dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+
+use super::config::{Config, STDIN_CONFIG_FILE_PATH};
+use super::error::Failure;
+
+/// The name of the generated service unit, the timer unit shares it.
+const UNIT_NAME: &str = "dd-back-up";
+
+#[derive(Args, Debug)]
+pub struct InstallSystemdArgs {
+    #[clap(short, long)]
+    /// The path to the configuration file the service runs with, made absolute.
+    pub config_file_path: Option<String>,
+
+    #[clap(long, default_value = "/etc/systemd/system")]
+    /// The directory the unit files are written to.
+    pub output_dir: String,
+
+    #[clap(long, default_value = "daily")]
+    /// When the timer runs the backups, as systemd calendar event, like `daily` or `Sun *-*-* 03:00`.
+    pub on_calendar: String,
+
+    #[clap(long)]
+    /// Flag to overwrite existing unit files.
+    pub force: bool,
+}
+
+/// Writes a `dd-back-up.service` running the backups with the configuration file,
+/// and a `dd-back-up.timer` starting it on the `--on-calendar` schedule.
+///
+/// The configuration file is only validated, the backup devices don't need to be present.
+/// Existing unit files are only overwritten with `--force`.
+///
+/// # Returns
+///
+/// An `Ok` variant if the units are written, or an `Err` variant with the `Failure` and its exit code.
+pub fn run(install_systemd_args: &InstallSystemdArgs) -> Result<(), Failure> {
+    if install_systemd_args.config_file_path.as_deref() == Some(STDIN_CONFIG_FILE_PATH) {
+        return Err("The service needs a config file, it can't read the config from stdin".into());
+    }
+    let config_file_path = absolute_path(&Config::resolve_config_file_path(
+        &install_systemd_args.config_file_path,
+    )?)?;
+    let config_file_path = config_file_path.to_str().ok_or(format!(
+        "Config file path {} is not valid UTF-8",
+        config_file_path.display()
+    ))?;
+    Config::new(&Some(config_file_path.to_string()))?;
+
+    let executable_path =
+        env::current_exe().map_err(|e| format!("Failed to read executable path: {}", e))?;
+    let executable_path = executable_path.to_str().ok_or(format!(
+        "Executable path {} is not valid UTF-8",
+        executable_path.display()
+    ))?;
+
+    for value in [
+        executable_path,
+        config_file_path,
+        &install_systemd_args.on_calendar,
+    ] {
+        if value.is_empty() || value.contains(['\n', '\r']) {
+            return Err(format!("Invalid value {:?} for a unit file", value).into());
+        }
+    }
+
+    let units = [
+        (
+            format!("{}.service", UNIT_NAME),
+            service_unit(executable_path, config_file_path),
+        ),
+        (
+            format!("{}.timer", UNIT_NAME),
+            timer_unit(&install_systemd_args.on_calendar),
+        ),
+    ];
+    let output_dir = Path::new(&install_systemd_args.output_dir);
+    if !install_systemd_args.force {
+        for (file_name, _) in &units {
+            let unit_path = output_dir.join(file_name);
+            if unit_path.exists() {
+                return Err(format!(
+                    "Unit file {} already exists, pass --force to overwrite it",
+                    unit_path.display()
+                )
+                .into());
+            }
+        }
+    }
+
+    fs::create_dir_all(output_dir)
+        .map_err(|e| format!("Failed to create {}: {}", output_dir.display(), e))?;
+    for (file_name, content) in &units {
+        let unit_path = output_dir.join(file_name);
+        fs::write(&unit_path, content)
+            .map_err(|e| format!("Failed to write {}: {}", unit_path.display(), e))?;
+        info!("Wrote unit file {}", unit_path.display());
+    }
+
+    println!(
+        "Enable the timer with: systemctl daemon-reload && systemctl enable --now {}.timer",
+        UNIT_NAME
+    );
+    Ok(())
+}
+
+/// Returns the service unit running the backups once with the configuration file.
+fn service_unit(executable_path: &str, config_file_path: &str) -> String {
+    format!(
+        "[Unit]
+Description=Back up block devices with dd_backup
+Documentation={}
+
+[Service]
+Type=oneshot
+ExecStart={} run --config-file-path {}
+",
+        env!("CARGO_PKG_REPOSITORY"),
+        systemd_quote(executable_path),
+        systemd_quote(config_file_path)
+    )
+}
+
+/// Returns the timer unit starting the service on the calendar event `on_calendar`.
+///
+/// Runs missed while the machine was off are caught up on the next boot.
+fn timer_unit(on_calendar: &str) -> String {
+    format!(
+        "[Unit]
+Description=Run dd_backup on a schedule
+
+[Timer]
+OnCalendar={}
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+",
+        on_calendar
+    )
+}
+
+/// Quotes `word` for a unit file command line if it contains whitespace, quotes, backslashes or `%` specifiers.
+fn systemd_quote(word: &str) -> String {
+    let escaped = word.replace('%', "%%");
+    if escaped
+        .chars()
+        .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'))
+    {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// Returns `path` joined onto the current directory if it is relative, since the service runs elsewhere.
+fn absolute_path(path: &Path) -> Result<PathBuf, String> {
+    match path.is_absolute() {
+        true => Ok(path.to_path_buf()),
+        false => env::current_dir()
+            .map(|current_dir| current_dir.join(path))
+            .map_err(|e| format!("Failed to read current directory: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_unit() {
+        assert_eq!(
+            service_unit("/usr/bin/dd_backup", "/root/my config.json"),
+            "[Unit]
+Description=Back up block devices with dd_backup
+Documentation=https://github.com/arminfro/dd_backup
+
+[Service]
+Type=oneshot
+ExecStart=/usr/bin/dd_backup run --config-file-path \"/root/my config.json\"
+"
+        );
+    }
+
+    #[test]
+    fn test_timer_unit() {
+        assert!(timer_unit("Sun *-*-* 03:00").contains("\nOnCalendar=Sun *-*-* 03:00\n"));
+    }
+
+    #[test]
+    fn test_systemd_quote() {
+        assert_eq!(systemd_quote("/etc/config.json"), "/etc/config.json");
+        assert_eq!(systemd_quote("/etc/my config"), "\"/etc/my config\"");
+        assert_eq!(systemd_quote("/etc/100%"), "/etc/100%%");
+        assert_eq!(systemd_quote("/etc/a\"b"), "\"/etc/a\\\"b\"");
+    }
+}
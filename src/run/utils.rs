@@ -1,37 +1,282 @@
-use chrono::Local;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{Local, NaiveDate};
+use relative_path::{Component, RelativePath};
+
+/// The format of dates in file names and placeholders, YYYY-MM-DD.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The format of times in file names, HH-MM-SS.
+pub const TIME_FORMAT: &str = "%H-%M-%S";
 
 /// Returns the current date in the the form YYYY-MM-DD as a String
 pub fn current_date() -> String {
     let current_date = Local::now();
-    current_date.format("%Y-%m-%d").to_string()
+    current_date.format(DATE_FORMAT).to_string()
+}
+
+/// The placeholders which can be used in a `destination_path`.
+pub const DATE_PLACEHOLDERS: [&str; 3] = ["{year}", "{month}", "{date}"];
+
+/// Replaces the date placeholders `{year}`, `{month}` and `{date}` in `template`
+/// with the current year (YYYY), month (MM) and date (YYYY-MM-DD).
+pub fn expand_date_placeholders(template: &str) -> String {
+    let now = Local::now();
+    template
+        .replace("{year}", &now.format("%Y").to_string())
+        .replace("{month}", &now.format("%m").to_string())
+        .replace("{date}", &current_date())
+}
+
+/// Replaces the placeholders of a shell `command` with their `values`, quoted with `shell_quote`,
+/// so a value with spaces or shell syntax stays a single word.
+pub fn expand_shell_placeholders(command: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(command.to_string(), |command, (placeholder, value)| {
+            command.replace(placeholder, &shell_quote(value))
+        })
+}
+
+/// Returns all placeholders in `template` which are not one of the `known_placeholders`.
+pub fn unknown_placeholders(template: &str, known_placeholders: &[&str]) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let placeholder = match rest[start..].find('}') {
+            Some(end) => &rest[start..start + end + 1],
+            None => &rest[start..],
+        };
+        if !known_placeholders.contains(&placeholder) {
+            unknown.push(placeholder.to_string());
+        }
+        rest = &rest[start + placeholder.len()..];
+    }
+    unknown
+}
+
+/// Returns whether the relative `path` leaves the directory it is joined onto,
+/// by traversing upward with `..` after normalization.
+pub fn escapes_base_dir(path: &str) -> bool {
+    RelativePath::new(path).normalize().components().next() == Some(Component::ParentDir)
+}
+
+/// Returns whether `argument` is a non-empty `mount` option list or type without whitespace
+/// or shell metacharacters, consisting only of alphanumerics and `,=._-/:+@`.
+pub fn is_plain_mount_argument(argument: &str) -> bool {
+    !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ",=._-/:+@".contains(c))
+}
+
+/// Returns whether `option` is a `dd` operand like `conv=noerror,sync` or `iflag=direct`,
+/// a lowercase key, `=` and a non-empty value without whitespace.
+pub fn is_dd_option(option: &str) -> bool {
+    match option.split_once('=') {
+        Some((key, value)) => {
+            !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_lowercase())
+                && !value.is_empty()
+                && !value.chars().any(char::is_whitespace)
+        }
+        None => false,
+    }
 }
 
-/// Converts a size string with unit suffix (e.g., "100M", "16G") to the equivalent size in bytes.
-/// Returns the converted size as a `Result<u64, String>`. If the conversion fails, an error message
-/// is returned as `String`.
+/// The prefixes of the kernel names of block devices, like `sda` or `nvme0n1`.
+const DEVICE_NAME_PREFIXES: [&str; 5] = ["sd", "hd", "vd", "nvme", "mmcblk"];
+
+/// Returns whether `serial` looks like the serial number of a device, as printed by `lsblk -o SERIAL`,
+/// instead of a device path or kernel name, or a string with whitespace.
+pub fn is_plausible_serial(serial: &str) -> bool {
+    let is_device_name = DEVICE_NAME_PREFIXES.iter().any(|prefix| {
+        serial.strip_prefix(prefix).is_some_and(|rest| {
+            rest.len() <= 6
+                && rest
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+    });
+    serial.len() >= 4
+        && !serial.starts_with("/dev/")
+        && !is_device_name
+        && serial.chars().all(|c| c.is_ascii_graphic())
+}
+
+/// Returns the path of the executable `program`.
+///
+/// A `program` containing a `/` is checked as path, otherwise it is searched in the dirs of `PATH`.
+/// Returns `None` if no such file exists.
+pub fn find_executable(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = Path::new(program);
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Quotes `word` for a POSIX shell, so it is passed on as a single argument.
+///
+/// Words of only safe characters are returned unchanged, others are wrapped in single quotes.
+pub fn shell_quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Converts a size string with unit suffix (e.g., "100M", "1.5G", "1KB") to the equivalent size in bytes.
+///
+/// Single letter suffixes and the `KiB`/`MiB`/... suffixes are binary (1024-based), as printed by `lsblk`,
+/// while `KB`/`MB`/... are decimal (1000-based). A bare number or a `B` suffix is a size in bytes.
+/// Fractional sizes are rounded to whole bytes.
+/// Returns `Ok(None)` for an unknown unit, or an error message as `String` if the number can't be parsed.
 pub fn convert_to_byte_size(size_str: &str) -> Result<Option<u64>, String> {
     let size_str = size_str.trim();
-    let unit = size_str.chars().last().ok_or("")?;
-    let size_of_unit = size_str[..size_str.len() - 1]
+    let unit_start = size_str
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size_str.len());
+    let (size_of_unit, unit) = size_str.split_at(unit_start);
+    let size_of_unit = size_of_unit
         .parse::<f64>()
         .map_err(|e| format!("Error parsing unit size: {}", e))?;
 
-    let size: Option<f64> = match unit {
-        'B' => Some(size_of_unit),
-        'K' => Some(size_of_unit * 1024.0),
-        'M' => Some(size_of_unit * 1024.0 * 1024.0),
-        'G' => Some(size_of_unit * 1024.0 * 1024.0 * 1024.0),
-        'T' => Some(size_of_unit * 1024.0 * 1024.0 * 1024.0 * 1024.0),
-        _ => None,
+    let factor = match unit {
+        "" | "B" => Some(1.0),
+        _ => {
+            let mut chars = unit.chars();
+            let exponent = chars
+                .next()
+                .and_then(|prefix| "KMGTP".find(prefix.to_ascii_uppercase()))
+                .map(|index| index as i32 + 1);
+            match (exponent, chars.as_str()) {
+                (Some(exponent), "" | "i" | "iB") => Some(1024f64.powi(exponent)),
+                (Some(exponent), "B") => Some(1000f64.powi(exponent)),
+                _ => None,
+            }
+        }
     };
 
-    if let Some(size) = size {
-        Ok(Some(size.round() as u64))
-    } else {
-        Ok(None)
+    Ok(factor.map(|factor| (size_of_unit * factor).round() as u64))
+}
+
+/// Parses a bandwidth like `50M` or `20MB`, in bytes per second, with the units of `convert_to_byte_size`.
+///
+/// # Returns
+///
+/// - `Ok(u64)`: The positive bandwidth in bytes per second.
+/// - `Err(String)`: If the bandwidth isn't a valid size string or zero.
+pub fn parse_bandwidth(bandwidth: &str) -> Result<u64, String> {
+    let bytes = convert_to_byte_size(bandwidth)
+        .map_err(|e| format!("Invalid bandwidth '{}': {}", bandwidth, e))?;
+    match bytes {
+        Some(bytes) if bytes > 0 => Ok(bytes),
+        _ => Err(format!(
+            "Invalid bandwidth '{}'. Must be a positive size per second like 50M.",
+            bandwidth
+        )),
     }
 }
 
+/// Parses a date like `2026-10-15` (YYYY-MM-DD), as given to `--after` and `--before`.
+///
+/// # Returns
+///
+/// - `Ok(NaiveDate)`: The parsed date.
+/// - `Err(String)`: If the date isn't a valid date in this format.
+pub fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .map_err(|e| format!("Invalid date '{}', expected YYYY-MM-DD: {}", date, e))
+}
+
+/// Returns whether `date` is strictly after `after` and strictly before `before`, each if given.
+pub fn is_within_dates(
+    date: NaiveDate,
+    after: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+) -> bool {
+    after.is_none_or(|after| date > after) && before.is_none_or(|before| date < before)
+}
+
+/// The units of a formatted byte size, see `format_byte_size_in`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteUnits {
+    /// Powers of 1024, like `KiB` and `MiB`, which `lsblk` and the config values use.
+    Binary,
+    /// Powers of 1000, like `kB` and `MB`, which `dd` reports its rate in.
+    Decimal,
+}
+
+/// Formats a byte size human-readable with binary units, like `"3.7 GiB"`, see `format_byte_size_in`.
+///
+/// This is the inverse of `convert_to_byte_size`, apart from the spelling of the unit.
+pub fn format_byte_size(bytes: u64) -> String {
+    format_byte_size_in(bytes, ByteUnits::Binary)
+}
+
+/// Formats a byte size human-readable with the largest fitting of the `units`, like `"3.7 GiB"` or `"4.0 GB"`.
+///
+/// Sizes below one KiB, or kB, are printed exactly in bytes, larger ones with one decimal place.
+/// A size just below a unit boundary, which would round up to `1024.0 KiB`, is printed as `1.0 MiB`.
+pub fn format_byte_size_in(bytes: u64, units: ByteUnits) -> String {
+    let (base, unit_names) = match units {
+        ByteUnits::Binary => (1024.0, ["KiB", "MiB", "GiB", "TiB"]),
+        ByteUnits::Decimal => (1000.0, ["kB", "MB", "GB", "TB"]),
+    };
+
+    if (bytes as f64) < base {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / base;
+    let mut unit = 0;
+    while (size * 10.0).round() / 10.0 >= base && unit + 1 < unit_names.len() {
+        size /= base;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, unit_names[unit])
+}
+
+/// Formats a duration compactly in whole seconds, like `"1h23m"`, `"4m05s"` or `"12s"`.
+///
+/// Durations of an hour and more are printed without seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, seconds) => format!("{}s", seconds),
+        (0, minutes, seconds) => format!("{}m{:02}s", minutes, seconds),
+        (hours, minutes, _) => format!("{}h{:02}m", hours, minutes),
+    }
+}
+
+/// Formats the rate of `bytes` transferred in `duration` with decimal units like `dd`, like `"101.0 MB/s"`.
+///
+/// A duration below a millisecond is counted as one, so the rate of an instant transfer stays finite.
+pub fn format_rate(bytes: u64, duration: Duration) -> String {
+    let millis = duration.as_millis().max(1);
+    let bytes_per_second = (bytes as u128 * 1000 / millis).min(u64::MAX as u128) as u64;
+    format!(
+        "{}/s",
+        format_byte_size_in(bytes_per_second, ByteUnits::Decimal)
+    )
+}
+
+/// Returns whether `answer` to a y/N prompt confirms it, only `y` or `yes` in any case do.
+pub fn is_confirmed(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -44,6 +289,188 @@ mod tests {
         assert_eq!(date.chars().nth(7).unwrap(), '-');
     }
 
+    #[test]
+    fn test_expand_date_placeholders() {
+        let expanded = expand_date_placeholders("./{year}/{month}/{date}");
+        let date = current_date();
+        assert_eq!(
+            expanded,
+            format!("./{}/{}/{}", &date[..4], &date[5..7], date)
+        );
+        assert_eq!(expand_date_placeholders("./backups"), "./backups");
+    }
+
+    #[test]
+    fn test_unknown_placeholders() {
+        assert!(unknown_placeholders("./{year}/{month}/{date}", &DATE_PLACEHOLDERS).is_empty());
+        assert!(unknown_placeholders("./backups", &DATE_PLACEHOLDERS).is_empty());
+        assert_eq!(
+            unknown_placeholders("./{year}/{day}/{month", &DATE_PLACEHOLDERS),
+            vec!["{day}".to_string(), "{month".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_format_byte_size() {
+        assert_eq!(format_byte_size(0), "0 B");
+        assert_eq!(format_byte_size(1023), "1023 B");
+        assert_eq!(format_byte_size(1024), "1.0 KiB");
+        assert_eq!(format_byte_size(1536), "1.5 KiB");
+        assert_eq!(format_byte_size(3_972_844_748), "3.7 GiB");
+        assert_eq!(format_byte_size(1099511627776), "1.0 TiB");
+        assert_eq!(format_byte_size(2 * 1099511627776 * 1024), "2048.0 TiB");
+
+        // just below a unit boundary
+        assert_eq!(format_byte_size(1024 * 1024 - 52), "1023.9 KiB");
+        assert_eq!(format_byte_size(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(format_byte_size(1024 * 1024), "1.0 MiB");
+        assert_eq!(format_byte_size(1024 * 1024 * 1024 - 1), "1.0 GiB");
+        assert_eq!(format_byte_size(1099511627776 - 1), "1.0 TiB");
+
+        // round trip through the lsblk notation, within the precision of one decimal place
+        for bytes in [
+            512,
+            1024,
+            1536,
+            1048576,
+            3_972_844_749 / 107374182 * 107374182,
+        ] {
+            let formatted = format_byte_size(bytes).replace("iB", "").replace(' ', "");
+            let parsed = convert_to_byte_size(&formatted).unwrap().unwrap();
+            assert!(
+                parsed.abs_diff(bytes) <= bytes / 100,
+                "{} {}",
+                bytes,
+                parsed
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_byte_size_in() {
+        assert_eq!(format_byte_size_in(0, ByteUnits::Decimal), "0 B");
+        assert_eq!(format_byte_size_in(999, ByteUnits::Decimal), "999 B");
+        assert_eq!(format_byte_size_in(1000, ByteUnits::Decimal), "1.0 kB");
+        assert_eq!(format_byte_size_in(1023, ByteUnits::Decimal), "1.0 kB");
+        assert_eq!(format_byte_size_in(999_949, ByteUnits::Decimal), "999.9 kB");
+        assert_eq!(format_byte_size_in(999_999, ByteUnits::Decimal), "1.0 MB");
+        assert_eq!(
+            format_byte_size_in(500_107_862_016, ByteUnits::Decimal),
+            "500.1 GB"
+        );
+        assert_eq!(
+            format_byte_size_in(3_972_844_748, ByteUnits::Binary),
+            format_byte_size(3_972_844_748)
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(900)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(12)), "12s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4m05s");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1h00m");
+        assert_eq!(format_duration(Duration::from_secs(4980 + 59)), "1h23m");
+        assert_eq!(format_duration(Duration::from_secs(26 * 3600)), "26h00m");
+    }
+
+    #[test]
+    fn test_format_rate() {
+        assert_eq!(
+            format_rate(500 * 1024 * 1024 * 1024, Duration::from_secs(4980)),
+            "107.8 MB/s"
+        );
+        assert_eq!(format_rate(1536, Duration::from_millis(500)), "3.1 kB/s");
+        assert_eq!(format_rate(512, Duration::ZERO), "512.0 kB/s");
+        assert_eq!(format_rate(0, Duration::from_secs(1)), "0 B/s");
+    }
+
+    #[test]
+    fn test_is_confirmed() {
+        assert!(is_confirmed("y\n"));
+        assert!(is_confirmed(" YES "));
+        assert!(!is_confirmed("\n"));
+        assert!(!is_confirmed("n"));
+        assert!(!is_confirmed("yep"));
+    }
+
+    #[test]
+    fn test_escapes_base_dir() {
+        assert!(!escapes_base_dir("./"));
+        assert!(!escapes_base_dir("backups/../other"));
+        assert!(!escapes_base_dir("/backups"));
+        assert!(escapes_base_dir("../backups"));
+        assert!(escapes_base_dir("backups/../../other"));
+        assert!(escapes_base_dir("/../backups"));
+    }
+
+    #[test]
+    fn test_is_dd_option() {
+        assert!(is_dd_option("conv=noerror,sync"));
+        assert!(is_dd_option("iflag=direct"));
+        assert!(!is_dd_option("noerror"));
+        assert!(!is_dd_option("=direct"));
+        assert!(!is_dd_option("iflag="));
+        assert!(!is_dd_option("iflag=direct oflag=direct"));
+        assert!(!is_dd_option("IFLAG=direct"));
+    }
+
+    #[test]
+    fn test_is_plain_mount_argument() {
+        assert!(is_plain_mount_argument("uid=1000,gid=1000,rw"));
+        assert!(is_plain_mount_argument("ntfs3"));
+        assert!(!is_plain_mount_argument(""));
+        assert!(!is_plain_mount_argument("rw; rm -rf /"));
+        assert!(!is_plain_mount_argument("rw,$(id)"));
+        assert!(!is_plain_mount_argument("rw noatime"));
+    }
+
+    #[test]
+    fn test_is_plausible_serial() {
+        assert!(is_plausible_serial("S3Z9NB0K123456A"));
+        assert!(is_plausible_serial("WD-WCC4N1234567"));
+        assert!(!is_plausible_serial("/dev/sda"));
+        assert!(!is_plausible_serial("sdb"));
+        assert!(!is_plausible_serial("sdb1"));
+        assert!(!is_plausible_serial("nvme0n1"));
+        assert!(!is_plausible_serial("S3Z9 NB0K"));
+        assert!(!is_plausible_serial(""));
+    }
+
+    #[test]
+    fn test_find_executable() {
+        assert!(find_executable("sh").is_some());
+        assert_eq!(find_executable("/bin/sh"), Some(PathBuf::from("/bin/sh")));
+        assert!(find_executable("dd-backup-does-not-exist").is_none());
+        assert!(find_executable("/does/not/exist").is_none());
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("if=/dev/sda"), "if=/dev/sda");
+        assert_eq!(
+            shell_quote("/mnt/2023-06-15_My Disk.img"),
+            "'/mnt/2023-06-15_My Disk.img'"
+        );
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("$(reboot)"), "'$(reboot)'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_expand_shell_placeholders() {
+        assert_eq!(
+            expand_shell_placeholders(
+                "systemctl stop db && sync {mountpath} # ${HOME} {device_path}",
+                &[
+                    ("{device_path}", "/dev/sdc1"),
+                    ("{mountpath}", "/mnt/my disk")
+                ]
+            ),
+            "systemctl stop db && sync '/mnt/my disk' # ${HOME} /dev/sdc1"
+        );
+    }
+
     #[test]
     fn test_convert_to_byte_size() {
         assert_eq!(convert_to_byte_size("0B"), Ok(Some(0)));
@@ -52,10 +479,73 @@ mod tests {
         assert_eq!(convert_to_byte_size("1M"), Ok(Some(1048576)));
         assert_eq!(convert_to_byte_size("1G"), Ok(Some(1073741824)));
         assert_eq!(convert_to_byte_size("1T"), Ok(Some(1099511627776)));
-        assert_eq!(convert_to_byte_size("100"), Ok(None));
+        assert_eq!(convert_to_byte_size("1X"), Ok(None));
+        assert_eq!(
+            convert_to_byte_size("K"),
+            Err("Error parsing unit size: cannot parse float from empty string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_convert_to_byte_size_bare_bytes() {
+        assert_eq!(convert_to_byte_size("100"), Ok(Some(100)));
+        assert_eq!(
+            convert_to_byte_size(" 4000787030016 "),
+            Ok(Some(4000787030016))
+        );
+    }
+
+    #[test]
+    fn test_convert_to_byte_size_binary_units() {
+        assert_eq!(convert_to_byte_size("1KiB"), Ok(Some(1024)));
+        assert_eq!(convert_to_byte_size("1MiB"), Ok(Some(1048576)));
+        assert_eq!(convert_to_byte_size("1Gi"), Ok(Some(1073741824)));
+        assert_eq!(convert_to_byte_size("1PiB"), Ok(Some(1125899906842624)));
+        assert_eq!(convert_to_byte_size("1MiX"), Ok(None));
+    }
+
+    #[test]
+    fn test_convert_to_byte_size_decimal_units() {
+        assert_eq!(convert_to_byte_size("1KB"), Ok(Some(1000)));
+        assert_eq!(convert_to_byte_size("1kB"), Ok(Some(1000)));
+        assert_eq!(convert_to_byte_size("1MB"), Ok(Some(1000000)));
+        assert_eq!(convert_to_byte_size("2GB"), Ok(Some(2000000000)));
+        assert_eq!(convert_to_byte_size("4TB"), Ok(Some(4000000000000)));
+    }
+
+    #[test]
+    fn test_convert_to_byte_size_fractional() {
+        assert_eq!(convert_to_byte_size("1.5G"), Ok(Some(1610612736)));
+        assert_eq!(convert_to_byte_size("931.5G"), Ok(Some(1000190509056)));
+        assert_eq!(convert_to_byte_size("1.5GB"), Ok(Some(1500000000)));
+        assert_eq!(convert_to_byte_size("0.5K"), Ok(Some(512)));
+    }
+
+    #[test]
+    fn test_parse_bandwidth() {
+        assert_eq!(parse_bandwidth("50M"), Ok(52428800));
+        assert_eq!(parse_bandwidth("20MB"), Ok(20000000));
+        assert!(parse_bandwidth("0").is_err());
+        assert!(parse_bandwidth("50X").is_err());
+        assert!(parse_bandwidth("fast").is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
         assert_eq!(
-            convert_to_byte_size("1KB"),
-            Err("Error parsing unit size: invalid float literal".to_string())
+            parse_date("2026-10-15"),
+            Ok(NaiveDate::from_ymd_opt(2026, 10, 15).unwrap())
         );
+        assert!(parse_date("2026-13-01").is_err());
+        assert!(parse_date("15.10.2026").is_err());
+    }
+
+    #[test]
+    fn test_is_within_dates() {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+        assert!(is_within_dates(date(15), None, None));
+        assert!(is_within_dates(date(15), Some(date(14)), Some(date(16))));
+        assert!(!is_within_dates(date(15), Some(date(15)), None));
+        assert!(!is_within_dates(date(15), None, Some(date(15))));
     }
 }
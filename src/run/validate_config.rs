@@ -0,0 +1,218 @@
+use std::fmt;
+
+use clap::Args;
+
+use super::config::{BackupConfig, BackupMethod, Config, FsckPolicy, OnComplete};
+use super::error::{BackupError, Failure};
+use super::utils::{find_executable, is_plausible_serial};
+
+/// The number of copies of a device above which it is warned about, since they likely fill the destination.
+const MAX_REASONABLE_COPIES: usize = 100;
+
+#[derive(Args, Debug)]
+pub struct ValidateConfigArgs {
+    #[clap(short, long)]
+    /// The path to the configuration file.
+    pub config_file_path: Option<String>,
+}
+
+/// A problem found in the configuration.
+#[derive(Debug, PartialEq)]
+enum Problem {
+    /// Makes the configuration invalid, a backup run fails with it.
+    Error(String),
+    /// Is allowed, but likely not intended, or only fails on hosts missing a program.
+    Warning(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Error(message) => write!(f, "error: {}", message),
+            Problem::Warning(message) => write!(f, "warning: {}", message),
+        }
+    }
+}
+
+/// Lints the configuration without backing up, printing all problems found.
+///
+/// Besides the errors of `validate_config`, the first one of the top-level fields and of each backup,
+/// it warns about soft constraints: devices with an unusually high number of copies, serial numbers
+/// which don't look like one, and programs the backups run which aren't found on the `PATH`.
+///
+/// # Returns
+///
+/// An `Ok` variant if the configuration has no errors, warnings are allowed, or an `Err` variant
+/// with the `Failure` and its exit code, if the configuration can't be read or has errors.
+pub fn run(validate_config_args: &ValidateConfigArgs) -> Result<(), Failure> {
+    let config = Config::read_config_file(&validate_config_args.config_file_path)?;
+
+    let problems = problems(&config);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    let errors = problems
+        .iter()
+        .filter(|problem| matches!(problem, Problem::Error(_)))
+        .count();
+    println!(
+        "{} error(s), {} warning(s)",
+        errors,
+        problems.len() - errors
+    );
+
+    match errors {
+        0 => Ok(()),
+        _ => Err(BackupError::InvalidConfig(format!("Config has {} error(s)", errors)).into()),
+    }
+}
+
+/// Returns the errors of `validate_config`, followed by the warnings about each backup.
+fn problems(config: &Config) -> Vec<Problem> {
+    let errors = Config::validation_errors(config)
+        .into_iter()
+        .map(Problem::Error);
+    let warnings = config
+        .backups
+        .iter()
+        .flat_map(backup_warnings)
+        .map(Problem::Warning);
+    errors.chain(warnings).collect()
+}
+
+/// Returns the warnings about the soft constraints of `backup`.
+fn backup_warnings(backup: &BackupConfig) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for device in &backup.backup_devices {
+        if let Some(serial) = device.serial.as_ref().filter(|s| !is_plausible_serial(s)) {
+            warnings.push(format!(
+                "Serial number '{}' in backup with {} doesn't look like one, compare it with `lsblk -d -o NAME,SERIAL`",
+                serial, backup.destination_id()
+            ));
+        }
+        if let Some(copies) = device
+            .copies
+            .filter(|&copies| copies > MAX_REASONABLE_COPIES)
+        {
+            warnings.push(format!(
+                "Device '{}' keeps {} copies, which likely fill the destination, consider `max_age_days`",
+                device.identifier(),
+                copies
+            ));
+        }
+    }
+    for program in programs(backup) {
+        if find_executable(&program).is_none() {
+            warnings.push(format!(
+                "Program '{}' run by backup with {} isn't found on the PATH",
+                program,
+                backup.destination_id()
+            ));
+        }
+    }
+    warnings
+}
+
+/// Returns the distinct programs the backups of `backup` run, besides `mount` and `umount`.
+fn programs(backup: &BackupConfig) -> Vec<String> {
+    let mut programs: Vec<String> = Vec::new();
+    let mut add = |program: &str| {
+        if !programs.iter().any(|present| present == program) {
+            programs.push(program.to_string());
+        }
+    };
+    for device in &backup.backup_devices {
+        match device.method.unwrap_or_default() {
+            BackupMethod::Dd => add("dd"),
+            BackupMethod::Rsync => add("rsync"),
+        }
+    }
+    if let Some(compression) = backup.compression {
+        add(compression.binary());
+    }
+    if backup.max_bandwidth.is_some() {
+        add("pv");
+    }
+    let skip_fsck = backup.skip_fsck == Some(true) || backup.fsck_policy == Some(FsckPolicy::Skip);
+    if !skip_fsck {
+        let fsck_command = backup.fsck_command.as_deref().unwrap_or("fsck");
+        if let Some(fsck_program) = fsck_command.split_whitespace().next() {
+            add(fsck_program);
+        }
+    }
+    match backup.on_complete {
+        Some(OnComplete::Eject) => add("eject"),
+        Some(OnComplete::PowerOff) => add("udisksctl"),
+        Some(OnComplete::None) | None => {}
+    }
+    programs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::config::BackupDevice;
+
+    #[test]
+    fn test_problems() {
+        let device = |serial: &str, copies: Option<usize>| BackupDevice {
+            serial: Some(serial.to_string()),
+            copies,
+            ..Default::default()
+        };
+        let config = Config {
+            backups: vec![
+                BackupConfig {
+                    uuid: "uuid1".to_string(),
+                    backup_devices: vec![device("S1234567", Some(0)), device("sda", Some(1000))],
+                    fsck_command: Some("dd-backup-does-not-exist -n".to_string()),
+                    ..Default::default()
+                },
+                BackupConfig {
+                    uuid: "uuid2".to_string(),
+                    destination_path: Some("../outside".to_string()),
+                    skip_fsck: Some(true),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let problems = problems(&config);
+        let errors: Vec<&Problem> = problems
+            .iter()
+            .filter(|problem| matches!(problem, Problem::Error(_)))
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(problems.len(), 5);
+        assert!(problems[2]
+            .to_string()
+            .starts_with("warning: Serial number 'sda'"));
+        assert!(problems[3]
+            .to_string()
+            .starts_with("warning: Device 'sda' keeps 1000 copies"));
+        assert_eq!(
+            problems[4],
+            Problem::Warning(
+                "Program 'dd-backup-does-not-exist' run by backup with UUID uuid1 isn't found on the PATH"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_programs() {
+        let backup = BackupConfig {
+            backup_devices: vec![BackupDevice {
+                method: Some(BackupMethod::Rsync),
+                ..Default::default()
+            }],
+            max_bandwidth: Some("10M".to_string()),
+            fsck_policy: Some(FsckPolicy::Skip),
+            on_complete: Some(OnComplete::PowerOff),
+            ..Default::default()
+        };
+        assert_eq!(programs(&backup), vec!["rsync", "pv", "udisksctl"]);
+        assert_eq!(programs(&BackupConfig::default()), vec!["fsck"]);
+    }
+}